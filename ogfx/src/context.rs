@@ -5,19 +5,48 @@ use wgpu::util::DeviceExt;
 
 pub struct RenderContext<'a> {
     projection_stack: RefCell<Vec<&'a wgpu::BindGroup>>,
+    pipeline_stack: RefCell<Vec<&'a wgpu::RenderPipeline>>,
 }
 
 impl<'a> RenderContext<'a> {
     pub fn new() -> Self {
         RenderContext {
             projection_stack: RefCell::new(Vec::new()),
+            pipeline_stack: RefCell::new(Vec::new()),
         }
     }
+
+    pub fn with_pipeline(
+        &self,
+        new: &'a wgpu::RenderPipeline,
+        pass: &mut wgpu::RenderPass<'a>,
+        f: impl Fn(&mut wgpu::RenderPass<'a>),
+    ) {
+        assert!(self.pipeline_stack.borrow().len() >= 1);
+        self.pipeline_stack.borrow_mut().push(new);
+        pass.set_pipeline(new);
+        f(pass);
+        self.pipeline_stack.borrow_mut().pop().unwrap();
+        pass.set_pipeline(self.pipeline_stack.borrow().last().unwrap());
+    }
+
+    pub fn with_initial_pipeline(
+        &self,
+        new: &'a wgpu::RenderPipeline,
+        pass: &mut wgpu::RenderPass<'a>,
+        f: impl Fn(&mut wgpu::RenderPass<'a>),
+    ) {
+        assert!(self.pipeline_stack.borrow().len() == 0);
+        self.pipeline_stack.borrow_mut().push(new);
+        pass.set_pipeline(new);
+        f(pass);
+        self.pipeline_stack.borrow_mut().pop().unwrap();
+    }
     pub fn with_projection(
         &self,
         new: &'a wgpu::BindGroup,
         pass: &mut wgpu::RenderPass<'a>,
-        f: impl Fn(&mut wgpu::RenderPass<'a>) -> (),
+        f: impl Fn(&mut wgpu::RenderPass<'a>),
     ) {
         assert!(self.projection_stack.borrow().len() >= 1);
         self.projection_stack.borrow_mut().push(new);
@@ -31,7 +60,7 @@ impl<'a> RenderContext<'a> {
         &self,
         new: &'a wgpu::BindGroup,
         pass: &mut wgpu::RenderPass<'a>,
-        f: impl Fn(&mut wgpu::RenderPass<'a>) -> (),
+        f: impl Fn(&mut wgpu::RenderPass<'a>),
     ) {
         assert!(self.projection_stack.borrow().len() == 0);
         self.projection_stack.borrow_mut().push(new);
@@ -62,6 +91,7 @@ impl Context {
     pub fn new(
         window: &impl raw_window_handle::HasRawWindowHandle,
         dimensions: cgmath::Vector2<u32>,
+        present_mode: wgpu::PresentMode,
     ) -> Self {
         let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
         let surface = unsafe { instance.create_surface(window) };
@@ -86,13 +116,7 @@ impl Context {
 
         surface.configure(
             &device,
-            &wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format: surface_format,
-                width: dimensions.x,
-                height: dimensions.y,
-                present_mode: wgpu::PresentMode::Mailbox,
-            },
+            &surface_configuration(surface_format, dimensions, present_mode),
         );
 
         let texture_bind_group_layout =
@@ -177,6 +201,14 @@ impl Context {
         }
     }
 
+    /// Reconfigures the surface to present frames with `present_mode`.
+    pub fn set_present_mode(&self, present_mode: wgpu::PresentMode) {
+        self.surface.configure(
+            &self.device,
+            &surface_configuration(self.surface_format, self.dimensions, present_mode),
+        );
+    }
+
     pub fn new_projection_transform(&self, transform: Transform) -> RawTransform {
         let mut raw = transform.as_matrix();
         raw.matrix = new_projection_matrix(self.dimensions.cast().unwrap()) * raw.matrix;
@@ -184,6 +216,20 @@ impl Context {
     }
 }
 
+fn surface_configuration(
+    format: wgpu::TextureFormat,
+    dimensions: cgmath::Vector2<u32>,
+    present_mode: wgpu::PresentMode,
+) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: dimensions.x,
+        height: dimensions.y,
+        present_mode,
+    }
+}
+
 fn new_projection_matrix(dimensions: cgmath::Vector2<f32>) -> cgmath::Matrix4<f32> {
     #[rustfmt::skip]
     pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -92,6 +92,11 @@ pub fn instance_matrix_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
                 shader_location: 6,
                 format: wgpu::VertexFormat::Float32x4,
             },
+            wgpu::VertexAttribute {
+                offset: mem::size_of::<[f32; 20]>() as wgpu::BufferAddress,
+                shader_location: 7,
+                format: wgpu::VertexFormat::Float32x4,
+            },
         ],
     }
 }
@@ -1,11 +1,25 @@
 use crate::{instance_matrix_desc, GraphicsContext, Shader, Vertex};
 
+/// Adds the colour of what's drawn to what's below it, weighted by its alpha.
+pub const ADDITIVE_BLENDING: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent::OVER,
+};
+
 pub struct Pipeline {
     pub pipeline: wgpu::RenderPipeline,
 }
 
 impl Pipeline {
     pub fn new(gfx: &GraphicsContext, shader: &Shader) -> Self {
+        Self::with_blend(gfx, shader, wgpu::BlendState::ALPHA_BLENDING)
+    }
+
+    pub fn with_blend(gfx: &GraphicsContext, shader: &Shader, blend: wgpu::BlendState) -> Self {
         let layout = gfx
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -32,7 +46,7 @@ impl Pipeline {
                     entry_point: shader.fs_name,
                     targets: &[wgpu::ColorTargetState {
                         format: gfx.surface_format,
-                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        blend: Some(blend),
                         write_mask: wgpu::ColorWrites::ALL,
                     }],
                 }),
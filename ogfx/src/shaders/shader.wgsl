@@ -6,6 +6,7 @@ struct VertexInput {
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] uv: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
 };
 
 struct ProjectionUniform {
@@ -14,12 +15,13 @@ struct ProjectionUniform {
 [[group(0), binding(0)]]
 var<uniform> proj: ProjectionUniform;
 
-struct ProjectionUniform {
+struct ViewUniform {
     matrix: mat4x4<f32>;
     source: vec4<f32>;
+    color: vec4<f32>;
 };
 [[group(1), binding(0)]]
-var<uniform> view: ProjectionUniform;
+var<uniform> view: ViewUniform;
 
 struct InstanceInput {
     [[location(2)]] model_matrix_0: vec4<f32>;
@@ -27,6 +29,7 @@ struct InstanceInput {
     [[location(4)]] model_matrix_2: vec4<f32>;
     [[location(5)]] model_matrix_3: vec4<f32>;
     [[location(6)]] source: vec4<f32>;
+    [[location(7)]] color: vec4<f32>;
 };
 
 [[stage(vertex)]]
@@ -43,6 +46,7 @@ fn vs_main(
 
     var out: VertexOutput;
     out.uv = model.uv * instance.source.zw * view.source.zw + instance.source.xy + view.source.xy;
+    out.color = instance.color * view.color;
     out.clip_position = proj.matrix * view.matrix * model_matrix * vec4<f32>(model.position, 0.0, 1.0);
     return out;
 }
@@ -54,7 +58,7 @@ var t_sampler: sampler;
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    let sample = textureSample(texture, t_sampler, in.uv);
+    let sample = textureSample(texture, t_sampler, in.uv) * in.color;
     if (sample.w <= 0.0) {
         discard;
     }
@@ -36,6 +36,18 @@ impl Sprite {
         }
     }
 
+    pub fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn set_color(&mut self, color: cgmath::Vector4<f32>) {
+        self.get_transform_mut().color = color;
+    }
+
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.get_transform_mut().color.w = alpha;
+    }
+
     pub fn get_transform_mut(&mut self) -> &mut Transform {
         self.dirty = true;
         &mut self.transform
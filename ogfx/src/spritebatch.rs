@@ -13,17 +13,25 @@ pub struct SpriteBatch {
 
     view: Transform,
     transforms: SlotMap<SpriteIdx, Transform>,
+    /// Sprites the instance buffer has room for.
+    capacity: usize,
     dirty: bool,
 }
 
+fn create_instance_buffer(gfx: &GraphicsContext, capacity: usize) -> wgpu::Buffer {
+    gfx.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: transform::RawTransform::packed_size() * capacity as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
 impl SpriteBatch {
+    /// Creates a batch with room for `capacity` sprites, it grows when more are inserted.
     pub fn new(gfx: &GraphicsContext, texture: ArcTexture, capacity: usize) -> Self {
-        let instance_buffer = gfx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: transform::RawTransform::packed_size() * capacity as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let capacity = capacity.max(1);
+        let instance_buffer = create_instance_buffer(gfx, capacity);
 
         let view = Transform::default();
         let view_buffer = Buffer::new_with_alignable_data(
@@ -48,6 +56,7 @@ impl SpriteBatch {
             view,
 
             transforms: SlotMap::with_capacity(capacity),
+            capacity,
             dirty: false,
         }
     }
@@ -57,6 +66,17 @@ impl SpriteBatch {
         &mut self.view
     }
 
+    /// Multiplies the color of every sprite in the batch.
+    pub fn set_color(&mut self, color: cgmath::Vector4<f32>) {
+        self.get_view_mut().color = color;
+    }
+
+    pub fn set_sprite_color(&mut self, key: SpriteIdx, color: cgmath::Vector4<f32>) {
+        if let Some(transform) = self.get_mut(key) {
+            transform.color = color;
+        }
+    }
+
     pub fn remove(&mut self, key: SpriteIdx) {
         self.dirty = true;
         self.transforms.remove(key);
@@ -77,6 +97,10 @@ impl SpriteBatch {
     }
 
     fn refresh_gpu_buffer(&mut self, gfx: &GraphicsContext) {
+        if self.transforms.len() > self.capacity {
+            self.capacity = self.transforms.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(gfx, self.capacity);
+        }
         gfx.queue.write_buffer(
             &self.view_buffer.buffer,
             0,
@@ -126,7 +150,8 @@ impl Renderable for SpriteBatch {
         pass.draw_indexed(
             0..self.texture.index_buffer.element_count as _,
             0,
-            0..self.transforms.len() as _,
+            // Sprites inserted since the last update only fit once it grew the buffer
+            0..self.transforms.len().min(self.capacity) as _,
         );
     }
 }
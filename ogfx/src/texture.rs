@@ -6,17 +6,29 @@ use wgpu::util::DeviceExt;
 pub struct RawTextureData {
     pub data: Vec<u8>,
     pub size: cgmath::Vector2<u32>,
+    /// Pixels per logical pixel, 2 for high resolution (`@2x`) images.
+    pub scale_factor: f32,
 }
 
 impl RawTextureData {
     pub fn from_raw_image(image_data: &[u8]) -> Self {
-        let img = image::load_from_memory(image_data).unwrap();
+        Self::try_from_raw_image(image_data).unwrap()
+    }
+
+    pub fn try_from_raw_image(image_data: &[u8]) -> image::ImageResult<Self> {
+        let img = image::load_from_memory(image_data)?;
         let dimensions = img.dimensions();
 
-        RawTextureData {
+        Ok(RawTextureData {
             data: img.into_rgba8().into_raw(),
             size: cgmath::vec2(dimensions.0, dimensions.1),
-        }
+            scale_factor: 1.0,
+        })
+    }
+
+    /// Size of the texture independent of its resolution.
+    pub fn logical_size(&self) -> cgmath::Vector2<f32> {
+        self.size.cast::<f32>().unwrap() / self.scale_factor
     }
 }
 
@@ -149,6 +161,8 @@ impl RawTexture {
 pub struct Texture {
     pub raw: RawTexture,
     pub size: cgmath::Vector2<u32>,
+    /// Pixels per logical pixel, see [`RawTextureData::scale_factor`].
+    pub scale_factor: f32,
 
     // Could be reused but would just be annoying to deal with
     pub vertex_buffer: Buffer,
@@ -190,6 +204,7 @@ impl Texture {
         Texture {
             raw,
             size,
+            scale_factor: 1.0,
             vertex_buffer,
             index_buffer,
         }
@@ -197,8 +212,24 @@ impl Texture {
 
     pub fn new(gfx: &GraphicsContext, image_data: &[u8], format: wgpu::TextureFormat) -> Self {
         let tex_data = RawTextureData::from_raw_image(image_data);
+        Texture::from_texture_data(gfx, &tex_data, format)
+    }
+
+    pub fn from_texture_data(
+        gfx: &GraphicsContext,
+        tex_data: &RawTextureData,
+        format: wgpu::TextureFormat,
+    ) -> Self {
         let raw = RawTexture::from_rgba8(gfx, &tex_data.data, tex_data.size, format);
 
-        Texture::from_raw_texture(gfx, raw, tex_data.size)
+        Texture {
+            scale_factor: tex_data.scale_factor,
+            ..Texture::from_raw_texture(gfx, raw, tex_data.size)
+        }
+    }
+
+    /// Size of the texture independent of its resolution.
+    pub fn logical_size(&self) -> cgmath::Vector2<f32> {
+        self.size.cast::<f32>().unwrap() / self.scale_factor
     }
 }
@@ -5,12 +5,13 @@ use crevice::std140::AsStd140;
 pub struct RawTransform {
     pub matrix: cgmath::Matrix4<f32>,
     pub source: cgmath::Vector4<f32>,
+    pub color: cgmath::Vector4<f32>,
 }
 
 impl RawTransform {
     pub fn packed_size() -> wgpu::BufferAddress {
         use std::mem::size_of;
-        (size_of::<cgmath::Matrix4<f32>>() + size_of::<cgmath::Vector4<f32>>()) as _
+        (size_of::<cgmath::Matrix4<f32>>() + size_of::<cgmath::Vector4<f32>>() * 2) as _
     }
 }
 
@@ -21,6 +22,8 @@ pub struct Transform {
     pub scale: cgmath::Vector2<f32>,
     pub rotation: cgmath::Rad<f32>,
     pub source: Rect<f32>,
+    /// Multiplied with the sampled texture color.
+    pub color: cgmath::Vector4<f32>,
 }
 
 impl Default for Transform {
@@ -31,6 +34,7 @@ impl Default for Transform {
             scale: cgmath::vec2(1.0, 1.0),
             rotation: cgmath::Rad(0.0),
             source: Rect::new(0.0, 0.0, 1.0, 1.0),
+            color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
         }
     }
 }
@@ -39,14 +43,18 @@ impl Transform {
     pub fn as_matrix(&self) -> RawTransform {
         let matrix = cgmath::Matrix4::from_translation(
             self.position.extend(self.layer as f32 / u16::MAX as f32),
-        ) * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, 1.0)
-            * cgmath::Matrix4::from_angle_z(self.rotation);
+        ) * cgmath::Matrix4::from_angle_z(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, 1.0);
         let source = cgmath::vec4(
             self.source.position.x,
             self.source.position.y,
             self.source.size.x,
             self.source.size.y,
         );
-        RawTransform { matrix, source }
+        RawTransform {
+            matrix,
+            source,
+            color: self.color,
+        }
     }
 }
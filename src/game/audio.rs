@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use kira::{
+    instance::InstanceSettings,
+    manager::AudioManager,
+    sound::{handle::SoundHandle, Sound, SoundSettings},
+};
+
+use super::hitsound::Sample;
+
+macro_rules! default_sample {
+    ($name:literal) => {
+        (
+            $name,
+            include_bytes!(concat!("../../resources/samples/", $name, ".wav")),
+        )
+    };
+}
+
+/// Built in samples, keyed by their file name without extension.
+const DEFAULT_SAMPLES: &[(&str, &[u8])] = &[
+    default_sample!("normal-hitnormal"),
+    default_sample!("normal-hitwhistle"),
+    default_sample!("normal-hitfinish"),
+    default_sample!("normal-hitclap"),
+    default_sample!("normal-slidertick"),
+    default_sample!("soft-hitnormal"),
+    default_sample!("soft-hitwhistle"),
+    default_sample!("soft-hitfinish"),
+    default_sample!("soft-hitclap"),
+    default_sample!("soft-slidertick"),
+    default_sample!("drum-hitnormal"),
+    default_sample!("drum-hitwhistle"),
+    default_sample!("drum-hitfinish"),
+    default_sample!("drum-hitclap"),
+    default_sample!("drum-slidertick"),
+];
+
+const SAMPLE_EXTENSIONS: [&str; 3] = ["wav", "ogg", "mp3"];
+
+/// A sample that couldn't be loaded, added to the audio manager, played or removed from it.
+#[derive(Debug)]
+pub enum SampleError {
+    Load(PathBuf, String),
+    Add(String, String),
+    Play(String, String),
+    Remove(String, String),
+}
+
+impl std::fmt::Display for SampleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SampleError::Load(path, e) => {
+                write!(f, "Failed to load sample '{}': {}", path.display(), e)
+            }
+            SampleError::Add(name, e) => write!(f, "Failed to add sample '{}': {}", name, e),
+            SampleError::Play(name, e) => write!(f, "Failed to play sample '{}': {}", name, e),
+            SampleError::Remove(name, e) => write!(f, "Failed to remove sample '{}': {}", name, e),
+        }
+    }
+}
+
+fn load_sound(path: &Path) -> Result<Sound, SampleError> {
+    Sound::from_file(path, SoundSettings::default())
+        .map_err(|e| SampleError::Load(path.to_owned(), e.to_string()))
+}
+
+/// Loads the sample `name` from `directory`, trying every supported extension.
+fn load_sample_file(directory: &Path, name: &str) -> Option<Result<Sound, SampleError>> {
+    SAMPLE_EXTENSIONS.iter().find_map(|extension| {
+        let path = directory.join(format!("{}.{}", name, extension));
+        path.is_file().then(|| load_sound(&path))
+    })
+}
+
+/// Loads every hitsound sample from `directory`, falling back to the built in ones.
+/// Samples that fail to load are replaced by the built in ones too, and returned with the errors.
+pub fn load_samples(directory: Option<&Path>) -> (Vec<(String, Sound)>, Vec<SampleError>) {
+    let mut errors = Vec::new();
+    let samples = DEFAULT_SAMPLES
+        .iter()
+        .map(|&(name, default)| {
+            let sound = match directory.and_then(|directory| load_sample_file(directory, name)) {
+                Some(Ok(sound)) => sound,
+                result => {
+                    errors.extend(result.and_then(Result::err));
+                    // The built in samples are known to decode
+                    Sound::from_wav_reader(std::io::Cursor::new(default), SoundSettings::default())
+                        .unwrap()
+                }
+            };
+            (name.to_owned(), sound)
+        })
+        .collect();
+    (samples, errors)
+}
+
+/// Loads the samples in `names` that the beatmap `directory` has, along with the errors of the
+/// ones it has but that fail to load.
+/// Names with an extension are paths to a file, the others are tried with every supported extension.
+pub fn load_beatmap_samples<'a>(
+    directory: &Path,
+    names: impl IntoIterator<Item = &'a str>,
+) -> (Vec<(String, Sound)>, Vec<SampleError>) {
+    let mut errors = Vec::new();
+    let samples = names
+        .into_iter()
+        .filter_map(|name| {
+            let sound = if Path::new(name).extension().is_some() {
+                load_sound(&directory.join(name))
+            } else {
+                load_sample_file(directory, name)?
+            };
+            match sound {
+                Ok(sound) => Some((name.to_owned(), sound)),
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            }
+        })
+        .collect();
+    (samples, errors)
+}
+
+/// Returns whether `name` is one of the samples a skin provides.
+pub fn is_skin_sample(name: &str) -> bool {
+    DEFAULT_SAMPLES.iter().any(|&(default, _)| default == name)
+}
+
+/// Adds `samples` to `sounds`, returning the errors of the ones that couldn't be added.
+fn add_sounds(
+    audio: &mut AudioManager,
+    sounds: &mut HashMap<String, SoundHandle>,
+    samples: Vec<(String, Sound)>,
+) -> Vec<SampleError> {
+    let mut errors = Vec::new();
+    for (name, sound) in samples {
+        match audio.add_sound(sound) {
+            Ok(handle) => {
+                sounds.insert(name, handle);
+            }
+            Err(e) => errors.push(SampleError::Add(name, e.to_string())),
+        }
+    }
+    errors
+}
+
+fn play_sound(handle: &mut SoundHandle, name: &str, volume: f32) -> Result<(), SampleError> {
+    handle
+        .play(InstanceSettings::new().volume(volume as f64))
+        .map(|_| ())
+        .map_err(|e| SampleError::Play(name.to_owned(), e.to_string()))
+}
+
+/// Samples added to the audio manager, ready to be played.
+#[derive(Clone)]
+pub struct SampleBank {
+    skin: HashMap<String, SoundHandle>,
+    /// Samples of the beatmap being played, keyed by their name including the sample index.
+    beatmap: HashMap<String, SoundHandle>,
+    /// Every sample is played at this volume, on top of its own.
+    volume: f32,
+}
+
+impl Default for SampleBank {
+    fn default() -> Self {
+        SampleBank {
+            skin: HashMap::new(),
+            beatmap: HashMap::new(),
+            volume: 1.0,
+        }
+    }
+}
+
+impl SampleBank {
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    /// Adds the samples of a skin, replacing the ones with the same name.
+    /// Returns the errors of the samples that couldn't be added.
+    pub fn add(
+        &mut self,
+        audio: &mut AudioManager,
+        samples: Vec<(String, Sound)>,
+    ) -> Vec<SampleError> {
+        add_sounds(audio, &mut self.skin, samples)
+    }
+
+    /// Adds samples from a beatmap directory, see [`load_beatmap_samples`].
+    /// They stay in the audio manager until [`SampleBank::remove_beatmap`] is called.
+    pub fn add_beatmap(
+        &mut self,
+        audio: &mut AudioManager,
+        samples: Vec<(String, Sound)>,
+    ) -> Vec<SampleError> {
+        add_sounds(audio, &mut self.beatmap, samples)
+    }
+
+    /// Removes the samples of the beatmap from the audio manager, once it's no longer played.
+    pub fn remove_beatmap(&mut self, audio: &mut AudioManager) -> Vec<SampleError> {
+        self.beatmap
+            .drain()
+            .filter_map(|(name, handle)| {
+                audio
+                    .remove_sound(handle.id())
+                    .err()
+                    .map(|e| SampleError::Remove(name, e.to_string()))
+            })
+            .collect()
+    }
+
+    /// Plays `sample`, preferring the beatmap's version of it.
+    pub fn play(&mut self, sample: &Sample) -> Result<(), SampleError> {
+        let handle = match sample.beatmap_name() {
+            Some(name) if self.beatmap.contains_key(&name) => self.beatmap.get_mut(&name),
+            _ => self.skin.get_mut(&sample.name),
+        };
+        match handle {
+            Some(handle) => play_sound(handle, &sample.name, sample.volume * self.volume),
+            None => Ok(()),
+        }
+    }
+
+    /// Plays the file at `path` in the beatmap directory.
+    pub fn play_beatmap_file(&mut self, path: &str, volume: f32) -> Result<(), SampleError> {
+        match self.beatmap.get_mut(path) {
+            Some(handle) => play_sound(handle, path, volume * self.volume),
+            None => Ok(()),
+        }
+    }
+}
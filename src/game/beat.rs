@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use super::chart::TimingPoint;
+
+/// Where a song time falls in the beats of a chart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beat {
+    /// Beats since the first timing point, negative before it.
+    pub index: i64,
+    /// How far into the beat, from 0 on the beat to 1 right before the next.
+    pub phase: f32,
+    /// Beat within the measure, 0 on the downbeat.
+    pub beat_in_measure: u32,
+    /// Beats per measure.
+    pub meter: u32,
+}
+
+impl Beat {
+    /// How far into the measure, from 0 on the downbeat to 1 right before the next.
+    pub fn measure_phase(&self) -> f32 {
+        (self.beat_in_measure as f32 + self.phase) / self.meter as f32
+    }
+
+    pub fn is_downbeat(&self) -> bool {
+        self.beat_in_measure == 0
+    }
+
+    /// Strength of a pulse on every beat, 1 on the beat and fading out until the next.
+    pub fn pulse(&self) -> f32 {
+        (1.0 - self.phase).powi(2)
+    }
+
+    /// Strength of a pulse on every downbeat, 1 on the downbeat and fading out over the measure.
+    pub fn measure_pulse(&self) -> f32 {
+        (1.0 - self.measure_phase()).powi(2)
+    }
+}
+
+/// Follows the beats set by the uninherited timing points of a chart.
+///
+/// Every timing point starts a new measure, as in osu!. Before the first timing point its beats
+/// continue backwards.
+///
+/// Stored as its timing points.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<TimingPoint>", into = "Vec<TimingPoint>")]
+pub struct BeatTracker {
+    timing_points: Vec<TimingPoint>,
+    /// Beat index each timing point starts at.
+    first_beats: Vec<i64>,
+}
+
+impl BeatTracker {
+    /// Creates a tracker from uninherited `timing_points` sorted by time.
+    pub fn new(timing_points: Vec<TimingPoint>) -> Self {
+        let mut first_beats = Vec::with_capacity(timing_points.len());
+        let mut beat = 0;
+        for (index, point) in timing_points.iter().enumerate() {
+            first_beats.push(beat);
+            if let Some(next) = timing_points.get(index + 1) {
+                // A beat cut short by the next timing point still counts
+                beat += ((next.time - point.time) / point.beat_length)
+                    .ceil()
+                    .max(0.0) as i64;
+            }
+        }
+        BeatTracker {
+            timing_points,
+            first_beats,
+        }
+    }
+
+    pub fn timing_points(&self) -> &[TimingPoint] {
+        &self.timing_points
+    }
+
+    /// Returns the beat at song `time`, `None` if there are no timing points.
+    pub fn beat_at(&self, time: f32) -> Option<Beat> {
+        let index = self
+            .timing_points
+            .iter()
+            .rposition(|point| point.time <= time)
+            .unwrap_or(0);
+        let point = self.timing_points.get(index)?;
+        let beats = (time - point.time) / point.beat_length;
+        let beat = beats.floor() as i64;
+        let meter = point.meter as i64;
+        Some(Beat {
+            index: self.first_beats[index] + beat,
+            phase: beats - beats.floor(),
+            beat_in_measure: beat.rem_euclid(meter) as u32,
+            meter: point.meter,
+        })
+    }
+}
+
+impl From<Vec<TimingPoint>> for BeatTracker {
+    fn from(timing_points: Vec<TimingPoint>) -> Self {
+        BeatTracker::new(timing_points)
+    }
+}
+
+impl From<BeatTracker> for Vec<TimingPoint> {
+    fn from(tracker: BeatTracker) -> Self {
+        tracker.timing_points
+    }
+}
+
+#[test]
+fn test_beat_tracker() {
+    assert_eq!(BeatTracker::default().beat_at(1.0), None);
+
+    let tracker = BeatTracker::new(vec![
+        TimingPoint {
+            time: 1.0,
+            beat_length: 0.5,
+            meter: 4,
+        },
+        // Cuts the third measure short after a beat and a half
+        TimingPoint {
+            time: 5.75,
+            beat_length: 0.25,
+            meter: 3,
+        },
+    ]);
+
+    let beat = tracker.beat_at(2.25).unwrap();
+    assert_eq!((beat.index, beat.beat_in_measure), (2, 2));
+    assert!((beat.phase - 0.5).abs() < 1e-6);
+    assert!((beat.measure_phase() - 0.625).abs() < 1e-6);
+
+    // Before the first timing point
+    let beat = tracker.beat_at(0.5).unwrap();
+    assert_eq!((beat.index, beat.beat_in_measure), (-1, 3));
+    assert_eq!(beat.pulse(), 1.0);
+    assert!((beat.measure_pulse() - 0.0625).abs() < 1e-6);
+
+    let beat = tracker.beat_at(5.5).unwrap();
+    assert_eq!((beat.index, beat.beat_in_measure), (9, 1));
+
+    // The second timing point restarts the measures
+    let beat = tracker.beat_at(5.75).unwrap();
+    assert_eq!((beat.index, beat.beat_in_measure), (10, 0));
+    assert!(beat.is_downbeat());
+    assert_eq!(beat.measure_pulse(), 1.0);
+    let beat = tracker.beat_at(6.5).unwrap();
+    assert_eq!((beat.index, beat.beat_in_measure), (13, 0));
+    assert_eq!(beat.meter, 3);
+}
@@ -1,32 +1,171 @@
-use crate::math;
+use std::collections::BTreeSet;
 
-#[derive(Clone, Copy)]
+use cgmath::InnerSpace;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{
+        beat::BeatTracker,
+        hitsound::{self, HitSamples, Hitsounds, ObjectSamples, StoryboardSample},
+        judgement::HitWindows,
+    },
+    math,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum CurveType {
     Perfect,
     Bezier,
     Linear,
 }
 
+impl From<CurveType> for osu_types::CurveType {
+    fn from(curve_type: CurveType) -> Self {
+        match curve_type {
+            CurveType::Perfect => osu_types::CurveType::Perfect,
+            CurveType::Bezier => osu_types::CurveType::Bezier,
+            CurveType::Linear => osu_types::CurveType::Linear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Slider {
     pub control_points: Vec<cgmath::Vector2<f32>>,
     pub curve_type: CurveType,
     pub repeat: u32,
     pub velocity: f32,
     pub length: f32,
+    /// Seconds between two slider ticks.
+    pub tick_interval: f32,
+    /// Samples of the head, every repeat and the tail.
+    pub edge_samples: Vec<HitSamples>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SliderEvent {
+    Tick,
+    /// Repeat or tail, indexing into [`Slider::edge_samples`].
+    Edge(usize),
+}
+
+/// Ticks closer than this to the end of a span are skipped.
+const TICK_END_LENIENCY: f32 = 0.01;
+
+impl Slider {
+    /// Returns the position where the slider path ends, starting from `start`.
+    pub fn path_end(&self, start: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+        let spline = osu_utils::Spline::from_control(
+            self.curve_type.into(),
+            std::iter::once(start)
+                .chain(self.control_points.iter().copied())
+                .map(|p| osu_types::osu_point(p.x as _, p.y as _))
+                .collect::<Vec<_>>()
+                .as_slice(),
+            Some(self.length * OSU_PIXEL_SCALE),
+        );
+        let end = spline.spline_points.last().unwrap();
+        cgmath::vec2(end.x, end.y)
+    }
+
+    /// Returns the times of the ticks, repeats and tail of the slider in order.
+    pub fn events(&self, start_time: f32) -> Vec<(f32, SliderEvent)> {
+        let span_duration = self.length / self.velocity;
+        let mut events = Vec::new();
+        for span in 0..=self.repeat {
+            let span_start = start_time + span_duration * span as f32;
+            let mut ticks = Vec::new();
+            let mut offset = self.tick_interval;
+            while self.tick_interval > 0.0 && offset < span_duration - TICK_END_LENIENCY {
+                ticks.push(offset);
+                offset += self.tick_interval;
+            }
+            // Going back, the ticks are passed in the opposite order
+            if span % 2 == 1 {
+                ticks = ticks.into_iter().rev().map(|t| span_duration - t).collect();
+            }
+            events.extend(
+                ticks
+                    .into_iter()
+                    .map(|t| (span_start + t, SliderEvent::Tick)),
+            );
+            events.push((
+                span_start + span_duration,
+                SliderEvent::Edge(span as usize + 1),
+            ));
+        }
+        events
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum HitObjectData {
     Circle,
     Slider(Slider),
 }
 
+/// Look of a hit marker, from the Ouendan games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkerStyle {
+    /// The skin's hit circles, what objects converted from .osu files get.
+    Standard,
+    /// Markers of the Ouendan squad.
+    Ouendan,
+    /// Markers of the cheerleaders from Ouendan 2.
+    Cheerleaders,
+}
+
+impl Default for MarkerStyle {
+    fn default() -> Self {
+        MarkerStyle::Standard
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HitObject {
     pub position: cgmath::Vector2<f32>,
     pub time: f32,
     pub data: HitObjectData,
+    #[serde(default)]
+    pub marker: MarkerStyle,
+    /// Samples of a circle or of the body of a slider.
+    pub samples: HitSamples,
+    /// How many objects this one is stacked on top of, already applied to `position`.
+    /// Negative when stacked below the end of a slider.
+    pub stack_height: i32,
+
+    pub new_combo: bool,
+    /// How many extra combo colours to skip when starting a new combo.
+    pub combo_colour_skip: u32,
+    /// Number shown on the object, starting at 1 for each combo.
+    pub combo_number: u32,
+    /// Index into the combo colours, needs to be wrapped by the amount of colours.
+    pub combo_colour: usize,
 }
 
 impl HitObject {
+    /// Returns the position where the player finishes the object.
+    pub fn end_position(&self) -> cgmath::Vector2<f32> {
+        match &self.data {
+            HitObjectData::Circle => self.position,
+            HitObjectData::Slider(s) => {
+                if s.repeat % 2 == 1 {
+                    self.position
+                } else {
+                    s.path_end(self.position)
+                }
+            }
+        }
+    }
+
+    /// Returns the samples played when the object is hit.
+    pub fn head_samples(&self) -> &HitSamples {
+        match &self.data {
+            HitObjectData::Slider(s) => s.edge_samples.first().unwrap_or(&self.samples),
+            _ => &self.samples,
+        }
+    }
+
     pub fn end_time(&self) -> f32 {
         match &self.data {
             HitObjectData::Circle => self.time,
@@ -37,18 +176,313 @@ impl HitObject {
     }
 }
 
+/// Part of the chart without objects, where health doesn't drain.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BreakPeriod {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl BreakPeriod {
+    pub fn contains(&self, time: f32) -> bool {
+        time >= self.start && time < self.end
+    }
+}
+
+/// Parses the break periods in the `[Events]` section of the .osu file `content`, sorted by time.
+pub fn parse_breaks(content: &str) -> Vec<BreakPeriod> {
+    let mut breaks = osu_section_lines(content, "Events")
+        .filter_map(|line| {
+            let mut fields = line.split(',').map(|field| field.trim());
+            if !matches!(fields.next()?, "2" | "Break") {
+                return None;
+            }
+            let start = fields.next()?.parse::<f32>().ok()?;
+            let end = fields.next()?.parse::<f32>().ok()?;
+            Some(BreakPeriod {
+                start: start / 1000.0,
+                end: end / 1000.0,
+            })
+        })
+        .collect::<Vec<_>>();
+    breaks.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    breaks
+}
+
+/// Reads the path of the background image from the `[Events]` of the .osu file `content`.
+///
+/// Videos aren't played. Beatmaps with one show their background image instead, like osu! does
+/// with video turned off, or the playfield if they only have the video.
+pub fn parse_background(content: &str) -> Option<String> {
+    osu_section_lines(content, "Events").find_map(|line| {
+        // 0,0,"filename",xOffset,yOffset
+        let mut fields = line.split(',').map(|field| field.trim());
+        match fields.next()? {
+            "0" | "Background" => Some(hitsound::normalize_path(fields.nth(1)?)),
+            // Video events included, the first frame of a video isn't decoded
+            _ => None,
+        }
+    })
+}
+
+/// Uninherited timing point, setting the tempo from `time` on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimingPoint {
+    pub time: f32,
+    /// Seconds per beat.
+    pub beat_length: f32,
+    /// Beats per measure.
+    pub meter: u32,
+}
+
+/// Part of the chart in kiai time, where the visuals pulse along with the beat.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KiaiSection {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl KiaiSection {
+    pub fn contains(&self, time: f32) -> bool {
+        time >= self.start && time < self.end
+    }
+}
+
+/// Parses the uninherited timing points in the `[TimingPoints]` of the .osu file `content`,
+/// sorted by time.
+pub fn parse_timing_points(content: &str) -> Vec<TimingPoint> {
+    let mut points = osu_section_lines(content, "TimingPoints")
+        .filter_map(|line| {
+            // time,beatLength,meter,sampleSet,sampleIndex,volume,uninherited,effects
+            let fields = line.split(',').map(|v| v.trim()).collect::<Vec<_>>();
+            let time = fields.get(0)?.parse::<f32>().ok()?;
+            let beat_length = fields.get(1)?.parse::<f32>().ok()?;
+            // Old beatmaps leave out the uninherited flag, telling inherited points apart by
+            // their negative beat length
+            let uninherited = fields.get(6).map_or(beat_length > 0.0, |v| *v != "0");
+            (uninherited && beat_length > 0.0).then(|| TimingPoint {
+                time: time / 1000.0,
+                beat_length: beat_length / 1000.0,
+                meter: fields
+                    .get(2)
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .filter(|&meter| meter > 0)
+                    .unwrap_or(4),
+            })
+        })
+        .collect::<Vec<_>>();
+    points.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+    points
+}
+
+/// Parses the kiai sections from the effects of the timing points in the .osu file `content`.
+/// The last section lasts until the end of the song if no timing point ends it.
+pub fn parse_kiai_sections(content: &str) -> Vec<KiaiSection> {
+    let mut toggles = osu_section_lines(content, "TimingPoints")
+        .filter_map(|line| {
+            let fields = line.split(',').map(|v| v.trim()).collect::<Vec<_>>();
+            let time = fields.get(0)?.parse::<f32>().ok()?;
+            let effects = fields
+                .get(7)
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(0);
+            Some((time / 1000.0, effects & 1 != 0))
+        })
+        .collect::<Vec<_>>();
+    toggles.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let mut sections = Vec::new();
+    let mut start = None;
+    for (time, kiai) in toggles {
+        match (start, kiai) {
+            (None, true) => start = Some(time),
+            (Some(section_start), false) => {
+                if time > section_start {
+                    sections.push(KiaiSection {
+                        start: section_start,
+                        end: time,
+                    });
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = start {
+        sections.push(KiaiSection {
+            start,
+            end: f32::INFINITY,
+        });
+    }
+    sections
+}
+
+/// When a cutscene panel is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PanelCondition {
+    Always,
+    /// Only if the player is passing when the panel comes up.
+    Passing,
+    /// Only if the player is failing when the panel comes up.
+    Failing,
+}
+
+/// Comic panel telling the story of the chart, as in the Ouendan games.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CutscenePanel {
+    pub start: f32,
+    pub end: f32,
+    /// Path of the image relative to the beatmap directory.
+    pub image: String,
+    /// Centre of the panel in playfield units.
+    pub position: cgmath::Vector2<f32>,
+    /// Size of the panel in playfield units.
+    pub size: cgmath::Vector2<f32>,
+    pub condition: PanelCondition,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChartData {
     pub objects: Vec<HitObject>,
+    /// Beats of the uninherited timing points.
+    pub beats: BeatTracker,
+    /// Sorted by time.
+    pub kiai_sections: Vec<KiaiSection>,
+    /// Sorted by time.
+    pub breaks: Vec<BreakPeriod>,
+    /// Path of the background image relative to the beatmap directory.
+    pub background: Option<String>,
+    /// Combo colours defined by the chart, empty if it uses the ones from the skin.
+    pub combo_colours: Vec<cgmath::Vector3<f32>>,
+    pub hitsounds: Hitsounds,
+    /// Samples triggered by the storyboard, sorted by time.
+    pub storyboard_samples: Vec<StoryboardSample>,
+    /// Sorted by time. Charts converted from .osu files have none.
+    #[serde(default)]
+    pub cutscenes: Vec<CutscenePanel>,
+}
+
+impl ChartData {
+    /// Returns the colour of `obj`, using `fallback` if the chart doesn't define any colours.
+    pub fn combo_colour(
+        &self,
+        obj: &HitObject,
+        fallback: &[cgmath::Vector3<f32>],
+    ) -> cgmath::Vector3<f32> {
+        let colours = if self.combo_colours.is_empty() {
+            fallback
+        } else {
+            &self.combo_colours[..]
+        };
+        colours[obj.combo_colour % colours.len()]
+    }
+
+    /// Returns the break `time` is in, if any.
+    pub fn break_at(&self, time: f32) -> Option<&BreakPeriod> {
+        self.breaks.iter().find(|period| period.contains(time))
+    }
+
+    pub fn is_kiai(&self, time: f32) -> bool {
+        self.kiai_sections
+            .iter()
+            .any(|section| section.contains(time))
+    }
+
+    /// Strength of the kiai pulse at `time`, 1 on every beat of a kiai section and fading out
+    /// until the next, always 0 outside of kiai time.
+    pub fn kiai_pulse(&self, time: f32) -> f32 {
+        match self.beats.beat_at(time) {
+            Some(beat) if self.is_kiai(time) => beat.pulse(),
+            _ => 0.0,
+        }
+    }
+
+    /// Whether health drains at `time`, between the first and last object and outside of breaks.
+    pub fn drains_health(&self, time: f32) -> bool {
+        match (self.objects.first(), self.objects.last()) {
+            (Some(first), Some(last)) => {
+                time >= first.time && time <= last.end_time() && self.break_at(time).is_none()
+            }
+            _ => false,
+        }
+    }
+
+    /// Song time skipping from `time` seeks to, `None` if there is no intro or break long enough
+    /// to skip. Play resumes with the same idle time before the next object as at the start.
+    pub fn skip_target(&self, time: f32, approach_seconds: f32) -> Option<f32> {
+        let next = self.objects.iter().position(|obj| obj.time > time)?;
+        if next != 0 && self.break_at(time).is_none() {
+            return None;
+        }
+        let target = self.objects[next].time - approach_seconds - MIN_LEAD_IN_SECONDS;
+        (target - time >= MIN_SKIP_SECONDS).then(|| target)
+    }
+
+    /// Returns the names of every sample the chart may load from the beatmap directory.
+    pub fn beatmap_sample_names(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        for obj in &self.objects {
+            let mut samples = self.hitsounds.resolve(obj.head_samples(), obj.time);
+            if let HitObjectData::Slider(slider) = &obj.data {
+                for (time, event) in slider.events(obj.time) {
+                    match event {
+                        SliderEvent::Tick => {
+                            samples.push(self.hitsounds.resolve_tick(&obj.samples, time))
+                        }
+                        SliderEvent::Edge(edge) => {
+                            samples.extend(self.hitsounds.resolve(
+                                slider.edge_samples.get(edge).unwrap_or(&obj.samples),
+                                time,
+                            ))
+                        }
+                    }
+                }
+            }
+            names.extend(samples.iter().filter_map(|sample| sample.beatmap_name()));
+        }
+        names.extend(
+            self.storyboard_samples
+                .iter()
+                .map(|sample| sample.path.clone()),
+        );
+        names
+    }
 }
 
+pub const DEFAULT_COMBO_COLOURS: [cgmath::Vector3<f32>; 4] = [
+    cgmath::Vector3::new(255.0 / 255.0, 192.0 / 255.0, 0.0 / 255.0),
+    cgmath::Vector3::new(0.0 / 255.0, 202.0 / 255.0, 0.0 / 255.0),
+    cgmath::Vector3::new(18.0 / 255.0, 124.0 / 255.0, 255.0 / 255.0),
+    cgmath::Vector3::new(242.0 / 255.0, 24.0 / 255.0, 57.0 / 255.0),
+];
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Modifiers {
     pub approach_rate: f32,
+    pub overall_difficulty: f32,
+    pub circle_size: f32,
+    /// HP drain rate, how fast health drains and how much misses cost.
+    pub drain_rate: f32,
 }
 
 impl Modifiers {
+    /// Radius of a hit circle in playfield units.
+    pub fn circle_radius(&self) -> f32 {
+        osu_cs_to_radius(self.circle_size) * OSU_PIXEL_SCALE
+    }
+
     pub fn approach_seconds(&self) -> f32 {
         1.0 - self.approach_rate * 0.1
     }
+
+    /// How long it takes for an object to fade in after it appears.
+    pub fn fade_in_seconds(&self) -> f32 {
+        0.4 * (self.approach_seconds() / 0.45).min(1.0)
+    }
+
+    pub fn hit_windows(&self) -> HitWindows {
+        HitWindows::from_od(self.overall_difficulty)
+    }
 }
 
 impl std::fmt::Debug for Modifiers {
@@ -62,16 +496,75 @@ impl std::fmt::Debug for Modifiers {
                     self.approach_seconds() * 1000.0
                 ),
             )
+            .field("overall_difficulty", &self.overall_difficulty)
+            .field("circle_size", &self.circle_size)
+            .field("drain_rate", &self.drain_rate)
             .finish()
     }
 }
 
-#[derive(Debug)]
+/// Idle time before the first object starts approaching.
+const MIN_LEAD_IN_SECONDS: f32 = 1.0;
+/// Shortest skip worth offering.
+const MIN_SKIP_SECONDS: f32 = 1.0;
+
+fn default_stack_leniency() -> f32 {
+    0.7
+}
+
+fn default_slider_multiplier() -> f32 {
+    1.4
+}
+
+fn default_slider_tick_rate() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChartInfo {
     pub title: String,
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub creator: String,
+    /// Name of the difficulty.
+    #[serde(default)]
+    pub version: String,
+    /// Path of the song relative to the beatmap directory.
+    #[serde(default)]
+    pub audio_file: String,
     pub modifiers: Modifiers,
+    /// Settings the objects were converted with, already applied to them.
+    /// Only needed to write the chart back to a .osu file.
+    #[serde(default = "default_stack_leniency")]
+    pub stack_leniency: f32,
+    #[serde(default = "default_slider_multiplier")]
+    pub slider_multiplier: f32,
+    #[serde(default = "default_slider_tick_rate")]
+    pub slider_tick_rate: f32,
+    /// Silence requested by the beatmap before the song starts, in seconds.
+    pub audio_lead_in: f32,
+    /// Song time song select and the main menu play the song from, `None` if the beatmap has none.
+    pub preview_time: Option<f32>,
+}
+
+impl ChartInfo {
+    /// Song time play starts at, negative to give time before the song or the first object.
+    pub fn start_time(&self, first_object_time: f32) -> f32 {
+        (-self.audio_lead_in)
+            .min(first_object_time - self.modifiers.approach_seconds() - MIN_LEAD_IN_SECONDS)
+            .min(0.0)
+    }
+
+    /// Song time to preview the song from, 40% into it if the beatmap doesn't have a preview point.
+    pub fn preview_start(&self, song_length: f32) -> f32 {
+        self.preview_time.unwrap_or(song_length * 0.4)
+    }
 }
 
+/// Size of one osu!pixel in ouendan playfield units.
+pub const OSU_PIXEL_SCALE: f32 = 640.0 / 512.0;
+
 fn ar_from_secs(sec: f32) -> f32 {
     (1.0 - sec) * 10.0
 }
@@ -88,19 +581,208 @@ fn osu_ar_to_secs(ar: f32) -> f32 {
     difficulty_range(ar, 1.800, 1.200, 0.450)
 }
 
+/// Inverse of [`osu_ar_to_secs`], the osu! approach rate objects approach for `secs` with.
+pub fn secs_to_osu_ar(secs: f32) -> f32 {
+    if secs < 1.2 {
+        5.0 + (1.2 - secs) / (1.2 - 0.45) * 5.0
+    } else {
+        5.0 - (secs - 1.2) / (1.8 - 1.2) * 5.0
+    }
+}
+
+/// Radius of a hit circle in osu!pixels for the given circle size.
+pub fn osu_cs_to_radius(cs: f32) -> f32 {
+    32.0 * (1.0 - 0.7 * (cs - 5.0) / 5.0)
+}
+
 #[test]
 fn test_difficulty_maps() {
     assert!((osu_ar_to_secs(9.0) - 0.600).abs() <= f32::EPSILON);
     assert!((osu_ar_to_secs(6.0) - 1.050).abs() <= f32::EPSILON);
     assert!((osu_ar_to_secs(3.0) - 1.440).abs() <= f32::EPSILON);
+    for ar in [0.0, 3.0, 5.0, 9.2, 10.0] {
+        assert!((secs_to_osu_ar(osu_ar_to_secs(ar)) - ar).abs() < 1e-4);
+    }
+}
+
+/// Objects closer than this (in osu!pixels) are considered to be on the same spot.
+const STACK_DISTANCE: f32 = 3.0;
+
+/// Stacks overlapping objects the same way osu! does, including circles placed on slider ends,
+/// then moves every stacked object towards the top left.
+fn apply_stacking(
+    objects: &mut [HitObject],
+    approach_seconds: f32,
+    stack_leniency: f32,
+    radius: f32,
+) {
+    let stack_threshold = approach_seconds * stack_leniency;
+    let stack_distance = STACK_DISTANCE * OSU_PIXEL_SCALE;
+    let mut stack_heights = vec![0i32; objects.len()];
+    let end_positions = objects
+        .iter()
+        .map(|obj| obj.end_position())
+        .collect::<Vec<_>>();
+
+    for i in (1..objects.len()).rev() {
+        if stack_heights[i] != 0 {
+            continue;
+        }
+        let mut current = i;
+        match objects[i].data {
+            HitObjectData::Circle => {
+                for n in (0..i).rev() {
+                    if objects[current].time - objects[n].end_time() > stack_threshold {
+                        break;
+                    }
+                    if let HitObjectData::Slider(_) = objects[n].data {
+                        if (end_positions[n] - objects[current].position).magnitude()
+                            < stack_distance
+                        {
+                            // Move everything stacked on the slider end away from the slider head
+                            let offset = stack_heights[current] - stack_heights[n] + 1;
+                            for j in n + 1..=i {
+                                if (end_positions[n] - objects[j].position).magnitude()
+                                    < stack_distance
+                                {
+                                    stack_heights[j] -= offset;
+                                }
+                            }
+                            break;
+                        }
+                    }
+                    if (objects[n].position - objects[current].position).magnitude()
+                        < stack_distance
+                    {
+                        stack_heights[n] = stack_heights[current] + 1;
+                        current = n;
+                    }
+                }
+            }
+            HitObjectData::Slider(_) => {
+                for n in (0..i).rev() {
+                    if objects[current].time - objects[n].time > stack_threshold {
+                        break;
+                    }
+                    if (end_positions[n] - objects[current].position).magnitude() < stack_distance {
+                        stack_heights[n] = stack_heights[current] + 1;
+                        current = n;
+                    }
+                }
+            }
+        }
+    }
+
+    let stack_offset = radius / 10.0 * OSU_PIXEL_SCALE;
+    for (obj, &stack_height) in objects.iter_mut().zip(stack_heights.iter()) {
+        let offset = cgmath::vec2(1.0, 1.0) * stack_height as f32 * -stack_offset;
+        obj.position += offset;
+        obj.stack_height = stack_height;
+        if let HitObjectData::Slider(s) = &mut obj.data {
+            for point in &mut s.control_points {
+                *point += offset;
+            }
+        }
+    }
+}
+
+/// Numbers every object within its combo and picks the combo colour index for it.
+fn assign_combos(objects: &mut [HitObject]) {
+    let mut combo_number = 0;
+    let mut combo_colour = 0;
+    for (idx, obj) in objects.iter_mut().enumerate() {
+        // The first object always starts a combo, but uses the first colour
+        if idx == 0 {
+            combo_number = 0;
+        } else if obj.new_combo {
+            combo_number = 0;
+            combo_colour += 1 + obj.combo_colour_skip as usize;
+        }
+        combo_number += 1;
+        obj.combo_number = combo_number;
+        obj.combo_colour = combo_colour;
+    }
+}
+
+/// Iterates the non empty lines of `section` in the raw `.osu` file `content`, skipping comments.
+pub fn osu_section_lines<'a>(content: &'a str, section: &'a str) -> impl Iterator<Item = &'a str> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .skip_while(move |line| {
+            !(line.starts_with('[') && line.ends_with(']') && &line[1..line.len() - 1] == section)
+        })
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+}
+
+/// Value of `key` in a `key: value` section of the .osu file `content`.
+fn osu_value<'a>(content: &'a str, section: &'a str, key: &str) -> Option<&'a str> {
+    osu_section_lines(content, section)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, value)| value.trim())
+}
+
+#[derive(Debug)]
+pub enum OsuError {
+    /// The beatmap parser couldn't read the file.
+    Parse,
+    /// The beatmap has a kind of object the game can't play, like spinners.
+    Unsupported {
+        object: &'static str,
+        /// Time of the object in milliseconds.
+        time: i32,
+    },
+}
+
+impl std::fmt::Display for OsuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OsuError::Parse => write!(f, "invalid .osu file"),
+            OsuError::Unsupported { object, time } => {
+                write!(f, "unsupported {} at {}ms", object, time)
+            }
+        }
+    }
 }
 
-pub fn load_osu_beatmap(beatmap: &osu_parser::Beatmap) -> (ChartInfo, ChartData) {
+/// Loads the chart of the `.osu` file `content`.
+pub fn load_osu_beatmap(content: &str) -> Result<(ChartInfo, ChartData), OsuError> {
+    let beatmap = osu_parser::load_content(content, osu_parser::BeatmapParseOptions::default())
+        .map_err(|_| OsuError::Parse)?;
+    let mut object_samples = ObjectSamples::parse_all(content);
+    let general_ms = |key| {
+        osu_value(content, "General", key)
+            .and_then(|value| value.parse::<i32>().ok())
+            .map(|ms| ms as f32 / 1000.0)
+    };
+    let slider_tick_rate = osu_value(content, "Difficulty", "SliderTickRate")
+        .and_then(|value| value.parse::<f32>().ok())
+        .unwrap_or(1.0);
+    let metadata = |key| osu_value(content, "Metadata", key).unwrap_or("").to_owned();
     let info = ChartInfo {
         title: beatmap.info.metadata.title.clone(),
+        artist: metadata("Artist"),
+        creator: metadata("Creator"),
+        version: metadata("Version"),
+        audio_file: osu_value(content, "General", "AudioFilename")
+            .map(hitsound::normalize_path)
+            .unwrap_or_default(),
         modifiers: Modifiers {
             approach_rate: ar_from_secs(osu_ar_to_secs(beatmap.info.difficulty.ar)),
+            overall_difficulty: beatmap.info.difficulty.od,
+            circle_size: beatmap.info.difficulty.cs,
+            drain_rate: osu_value(content, "Difficulty", "HPDrainRate")
+                .and_then(|value| value.parse::<f32>().ok())
+                .unwrap_or(5.0),
         },
+        audio_lead_in: general_ms("AudioLeadIn").unwrap_or(0.0).max(0.0),
+        preview_time: general_ms("PreviewTime").filter(|&time| time >= 0.0),
+        stack_leniency: beatmap.info.general.stack_leniency,
+        slider_multiplier: beatmap.info.difficulty.slider_multiplier,
+        slider_tick_rate,
     };
     let opx_per_secs = beatmap
         .timing_points
@@ -120,66 +802,146 @@ pub fn load_osu_beatmap(beatmap: &osu_parser::Beatmap) -> (ChartInfo, ChartData)
             ))
         })
         .collect::<Vec<_>>();
+    let beat_length_at = |time: i32| {
+        let mut uninherited = beatmap.timing_points.iter().filter(|tp| tp.uninherited);
+        uninherited
+            .clone()
+            .take_while(|tp| tp.time <= time)
+            .last()
+            .or_else(|| uninherited.next())
+            .map(|tp| tp.beat_length / 1000.0)
+            .unwrap_or(1.0)
+    };
     fn opx_to_oepx(x: i16, y: i16) -> cgmath::Vector2<f32> {
         cgmath::vec2(
             math::remap(0.0, 512.0, -320.0, 320.0, x as f32),
             math::remap(0.0, 384.0, -240.0, 240.0, y as f32),
         )
     }
-    let data = ChartData {
+    let mut data = ChartData {
         objects: beatmap
             .hit_objects
             .iter()
-            .map(|hit_object| HitObject {
-                position: opx_to_oepx(hit_object.position.0 as i16, hit_object.position.1 as i16),
-                time: (hit_object.time as f32) / 1000.0,
-                data: match &hit_object.specific {
-                    osu_types::SpecificHitObject::Circle => HitObjectData::Circle,
-                    osu_types::SpecificHitObject::Slider {
-                        curve_type,
-                        curve_points,
-                        slides,
-                        length,
-                    } => {
-                        let opx_per_sec = opx_per_secs
-                            .iter()
-                            .find(|p| p.0 >= hit_object.time as i32)
-                            .unwrap()
-                            .1;
-                        HitObjectData::Slider(Slider {
-                            control_points: curve_points
+            .map(|hit_object| {
+                // Objects the samples weren't read for keep the ones of their sample point
+                let samples = object_samples
+                    .get_mut(&(
+                        hit_object.position.0 as i32,
+                        hit_object.position.1 as i32,
+                        hit_object.time as i32,
+                    ))
+                    .and_then(|samples| samples.pop_front())
+                    .unwrap_or_default();
+                let unsupported = |object| OsuError::Unsupported {
+                    object,
+                    time: hit_object.time as i32,
+                };
+                Ok(HitObject {
+                    position: opx_to_oepx(
+                        hit_object.position.0 as i16,
+                        hit_object.position.1 as i16,
+                    ),
+                    time: (hit_object.time as f32) / 1000.0,
+                    data: match &hit_object.specific {
+                        osu_types::SpecificHitObject::Circle => HitObjectData::Circle,
+                        osu_types::SpecificHitObject::Slider {
+                            curve_type,
+                            curve_points,
+                            slides,
+                            length,
+                        } => {
+                            let opx_per_sec = opx_per_secs
                                 .iter()
-                                //.map(|p| cgmath::vec2(p.x as f32, p.y as f32))
-                                .map(|p| opx_to_oepx(p.x, p.y))
-                                .collect::<Vec<_>>(),
-                            curve_type: match curve_type {
-                                osu_types::CurveType::Bezier => CurveType::Bezier,
-                                osu_types::CurveType::Perfect => CurveType::Perfect,
-                                osu_types::CurveType::Linear => CurveType::Linear,
-                                osu_types::CurveType::Catmull => todo!(),
-                            },
-                            repeat: (*slides as u32 - 1),
-                            velocity: opx_per_sec,
-                            length: *length,
-                        })
-                    }
-                    osu_types::SpecificHitObject::Spinner { end_time: _ } => todo!(),
-                    osu_types::SpecificHitObject::ManiaHold {} => todo!(),
-                },
+                                .find(|p| p.0 >= hit_object.time as i32)
+                                .unwrap()
+                                .1;
+                            HitObjectData::Slider(Slider {
+                                control_points: curve_points
+                                    .iter()
+                                    //.map(|p| cgmath::vec2(p.x as f32, p.y as f32))
+                                    .map(|p| opx_to_oepx(p.x, p.y))
+                                    .collect::<Vec<_>>(),
+                                curve_type: match curve_type {
+                                    osu_types::CurveType::Bezier => CurveType::Bezier,
+                                    osu_types::CurveType::Perfect => CurveType::Perfect,
+                                    osu_types::CurveType::Linear => CurveType::Linear,
+                                    osu_types::CurveType::Catmull => {
+                                        return Err(unsupported("Catmull slider"))
+                                    }
+                                },
+                                repeat: (*slides as u32 - 1),
+                                velocity: opx_per_sec,
+                                length: *length,
+                                tick_interval: beat_length_at(hit_object.time as i32)
+                                    / slider_tick_rate,
+                                edge_samples: samples.edges,
+                            })
+                        }
+                        osu_types::SpecificHitObject::Spinner { end_time: _ } => {
+                            return Err(unsupported("spinner"))
+                        }
+                        osu_types::SpecificHitObject::ManiaHold {} => {
+                            return Err(unsupported("mania hold"))
+                        }
+                    },
+                    marker: MarkerStyle::Standard,
+                    samples: samples.samples,
+                    stack_height: 0,
+                    new_combo: hit_object.new_combo,
+                    combo_colour_skip: hit_object.color_skip as u32,
+                    combo_number: 0,
+                    combo_colour: 0,
+                })
             })
+            .collect::<Result<Vec<_>, _>>()?,
+        combo_colours: beatmap
+            .info
+            .colours
+            .combo
+            .iter()
+            .map(|c| cgmath::vec3(c.r as f32, c.g as f32, c.b as f32) / 255.0)
             .collect(),
+        beats: BeatTracker::new(parse_timing_points(content)),
+        kiai_sections: parse_kiai_sections(content),
+        breaks: parse_breaks(content),
+        background: parse_background(content),
+        hitsounds: Hitsounds::parse(content),
+        storyboard_samples: hitsound::parse_storyboard_samples(content),
+        cutscenes: Vec::new(),
     };
-    (info, data)
+    assign_combos(&mut data.objects);
+    apply_stacking(
+        &mut data.objects,
+        osu_ar_to_secs(beatmap.info.difficulty.ar),
+        beatmap.info.general.stack_leniency,
+        osu_cs_to_radius(beatmap.info.difficulty.cs),
+    );
+    Ok((info, data))
+}
+
+/// Loads the chart of a bundled `.osu` file known to be valid.
+#[cfg(test)]
+pub(crate) fn load_test_chart(content: &str) -> (ChartInfo, ChartData) {
+    load_osu_beatmap(content).unwrap()
+}
+
+#[test]
+fn test_unsupported_objects() {
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [test].osu");
+    let with_spinner = format!("{}\n256,192,6000,8,0,7000\n", content.trim_end());
+    assert!(matches!(
+        load_osu_beatmap(&with_spinner),
+        Err(OsuError::Unsupported {
+            object: "spinner",
+            time: 6000
+        })
+    ));
 }
 
 #[test]
 fn test_osu_conversion() {
-    let beatmap = osu_parser::load_content(
-        include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu"),
-        osu_parser::BeatmapParseOptions::default(),
-    )
-    .unwrap();
-    let (_info, data) = load_osu_beatmap(&beatmap);
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let (_info, data) = load_test_chart(content);
     fn check(correct: f32, value: f32) {
         assert!(
             (value - correct).abs() <= f32::EPSILON,
@@ -193,3 +955,277 @@ fn test_osu_conversion() {
     // ?? why??
     check(4.399, first.end_time());
 }
+
+#[test]
+fn test_breaks() {
+    let breaks = parse_breaks(
+        "[Events]\n//Break Periods\n2,20500,25000\nBreak,1000,3000\n0,0,\"bg.jpg\",0,0\n",
+    );
+    assert_eq!(
+        breaks,
+        vec![
+            BreakPeriod {
+                start: 1.0,
+                end: 3.0
+            },
+            BreakPeriod {
+                start: 20.5,
+                end: 25.0
+            },
+        ]
+    );
+
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let (_info, mut data) = load_test_chart(content);
+    assert!(data.breaks.is_empty());
+
+    // Open up a long gap after the 10th object and make it a break
+    let gap_start = data.objects[9].end_time();
+    for obj in &mut data.objects[10..] {
+        obj.time += 20.0;
+    }
+    let next = data.objects[10].time;
+    data.breaks.push(BreakPeriod {
+        start: gap_start + 0.5,
+        end: next - 0.5,
+    });
+
+    let approach = 0.5;
+    assert!(!data.drains_health(0.0));
+    assert!(data.drains_health(data.objects[0].time));
+    assert!(!data.drains_health(gap_start + 1.0));
+    assert!(data.drains_health(next));
+
+    // Intro
+    assert_eq!(
+        data.skip_target(0.0, approach),
+        Some(4.068 - approach - 1.0)
+    );
+    assert_eq!(data.skip_target(3.0, approach), None);
+    // Break
+    assert_eq!(
+        data.skip_target(gap_start + 1.0, approach),
+        Some(next - approach - 1.0)
+    );
+    assert_eq!(data.skip_target(next - 2.0, approach), None);
+    // Gameplay
+    assert_eq!(data.skip_target(data.objects[5].time, approach), None);
+}
+
+#[test]
+fn test_kiai() {
+    let content = "[TimingPoints]
+1000,500,3,1,0,100,1,0
+2000,-100,4,1,0,100,0,1
+3000,-100,4,1,0,100,0,0
+4000,250,4,1,0,100,1,1
+4500,-50,4,1,0,100,0,9
+";
+    assert_eq!(
+        parse_timing_points(content),
+        vec![
+            TimingPoint {
+                time: 1.0,
+                beat_length: 0.5,
+                meter: 3
+            },
+            TimingPoint {
+                time: 4.0,
+                beat_length: 0.25,
+                meter: 4
+            },
+        ]
+    );
+    assert_eq!(
+        parse_kiai_sections(content),
+        vec![
+            KiaiSection {
+                start: 2.0,
+                end: 3.0
+            },
+            KiaiSection {
+                start: 4.0,
+                end: f32::INFINITY
+            },
+        ]
+    );
+
+    let (_info, mut data) = load_test_chart(include_str!(
+        "../../positive MAD-crew - Mynarco Addiction (Okoratu) [test].osu"
+    ));
+    data.beats = BeatTracker::new(parse_timing_points(content));
+    data.kiai_sections = parse_kiai_sections(content);
+    assert_eq!(data.kiai_pulse(1.5), 0.0);
+    assert_eq!(data.kiai_pulse(2.0), 1.0);
+    assert!((data.kiai_pulse(2.25) - 0.25).abs() < 1e-6);
+    assert_eq!(data.kiai_pulse(3.0), 0.0);
+    assert_eq!(data.kiai_pulse(100.0), 1.0);
+}
+
+#[test]
+fn test_background() {
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let (_info, data) = load_test_chart(content);
+    assert_eq!(
+        data.background.as_deref(),
+        Some("i_found_that_within_10_mins.jpg")
+    );
+    assert_eq!(
+        parse_background("[Events]\nVideo,0,\"video.avi\"\n0,0,\"bg\\bg.png\",0,0\n").as_deref(),
+        Some("bg/bg.png")
+    );
+    assert_eq!(
+        parse_background("[Events]\n1,-200,\"video.mp4\",0,0\nBackground,0,\"bg.jpg\"\n")
+            .as_deref(),
+        Some("bg.jpg")
+    );
+    assert_eq!(parse_background("[Events]\nVideo,0,\"video.avi\"\n"), None);
+}
+
+#[test]
+fn test_lead_in() {
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let (info, data) = load_test_chart(content);
+    assert_eq!(info.audio_lead_in, 0.0);
+    assert_eq!(info.preview_time, Some(242.7));
+    assert_eq!(info.preview_start(300.0), 242.7);
+    // The first object is late enough to approach fully without lead-in
+    assert_eq!(info.start_time(data.objects[0].time), 0.0);
+    // Objects at the very start still get their whole approach and some time to get ready
+    let start = info.start_time(0.0);
+    assert!((start + info.modifiers.approach_seconds() + MIN_LEAD_IN_SECONDS).abs() < 1e-6);
+
+    let info = ChartInfo {
+        audio_lead_in: 5.0,
+        preview_time: None,
+        ..info
+    };
+    assert_eq!(info.start_time(data.objects[0].time), -5.0);
+    assert_eq!(info.preview_start(300.0), 120.0);
+}
+
+#[test]
+fn test_stacking() {
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let (_info, data) = load_test_chart(content);
+    let stack_offset = osu_cs_to_radius(4.2) / 10.0 * OSU_PIXEL_SCALE;
+    fn check(correct: cgmath::Vector2<f32>, value: cgmath::Vector2<f32>) {
+        assert!(
+            (value - correct).magnitude() <= 0.001,
+            "Correct: {:?}, Value: {:?}",
+            correct,
+            value
+        );
+    }
+
+    // Two circles stacked on the head of a slider at 367,83 (43681ms)
+    let slider = &data.objects[157];
+    assert_eq!(slider.stack_height, 0);
+    assert_eq!(data.objects[156].stack_height, 1);
+    assert_eq!(data.objects[155].stack_height, 2);
+    check(
+        slider.position - cgmath::vec2(stack_offset, stack_offset),
+        data.objects[156].position,
+    );
+    check(
+        slider.position - cgmath::vec2(stack_offset, stack_offset) * 2.0,
+        data.objects[155].position,
+    );
+
+    // Circle pair at 255,167 (29924ms)
+    assert_eq!(data.objects[87].stack_height, 1);
+    assert_eq!(data.objects[88].stack_height, 0);
+    check(
+        data.objects[88].position - cgmath::vec2(stack_offset, stack_offset),
+        data.objects[87].position,
+    );
+
+    // Objects with nothing underneath stay put
+    assert_eq!(data.objects[0].stack_height, 0);
+    check(cgmath::vec2(-151.25, -92.5), data.objects[0].position);
+}
+
+#[test]
+fn test_combos() {
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let (_info, data) = load_test_chart(content);
+    assert_eq!(
+        data.combo_colours,
+        vec![
+            cgmath::vec3(18.0, 124.0, 255.0) / 255.0,
+            cgmath::vec3(128.0, 0.0, 128.0) / 255.0
+        ]
+    );
+
+    // First combo has 8 objects, the 9th starts a new one
+    let numbers = data.objects[..10]
+        .iter()
+        .map(|obj| obj.combo_number)
+        .collect::<Vec<_>>();
+    assert_eq!(numbers, vec![1, 2, 3, 4, 5, 6, 7, 8, 1, 2]);
+    assert_eq!(data.objects[7].combo_colour, 0);
+    assert_eq!(data.objects[8].combo_colour, 1);
+    let fallback = &DEFAULT_COMBO_COLOURS;
+    assert_eq!(
+        data.combo_colour(&data.objects[0], fallback),
+        data.combo_colours[0]
+    );
+    assert_eq!(
+        data.combo_colour(&data.objects[8], fallback),
+        data.combo_colours[1]
+    );
+    assert_eq!(
+        data.combo_colour(&data.objects[15], fallback),
+        data.combo_colours[0]
+    );
+}
+
+#[test]
+fn test_slider_events() {
+    let slider = Slider {
+        control_points: Vec::new(),
+        curve_type: CurveType::Linear,
+        repeat: 1,
+        velocity: 100.0,
+        length: 100.0,
+        tick_interval: 0.3,
+        edge_samples: Vec::new(),
+    };
+    let events = slider.events(10.0);
+    let expected = [
+        (10.3, SliderEvent::Tick),
+        (10.6, SliderEvent::Tick),
+        (10.9, SliderEvent::Tick),
+        (11.0, SliderEvent::Edge(1)),
+        (11.1, SliderEvent::Tick),
+        (11.4, SliderEvent::Tick),
+        (11.7, SliderEvent::Tick),
+        (12.0, SliderEvent::Edge(2)),
+    ];
+    assert_eq!(events.len(), expected.len());
+    for ((time, event), (expected_time, expected_event)) in events.into_iter().zip(expected) {
+        assert!((time - expected_time).abs() < 0.0001);
+        assert_eq!(event, expected_event);
+    }
+
+    let data = {
+        let content =
+            include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+        load_test_chart(content).1
+    };
+    match &data.objects[0].data {
+        HitObjectData::Slider(slider) => {
+            // 331.49ms per beat with a tick rate of 2
+            assert!((slider.tick_interval - 0.16574586).abs() < 0.0001);
+            assert_eq!(slider.edge_samples.len(), 2);
+            assert_eq!(
+                slider.edge_samples[1].additions,
+                crate::game::hitsound::WHISTLE
+            );
+        }
+        HitObjectData::Circle => panic!("First object should be a slider"),
+    }
+    let names = data.beatmap_sample_names();
+    assert!(names.contains("soft-hitnormal12"));
+    assert!(names.contains("soft-slidertick12"));
+}
@@ -0,0 +1,160 @@
+//! Native chart files, `ChartInfo` and `ChartData` written as RON.
+//!
+//! Unlike .osu files they keep everything the game knows about a chart, including the Ouendan
+//! features, and load without going through the beatmap parser.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::chart::{self, ChartData, ChartInfo};
+
+/// Version written to new files. Files of any other version are refused.
+pub const FORMAT_VERSION: u32 = 1;
+pub const EXTENSION: &str = "ochart";
+
+#[derive(Debug)]
+pub enum ChartFileError {
+    Ron(ron::Error),
+    UnsupportedVersion(u32),
+    Osu(chart::OsuError),
+}
+
+impl std::fmt::Display for ChartFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChartFileError::Ron(e) => write!(f, "{}", e),
+            ChartFileError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported chart version {}, expected {}",
+                version, FORMAT_VERSION
+            ),
+            ChartFileError::Osu(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Read first on its own, so files of other versions are refused before the rest is parsed.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct ChartFile {
+    info: ChartInfo,
+    data: ChartData,
+}
+
+#[derive(Serialize)]
+struct ChartFileRef<'a> {
+    version: u32,
+    info: &'a ChartInfo,
+    data: &'a ChartData,
+}
+
+/// Whether `path` is a native chart file rather than a .osu one.
+pub fn is_chart_file(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == EXTENSION)
+}
+
+/// Loads the chart of the native chart file `content`.
+pub fn parse(content: &str) -> Result<(ChartInfo, ChartData), ChartFileError> {
+    let header = ron::from_str::<Header>(content).map_err(ChartFileError::Ron)?;
+    if header.version != FORMAT_VERSION {
+        return Err(ChartFileError::UnsupportedVersion(header.version));
+    }
+    let file = ron::from_str::<ChartFile>(content).map_err(ChartFileError::Ron)?;
+    Ok((file.info, file.data))
+}
+
+/// Loads the chart of `content`, read from the .osu or native chart file at `path`.
+pub fn load_chart(path: &Path, content: &str) -> Result<(ChartInfo, ChartData), ChartFileError> {
+    if is_chart_file(path) {
+        parse(content)
+    } else {
+        chart::load_osu_beatmap(content).map_err(ChartFileError::Osu)
+    }
+}
+
+/// Writes a chart to a native chart file.
+pub fn write(info: &ChartInfo, data: &ChartData) -> String {
+    let file = ChartFileRef {
+        version: FORMAT_VERSION,
+        info,
+        data,
+    };
+    // Every type in a chart serializes to RON
+    ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::new()).unwrap()
+}
+
+/// Converts the .osu file `content` to a native chart file.
+pub fn convert_osu(content: &str) -> Result<String, chart::OsuError> {
+    let (info, data) = chart::load_osu_beatmap(content)?;
+    Ok(write(&info, &data))
+}
+
+#[cfg(test)]
+const TEST_CHART: &str =
+    include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+
+#[test]
+fn test_round_trip() {
+    let (info, data) = chart::load_test_chart(TEST_CHART);
+    let written = write(&info, &data);
+    let (loaded_info, loaded_data) = parse(&written).unwrap();
+    assert_eq!(loaded_info, info);
+    assert_eq!(loaded_data, data);
+    assert_eq!(write(&loaded_info, &loaded_data), written);
+
+    assert_eq!(convert_osu(TEST_CHART).unwrap(), written);
+}
+
+#[test]
+fn test_ouendan_features() {
+    use chart::{CutscenePanel, MarkerStyle, PanelCondition};
+
+    let (info, mut data) = chart::load_test_chart(TEST_CHART);
+    data.objects[0].marker = MarkerStyle::Ouendan;
+    data.objects[1].marker = MarkerStyle::Cheerleaders;
+    data.cutscenes = vec![
+        CutscenePanel {
+            start: 1.0,
+            end: 3.5,
+            image: "panels/intro.png".to_owned(),
+            position: cgmath::vec2(-120.0, 0.0),
+            size: cgmath::vec2(300.0, 200.0),
+            condition: PanelCondition::Always,
+        },
+        CutscenePanel {
+            start: 60.0,
+            end: 62.0,
+            image: "panels/cheer.png".to_owned(),
+            position: cgmath::vec2(120.0, -60.0),
+            size: cgmath::vec2(200.0, 150.0),
+            condition: PanelCondition::Failing,
+        },
+    ];
+    let (_, loaded) = parse(&write(&info, &data)).unwrap();
+    assert_eq!(loaded, data);
+}
+
+#[test]
+fn test_version() {
+    let (info, data) = chart::load_test_chart(TEST_CHART);
+    let written = write(&info, &data);
+    assert!(written.contains(&format!("version: {}", FORMAT_VERSION)));
+    let newer = written.replacen(
+        &format!("version: {}", FORMAT_VERSION),
+        &format!("version: {}", FORMAT_VERSION + 1),
+        1,
+    );
+    assert!(matches!(
+        parse(&newer),
+        Err(ChartFileError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+    ));
+    assert!(matches!(
+        parse("(version: 1, info: ())"),
+        Err(ChartFileError::Ron(_))
+    ));
+}
@@ -0,0 +1,199 @@
+use std::time::Instant;
+
+use kira::instance::{
+    handle::InstanceHandle, InstanceState, PauseInstanceSettings, ResumeInstanceSettings,
+    StopInstanceSettings,
+};
+
+/// Drift from the audio position above which the clock jumps to it instead of easing towards it.
+const MAX_DRIFT_SECONDS: f64 = 0.1;
+/// Fraction of the drift from the audio position corrected every update.
+const DRIFT_CORRECTION: f64 = 0.1;
+
+/// Returns the clock time after `elapsed` song seconds, corrected towards `audio_time`.
+fn advance(time: f64, elapsed: f64, audio_time: f64) -> f64 {
+    let predicted = time + elapsed;
+    let drift = audio_time - predicted;
+    if drift.abs() > MAX_DRIFT_SECONDS {
+        audio_time
+    } else {
+        // Easing backwards would make objects jitter, wait for the audio to catch up instead
+        (predicted + drift * DRIFT_CORRECTION).max(time)
+    }
+}
+
+/// Song time everything synchronized to the music reads from.
+///
+/// The audio position only advances once per audio buffer, so in between the clock runs on a
+/// monotonic timer, while slowly correcting towards the audio position to never drift away from it.
+/// The audio offset is applied on top of it, see [`crate::game::offset::Offsets`].
+///
+/// Before the song starts, during the lead-in, the clock runs on the timer alone with a negative time.
+pub struct GameClock {
+    song: InstanceHandle,
+    /// Position of the audio, without the offset.
+    time: f64,
+    offset: f64,
+    last_update: Instant,
+    rate: f64,
+    paused: bool,
+    /// Whether the clock is in the lead-in, with the song held at its start.
+    lead_in: bool,
+}
+
+impl GameClock {
+    pub fn new(song: InstanceHandle) -> Self {
+        GameClock {
+            time: song.position(),
+            song,
+            last_update: Instant::now(),
+            offset: 0.0,
+            rate: 1.0,
+            paused: false,
+            lead_in: false,
+        }
+    }
+
+    /// Advances the clock, should be called once per frame before anything reads it.
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+        if self.paused {
+            return;
+        }
+        if self.lead_in {
+            self.time += elapsed * self.rate;
+            if self.time >= 0.0 {
+                self.lead_in = false;
+                self.song.seek_to(self.time).unwrap();
+                self.resume_song();
+            }
+            return;
+        }
+        if !matches!(self.song.state(), InstanceState::Playing) {
+            return;
+        }
+
+        self.time = advance(self.time, elapsed * self.rate, self.song.position());
+    }
+
+    /// Current song time in seconds, delayed by the audio offset.
+    pub fn time(&self) -> f64 {
+        self.time - self.offset
+    }
+
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    /// Sets the audio offset in seconds, positive when the audio is heard late.
+    pub fn set_offset(&mut self, offset: f64) {
+        self.offset = offset;
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Whether the song hasn't started yet.
+    pub fn in_lead_in(&self) -> bool {
+        self.lead_in
+    }
+
+    /// Stops the song for good, once nothing is timed from it anymore.
+    pub fn stop(mut self) {
+        self.song
+            .stop(StopInstanceSettings { fade_tween: None })
+            .unwrap();
+    }
+
+    fn pause_song(&mut self) {
+        self.song
+            .pause(PauseInstanceSettings { fade_tween: None })
+            .unwrap();
+    }
+
+    fn resume_song(&mut self) {
+        self.song
+            .resume(ResumeInstanceSettings {
+                fade_tween: None,
+                rewind_to_pause_position: false,
+            })
+            .unwrap();
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused {
+            return;
+        }
+        if !self.lead_in {
+            self.pause_song();
+        }
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+        if !self.lead_in {
+            self.resume_song();
+        }
+        self.paused = false;
+        self.last_update = Instant::now();
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Moves the song and the clock to `time` seconds of song time.
+    /// Seeking before the start of the song holds it there until the clock reaches it.
+    pub fn seek(&mut self, time: f64) {
+        let time = time + self.offset;
+        let lead_in = time < 0.0;
+        if lead_in != self.lead_in && !self.paused {
+            if lead_in {
+                self.pause_song();
+            } else {
+                self.resume_song();
+            }
+        }
+        self.song.seek_to(time.max(0.0)).unwrap();
+        self.lead_in = lead_in;
+        self.time = time;
+        self.last_update = Instant::now();
+    }
+
+    /// Changes the playback speed of the song, 1 being the normal speed.
+    pub fn set_rate(&mut self, rate: f64) {
+        self.song.set_playback_rate(rate).unwrap();
+        self.rate = rate;
+    }
+}
+
+#[test]
+fn test_advance() {
+    // Audio position stuck on the same buffer, the clock keeps going smoothly
+    let time = advance(1.0, 0.004, 1.0);
+    assert!(time > 1.0 && time < 1.004);
+    // Never goes backwards even if the audio is behind
+    assert_eq!(advance(1.0, 0.0, 0.95), 1.0);
+    // Drift gets corrected over time
+    let mut time = 1.0;
+    for _ in 0..200 {
+        time = advance(time, 0.001, 1.05);
+    }
+    assert!((time - 1.05).abs() < 0.05);
+    // Large jumps like seeks are followed immediately
+    assert_eq!(advance(1.0, 0.016, 30.0), 30.0);
+}
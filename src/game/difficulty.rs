@@ -0,0 +1,335 @@
+//! Difficulty of a chart, after osu!'s standard difficulty model: aim and speed strains that
+//! build up with every object and decay over time, weighted by their highest peaks.
+
+use cgmath::InnerSpace;
+
+use super::chart::{self, ChartData, ChartInfo, HitObject, HitObjectData, OSU_PIXEL_SCALE};
+
+/// Radius objects are scaled to before measuring distances, in osu!pixels.
+const NORMALIZED_RADIUS: f32 = 52.0;
+/// Objects closer in time than this are strained as if they were this far apart, in milliseconds.
+const MIN_STRAIN_TIME: f32 = 50.0;
+/// Length of the sections the highest strain is taken from, in milliseconds.
+const SECTION_LENGTH: f32 = 400.0;
+/// Weight of each section peak relative to the next higher one.
+const DECAY_WEIGHT: f32 = 0.9;
+const DIFFICULTY_MULTIPLIER: f32 = 0.0675;
+/// The cursor only has to stay within this many radii of the slider ball.
+const FOLLOW_RADIUS_SCALE: f32 = 3.0;
+
+/// Numbers describing how hard a chart is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifficultyAttributes {
+    pub star_rating: f32,
+    /// Difficulty of moving the cursor between objects.
+    pub aim: f32,
+    /// Difficulty of tapping fast.
+    pub speed: f32,
+    pub max_combo: u32,
+    /// osu! approach rate and overall difficulty of the chart.
+    pub approach_rate: f32,
+    pub overall_difficulty: f32,
+    pub circle_count: u32,
+    pub slider_count: u32,
+}
+
+/// Object as seen by the strain skills, measured against the objects before it.
+struct DifficultyObject {
+    start_time: f32,
+    /// Milliseconds since the previous object.
+    delta_time: f32,
+    /// `delta_time`, at least [`MIN_STRAIN_TIME`].
+    strain_time: f32,
+    /// Normalized distance from where the cursor left the previous object.
+    jump_distance: f32,
+    /// Normalized distance the cursor travelled following the previous object if it's a slider.
+    travel_distance: f32,
+    /// Angle at the previous object between the jumps to and from it, in radians.
+    angle: Option<f32>,
+    /// `jump_distance` and `strain_time` of the previous object, `None` for the first one.
+    previous: Option<(f32, f32)>,
+}
+
+/// Where the cursor leaves `obj`.
+fn cursor_end(obj: &HitObject) -> cgmath::Vector2<f32> {
+    obj.end_position() / OSU_PIXEL_SCALE
+}
+
+/// Distance the cursor has to travel along a slider, in osu!pixels. The cursor may lag behind
+/// the ball by the follow radius, so short sliders barely need any movement.
+fn travel_distance(obj: &HitObject, radius: f32) -> f32 {
+    match &obj.data {
+        HitObjectData::Slider(slider) => {
+            (slider.length * (slider.repeat + 1) as f32 - radius * FOLLOW_RADIUS_SCALE).max(0.0)
+        }
+        HitObjectData::Circle => 0.0,
+    }
+}
+
+fn difficulty_objects(objects: &[HitObject], radius: f32) -> Vec<DifficultyObject> {
+    let mut scaling_factor = NORMALIZED_RADIUS / radius;
+    // Small circles are harder to aim at than their spacing suggests
+    if radius < 30.0 {
+        scaling_factor *= 1.0 + (30.0 - radius).min(5.0) / 50.0;
+    }
+
+    let mut previous = None;
+    objects
+        .windows(2)
+        .enumerate()
+        .map(|(idx, pair)| {
+            let (last, current) = (&pair[0], &pair[1]);
+            let position = current.position / OSU_PIXEL_SCALE;
+            let last_end = cursor_end(last);
+            let angle = idx.checked_sub(1).map(|last_last| {
+                let v1 = cursor_end(&objects[last_last]) - last.position / OSU_PIXEL_SCALE;
+                let v2 = position - last_end;
+                let dot = v1.dot(v2);
+                let det = v1.x * v2.y - v1.y * v2.x;
+                det.atan2(dot).abs()
+            });
+            let delta_time = (current.time - last.time) * 1000.0;
+            let object = DifficultyObject {
+                start_time: current.time * 1000.0,
+                delta_time,
+                strain_time: delta_time.max(MIN_STRAIN_TIME),
+                jump_distance: (position - last_end).magnitude() * scaling_factor,
+                travel_distance: travel_distance(last, radius) * scaling_factor,
+                angle,
+                previous,
+            };
+            previous = Some((object.jump_distance, object.strain_time));
+            object
+        })
+        .collect()
+}
+
+/// Strain of an object on one skill.
+trait Skill {
+    const SKILL_MULTIPLIER: f32;
+    const STRAIN_DECAY_BASE: f32;
+
+    fn strain_value(current: &DifficultyObject) -> f32;
+}
+
+struct Aim;
+
+impl Aim {
+    const ANGLE_BONUS_BEGIN: f32 = std::f32::consts::PI / 3.0;
+    const TIMING_THRESHOLD: f32 = 107.0;
+}
+
+fn diminishing_exp(value: f32) -> f32 {
+    value.powf(0.99)
+}
+
+impl Skill for Aim {
+    const SKILL_MULTIPLIER: f32 = 26.25;
+    const STRAIN_DECAY_BASE: f32 = 0.15;
+
+    fn strain_value(current: &DifficultyObject) -> f32 {
+        let mut result = 0.0;
+        if let (Some((previous_jump, previous_strain_time)), Some(angle)) =
+            (current.previous, current.angle)
+        {
+            // Sharp turns between two long jumps
+            if angle > Self::ANGLE_BONUS_BEGIN {
+                let scale = 90.0;
+                let angle_bonus = ((previous_jump - scale).max(0.0)
+                    * (angle - Self::ANGLE_BONUS_BEGIN).sin().powi(2)
+                    * (current.jump_distance - scale).max(0.0))
+                .sqrt();
+                result = 1.5 * diminishing_exp(angle_bonus.max(0.0))
+                    / previous_strain_time.max(Self::TIMING_THRESHOLD);
+            }
+        }
+
+        let jump = diminishing_exp(current.jump_distance);
+        let travel = diminishing_exp(current.travel_distance);
+        let distance = jump + travel + (travel * jump).sqrt();
+        f32::max(
+            result + distance / current.strain_time.max(Self::TIMING_THRESHOLD),
+            distance / current.strain_time,
+        )
+    }
+}
+
+struct Speed;
+
+impl Speed {
+    const SINGLE_SPACING_THRESHOLD: f32 = 125.0;
+    const ANGLE_BONUS_BEGIN: f32 = 5.0 * std::f32::consts::PI / 6.0;
+    const MIN_SPEED_BONUS: f32 = 75.0;
+    const MAX_SPEED_BONUS: f32 = 45.0;
+    const SPEED_BALANCING_FACTOR: f32 = 40.0;
+}
+
+impl Skill for Speed {
+    const SKILL_MULTIPLIER: f32 = 1400.0;
+    const STRAIN_DECAY_BASE: f32 = 0.3;
+
+    fn strain_value(current: &DifficultyObject) -> f32 {
+        use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+        let distance =
+            Self::SINGLE_SPACING_THRESHOLD.min(current.travel_distance + current.jump_distance);
+        let delta_time = current.delta_time.max(Self::MAX_SPEED_BONUS);
+
+        let mut speed_bonus = 1.0;
+        if delta_time < Self::MIN_SPEED_BONUS {
+            speed_bonus +=
+                ((Self::MIN_SPEED_BONUS - delta_time) / Self::SPEED_BALANCING_FACTOR).powi(2);
+        }
+
+        let mut angle_bonus = 1.0;
+        if let Some(angle) = current.angle {
+            if angle < Self::ANGLE_BONUS_BEGIN {
+                angle_bonus = 1.0 + (1.5 * (Self::ANGLE_BONUS_BEGIN - angle)).sin().powi(2) / 3.57;
+                if angle < FRAC_PI_2 {
+                    angle_bonus = 1.28;
+                    if distance < 90.0 && angle < FRAC_PI_4 {
+                        angle_bonus += (1.0 - angle_bonus) * ((90.0 - distance) / 10.0).min(1.0);
+                    } else if distance < 90.0 {
+                        angle_bonus += (1.0 - angle_bonus)
+                            * ((90.0 - distance) / 10.0).min(1.0)
+                            * ((FRAC_PI_2 - angle) / FRAC_PI_4).sin();
+                    }
+                }
+            }
+        }
+
+        (1.0 + (speed_bonus - 1.0) * 0.75)
+            * angle_bonus
+            * (0.95 + speed_bonus * (distance / Self::SINGLE_SPACING_THRESHOLD).powf(3.5))
+            / current.strain_time
+    }
+}
+
+/// Difficulty value of a skill over every object: the highest strain of each section,
+/// summed with less and less weight from the highest down.
+/// Sections are aligned to multiples of their length, starting with the one `first_time` is in.
+fn skill_difficulty<S: Skill>(objects: &[DifficultyObject], first_time: f32) -> f32 {
+    let mut peaks = Vec::new();
+    let mut strain = 0.0;
+    let mut section_peak = 0.0;
+    let mut section_end = (first_time / SECTION_LENGTH).ceil() * SECTION_LENGTH;
+    let decay = |strain: f32, ms: f32| strain * S::STRAIN_DECAY_BASE.powf(ms / 1000.0);
+
+    for (idx, current) in objects.iter().enumerate() {
+        let previous = idx.checked_sub(1).map(|previous| &objects[previous]);
+        while current.start_time > section_end {
+            peaks.push(section_peak);
+            // The next section starts with what is left of the strain when it begins
+            section_peak = previous.map_or(0.0, |previous| {
+                decay(strain, section_end - previous.start_time)
+            });
+            section_end += SECTION_LENGTH;
+        }
+
+        strain = decay(strain, current.delta_time) + S::strain_value(current) * S::SKILL_MULTIPLIER;
+        section_peak = f32::max(section_peak, strain);
+    }
+    peaks.push(section_peak);
+
+    peaks.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let mut weight = 1.0;
+    let mut difficulty = 0.0;
+    for peak in peaks {
+        difficulty += peak * weight;
+        weight *= DECAY_WEIGHT;
+    }
+    difficulty
+}
+
+/// Highest combo reachable on the chart: one for every circle, and for every slider head,
+/// tick, repeat and tail.
+pub fn max_combo(data: &ChartData) -> u32 {
+    data.objects
+        .iter()
+        .map(|obj| match &obj.data {
+            HitObjectData::Circle => 1,
+            HitObjectData::Slider(slider) => 1 + slider.events(obj.time).len() as u32,
+        })
+        .sum()
+}
+
+pub fn calculate(info: &ChartInfo, data: &ChartData) -> DifficultyAttributes {
+    let radius = chart::osu_cs_to_radius(info.modifiers.circle_size);
+    let objects = difficulty_objects(&data.objects, radius);
+    let first_time = data.objects.first().map_or(0.0, |obj| obj.time * 1000.0);
+    let aim = skill_difficulty::<Aim>(&objects, first_time).sqrt() * DIFFICULTY_MULTIPLIER;
+    let speed = skill_difficulty::<Speed>(&objects, first_time).sqrt() * DIFFICULTY_MULTIPLIER;
+    let slider_count = data
+        .objects
+        .iter()
+        .filter(|obj| matches!(obj.data, HitObjectData::Slider(_)))
+        .count() as u32;
+    DifficultyAttributes {
+        star_rating: aim + speed + (aim - speed).abs() / 2.0,
+        aim,
+        speed,
+        max_combo: max_combo(data),
+        approach_rate: chart::secs_to_osu_ar(info.modifiers.approach_seconds()),
+        overall_difficulty: info.modifiers.overall_difficulty,
+        circle_count: data.objects.len() as u32 - slider_count,
+        slider_count,
+    }
+}
+
+#[test]
+fn test_difficulty() {
+    // Five circles jumping around the corners, worked out by hand
+    let (info, data) = chart::load_test_chart(include_str!(
+        "../../positive MAD-crew - Mynarco Addiction (Okoratu) [corner].osu"
+    ));
+    let attributes = calculate(&info, &data);
+    assert!((attributes.aim - 1.69639).abs() < 0.001);
+    assert!((attributes.speed - 0.79504).abs() < 0.001);
+    assert!((attributes.star_rating - 2.94211).abs() < 0.001);
+    assert_eq!(attributes.max_combo, 5);
+    assert_eq!((attributes.circle_count, attributes.slider_count), (5, 0));
+    assert!((attributes.approach_rate - 9.2).abs() < 0.001);
+
+    // Sliders count their ticks and tails
+    let (info, data) = chart::load_test_chart(include_str!(
+        "../../positive MAD-crew - Mynarco Addiction (Okoratu) [test].osu"
+    ));
+    let attributes = calculate(&info, &data);
+    assert_eq!(attributes.max_combo, 13);
+    assert_eq!((attributes.circle_count, attributes.slider_count), (0, 4));
+    assert!((attributes.approach_rate - 9.2).abs() < 0.001);
+    assert!((attributes.overall_difficulty - 8.5).abs() < 0.001);
+
+    let (info, data) = chart::load_test_chart(include_str!(
+        "../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu"
+    ));
+    let attributes = calculate(&info, &data);
+    assert!(attributes.max_combo as usize > data.objects.len());
+    assert!(attributes.aim > 0.0 && attributes.speed > 0.0);
+}
+
+#[test]
+fn test_spacing() {
+    let (info, data) = chart::load_test_chart(include_str!(
+        "../../positive MAD-crew - Mynarco Addiction (Okoratu) [corner].osu"
+    ));
+    let base = calculate(&info, &data);
+
+    // The same jumps in half the time
+    let mut faster = data.clone();
+    for obj in &mut faster.objects {
+        obj.time /= 2.0;
+    }
+    let faster = calculate(&info, &faster);
+    assert!(faster.aim > base.aim);
+    assert!(faster.speed > base.speed);
+
+    // Half as far apart
+    let mut closer = data;
+    for obj in &mut closer.objects {
+        obj.position /= 2.0;
+    }
+    let closer = calculate(&info, &closer);
+    assert!(closer.aim < base.aim);
+    assert!(closer.star_rating < base.star_rating);
+}
@@ -1,17 +1,50 @@
-use std::{collections::HashMap, hash::Hash, num::NonZeroU32};
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, num::NonZeroU32};
 
 use atlas_packer::PackSolver;
 use ogfx::{self, texture::RawTextureData, GraphicsContext, Rect};
 
+#[derive(Clone)]
 pub struct Atlas<T> {
     pub texture: ogfx::ArcTexture,
     pub sub_textures: HashMap<T, Rect<f32>>,
+    /// Pixels per logical pixel of each sub texture.
+    pub scale_factors: HashMap<T, f32>,
 }
 
 impl<T> Atlas<T>
 where
     T: Clone + Hash + Eq,
 {
+    /// Returns the size of a sub texture in pixels.
+    pub fn pixel_size<Q>(&self, key: &Q) -> cgmath::Vector2<f32>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = self.sub_textures[key].size;
+        cgmath::vec2(
+            size.x * self.texture.size.x as f32,
+            size.y * self.texture.size.y as f32,
+        )
+    }
+
+    /// Returns the size of a sub texture independent of its resolution.
+    pub fn logical_size<Q>(&self, key: &Q) -> cgmath::Vector2<f32>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pixel_size(key) / self.scale_factors[key]
+    }
+
+    /// Returns the scale needed for a sprite showing the sub texture to be `size` large.
+    pub fn scale_for(&self, size: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+        cgmath::vec2(
+            size.x / self.texture.size.x as f32,
+            size.y / self.texture.size.y as f32,
+        )
+    }
+
     pub fn new(
         gfx: &GraphicsContext,
         textures: &[(&T, &RawTextureData)],
@@ -88,6 +121,10 @@ where
                     )
                 })
                 .collect(),
+            scale_factors: textures
+                .iter()
+                .map(|(key, raw)| ((*key).clone(), raw.scale_factor))
+                .collect(),
         }
     }
 }
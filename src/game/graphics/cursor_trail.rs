@@ -0,0 +1,69 @@
+use std::{collections::VecDeque, time::Instant};
+
+use ogfx::{
+    spritebatch::SpriteIdx, ArcTexture, GraphicsContext, RenderContext, Renderable, SpriteBatch,
+    Transform,
+};
+
+use crate::math;
+
+/// How long a copy of the cursor takes to fade out.
+const FADE_SECONDS: f32 = 0.15;
+/// Least time between two copies, so the trail doesn't depend on the frame rate.
+const SPACING_SECONDS: f32 = 0.01;
+/// Opacity of the newest copy between beats.
+const ALPHA: f32 = 0.3;
+/// Opacity added to the trail on a beat.
+const PULSE_ALPHA: f32 = 0.3;
+
+/// Fading copies of the cursor left where it was.
+pub struct CursorTrail {
+    batch: SpriteBatch,
+    /// Copies of the cursor, newest first, with when they were left.
+    copies: VecDeque<(Instant, SpriteIdx)>,
+}
+
+impl CursorTrail {
+    pub fn new(gfx: &GraphicsContext, texture: ArcTexture) -> Self {
+        let capacity = (FADE_SECONDS / SPACING_SECONDS).ceil() as usize + 1;
+        CursorTrail {
+            batch: SpriteBatch::new(gfx, texture, capacity),
+            copies: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Leaves a copy of the `cursor` and fades the older ones, brighter by `pulse` on a beat.
+    pub fn update(&mut self, gfx: &GraphicsContext, cursor: &Transform, pulse: f32) {
+        let now = Instant::now();
+        let spaced = self.copies.front().map_or(true, |&(left_at, _)| {
+            (now - left_at).as_secs_f32() >= SPACING_SECONDS
+        });
+        if spaced {
+            let key = self.batch.insert(*cursor);
+            self.copies.push_front((now, key));
+        }
+        while let Some(&(left_at, key)) = self.copies.back() {
+            if (now - left_at).as_secs_f32() < FADE_SECONDS {
+                break;
+            }
+            self.batch.remove(key);
+            self.copies.pop_back();
+        }
+
+        let alpha = ALPHA + PULSE_ALPHA * pulse;
+        for &(left_at, key) in &self.copies {
+            let age = (now - left_at).as_secs_f32();
+            if let Some(transform) = self.batch.get_mut(key) {
+                transform.scale = cursor.scale;
+                transform.color.w = alpha * math::clamped_remap(0.0, FADE_SECONDS, 1.0, 0.0, age);
+            }
+        }
+        self.batch.update(gfx);
+    }
+}
+
+impl Renderable for CursorTrail {
+    fn render<'data>(&'data self, rctx: &RenderContext<'data>, pass: &mut wgpu::RenderPass<'data>) {
+        self.batch.render(rctx, pass);
+    }
+}
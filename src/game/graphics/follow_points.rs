@@ -0,0 +1,149 @@
+use cgmath::InnerSpace;
+use ogfx::{
+    spritebatch::SpriteIdx, GraphicsContext, RenderContext, Renderable, SpriteBatch, Transform,
+};
+
+use crate::{
+    game::chart::{ChartData, OSU_PIXEL_SCALE},
+    math,
+};
+
+use super::atlas::Atlas;
+
+/// Distance between two follow points in osu!pixels.
+const SPACING: f32 = 32.0;
+/// How long before reaching its end position a follow point appears.
+const PREEMPT_SECONDS: f32 = 0.8;
+const FADE_IN_SECONDS: f32 = 0.4;
+const FADE_OUT_SECONDS: f32 = 0.4;
+
+struct FollowPoint {
+    start: cgmath::Vector2<f32>,
+    end: cgmath::Vector2<f32>,
+    rotation: cgmath::Rad<f32>,
+    /// When the point has reached `end` and starts fading out.
+    time: f32,
+}
+
+impl FollowPoint {
+    fn appear_time(&self) -> f32 {
+        self.time - PREEMPT_SECONDS
+    }
+
+    fn disappear_time(&self) -> f32 {
+        self.time + FADE_OUT_SECONDS
+    }
+}
+
+/// Trail of points leading from the end of an object to the next one in the same combo.
+pub struct FollowPoints {
+    batch: SpriteBatch,
+    base_transform: Transform,
+
+    points: Vec<FollowPoint>,
+    pending_start: usize,
+    active: Vec<(usize, SpriteIdx)>,
+}
+
+impl FollowPoints {
+    pub fn new(
+        gfx: &GraphicsContext,
+        chart_data: &ChartData,
+        atlas: &Atlas<String>,
+        entry: &str,
+    ) -> Self {
+        let spacing = SPACING * OSU_PIXEL_SCALE;
+        let mut points = Vec::new();
+        for pair in chart_data.objects.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            if next.new_combo {
+                continue;
+            }
+            let start = prev.end_position();
+            let vector = next.position - start;
+            let distance = vector.magnitude();
+            let duration = next.time - prev.end_time();
+            let rotation = cgmath::Rad(vector.y.atan2(vector.x));
+
+            let mut d = spacing * 1.5;
+            while d < distance - spacing {
+                let fraction = d / distance;
+                points.push(FollowPoint {
+                    start: start + vector * (fraction - 0.1),
+                    end: start + vector * fraction,
+                    rotation,
+                    time: prev.end_time() + duration * fraction,
+                });
+                d += spacing;
+            }
+        }
+        points.sort_by(|a, b| a.appear_time().partial_cmp(&b.appear_time()).unwrap());
+
+        let size = atlas.logical_size(entry) * OSU_PIXEL_SCALE;
+        let base_transform = Transform {
+            scale: atlas.scale_for(size),
+            source: atlas.sub_textures[entry].cast(),
+            ..Default::default()
+        };
+
+        FollowPoints {
+            batch: SpriteBatch::new(gfx, atlas.texture.clone(), 512),
+            base_transform,
+            points,
+            pending_start: 0,
+            active: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, gfx: &GraphicsContext, time: f32) {
+        while let Some(point) = self.points.get(self.pending_start) {
+            if point.appear_time() > time {
+                break;
+            }
+            if point.disappear_time() > time {
+                let key = self.batch.insert(Transform {
+                    position: point.start,
+                    rotation: point.rotation,
+                    ..self.base_transform
+                });
+                self.active.push((self.pending_start, key));
+            }
+            self.pending_start += 1;
+        }
+
+        let FollowPoints {
+            batch,
+            base_transform,
+            points,
+            active,
+            ..
+        } = self;
+        active.retain(|&(idx, key)| {
+            let point = &points[idx];
+            if time > point.disappear_time() {
+                batch.remove(key);
+                return false;
+            }
+            let fade_in = math::clamped_remap(
+                point.appear_time(),
+                point.appear_time() + FADE_IN_SECONDS,
+                0.0,
+                1.0,
+                time,
+            );
+            let fade_out = math::clamped_remap(point.time, point.disappear_time(), 1.0, 0.0, time);
+            let transform = batch.get_mut(key).unwrap();
+            transform.position = point.start + (point.end - point.start) * fade_in;
+            transform.scale = base_transform.scale * math::lerp(1.5, 1.0, fade_in);
+            transform.color.w = fade_in.min(fade_out);
+            true
+        });
+        self.batch.update(gfx);
+    }
+}
+
+impl Renderable for FollowPoints {
+    fn render<'data>(&'data self, rctx: &RenderContext<'data>, pass: &mut wgpu::RenderPass<'data>) {
+        self.batch.render(rctx, pass);
+    }
+}
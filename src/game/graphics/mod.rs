@@ -1,2 +1,5 @@
 pub mod atlas;
+pub mod cursor_trail;
+pub mod follow_points;
 pub mod slider;
+pub mod storyboard;
@@ -1,3 +1,4 @@
+use crevice::std140::{AsStd140, Std140};
 use ogfx::{GraphicsContext, RenderContext, Renderable};
 
 use crate::{game::chart, math};
@@ -8,8 +9,8 @@ pub struct Slider {
     track: ogfx::ArcTexture,
     vertex: ogfx::Buffer,
     index: ogfx::Buffer,
-    // Temp
-    pub instance: ogfx::Buffer,
+    transform: ogfx::Transform,
+    instance: ogfx::Buffer,
     #[allow(dead_code)]
     view: ogfx::Buffer,
     view_binding: wgpu::BindGroup,
@@ -21,15 +22,12 @@ impl Slider {
         curve_type: chart::CurveType,
         initial_position: cgmath::Vector2<f32>,
         control_points: &[cgmath::Vector2<f32>],
+        width: f32,
         atlas: &Atlas<String>,
         entry: &str,
     ) -> Self {
         let spline = osu_utils::Spline::from_control(
-            match curve_type {
-                chart::CurveType::Perfect => osu_types::CurveType::Perfect,
-                chart::CurveType::Bezier => osu_types::CurveType::Bezier,
-                chart::CurveType::Linear => osu_types::CurveType::Linear,
-            },
+            curve_type.into(),
             std::iter::once(initial_position)
                 .chain(control_points.iter().copied())
                 .map(|p| osu_types::osu_point(p.x as _, p.y as _))
@@ -61,7 +59,7 @@ impl Slider {
                 .tessellate_path(
                     &path,
                     &lyon::lyon_tessellation::StrokeOptions::default()
-                        .with_line_width(60.0)
+                        .with_line_width(width)
                         .with_start_cap(lyon::lyon_tessellation::LineCap::Round)
                         .with_end_cap(lyon::lyon_tessellation::LineCap::Round),
                     &mut lyon::lyon_tessellation::BuffersBuilder::new(
@@ -86,9 +84,10 @@ impl Slider {
         let index_buffer =
             ogfx::Buffer::new_with_data(gfx, &geometry.indices, wgpu::BufferUsages::INDEX);
 
+        let transform = ogfx::Transform::default();
         let instance_buffer = ogfx::Buffer::new_with_alignable_data(
             gfx,
-            &[ogfx::Transform::default().as_matrix()],
+            &[transform.as_matrix()],
             wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         );
 
@@ -114,6 +113,7 @@ impl Slider {
             track: atlas.texture.clone(),
             vertex: vertex_buffer,
             index: index_buffer,
+            transform,
             instance: instance_buffer,
             view: view_buffer,
             view_binding,
@@ -121,6 +121,21 @@ impl Slider {
     }
 }
 
+impl Slider {
+    pub fn set_color(&mut self, gfx: &GraphicsContext, color: cgmath::Vector4<f32>) {
+        self.transform.color = color;
+        gfx.queue.write_buffer(
+            &self.instance.buffer,
+            0,
+            self.transform.as_matrix().as_std140().as_bytes(),
+        );
+    }
+
+    pub fn color(&self) -> cgmath::Vector4<f32> {
+        self.transform.color
+    }
+}
+
 impl Renderable for Slider {
     fn render<'data>(
         &'data self,
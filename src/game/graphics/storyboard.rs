@@ -0,0 +1,389 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use atlas_packer::PackSolver;
+use cgmath::{ElementWise, Rotation, Rotation2};
+use ogfx::{
+    pipeline::ADDITIVE_BLENDING, spritebatch::SpriteIdx, texture::RawTextureData, GraphicsContext,
+    Pipeline, RenderContext, Renderable, Shader, SpriteBatch, Transform,
+};
+
+use crate::game::{
+    chart::OSU_PIXEL_SCALE,
+    hitsound::Sample,
+    storyboard::{self, ElementState, Layer, Origin, Storyboard, STORYBOARD_SIZE},
+};
+
+use super::atlas::Atlas;
+
+/// Pixels of images packed in one atlas before the next images go to a new one.
+const MAX_ATLAS_AREA: u32 = 4096 * 4096;
+
+/// Loads every image of `storyboard` from the beatmap `directory`, keyed by their path.
+pub fn load_textures(storyboard: &Storyboard, directory: &Path) -> Vec<(String, RawTextureData)> {
+    let mut paths = storyboard
+        .elements
+        .iter()
+        .flat_map(|element| element.frame_paths())
+        .collect::<Vec<_>>();
+    // Keep the first appearance of every image so images shown together end up in the same atlas
+    let mut seen = std::collections::HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let file = directory.join(&path);
+            let data = std::fs::read(&file)
+                .map_err(|e| {
+                    println!(
+                        "Failed to read storyboard image '{}': {}",
+                        file.display(),
+                        e
+                    )
+                })
+                .ok()?;
+            let texture = RawTextureData::try_from_raw_image(&data)
+                .map_err(|e| {
+                    println!(
+                        "Failed to load storyboard image '{}': {}",
+                        file.display(),
+                        e
+                    )
+                })
+                .ok()?;
+            Some((path, texture))
+        })
+        .collect()
+}
+
+/// Whether the images fit in one atlas of at most `max_size` pixels a side once packed.
+fn fits(textures: &[(String, RawTextureData)], max_size: u32) -> bool {
+    let rects = textures
+        .iter()
+        .map(|(_, texture)| texture.size.into())
+        .collect::<Vec<_>>();
+    let dimensions = PackSolver::new(&rects).solve().dimensions;
+    dimensions.x <= max_size && dimensions.y <= max_size
+}
+
+/// Sprite showing one storyboard element.
+struct ElementSprite {
+    atlas: usize,
+    layer: Layer,
+    sprite: SpriteIdx,
+    /// Sprite in the additive batch, for elements drawn with additive blending at some point.
+    additive_sprite: Option<SpriteIdx>,
+    /// Atlas entry of every animation frame, the first one for frames that failed to load.
+    frames: Vec<String>,
+}
+
+/// Draws a storyboard with one sprite batch per layer, atlas and blending, keeping the order of
+/// the elements within each batch.
+///
+/// Additive elements are drawn after the other elements of their atlas.
+pub struct StoryboardRenderer {
+    storyboard: Storyboard,
+    atlases: Vec<Atlas<String>>,
+    additive_pipeline: Pipeline,
+    /// Batches keyed by layer, atlas and whether they're drawn with additive blending.
+    batches: BTreeMap<(Layer, usize, bool), SpriteBatch>,
+    /// Sprite of each element, `None` if its image couldn't be loaded.
+    sprites: Vec<Option<ElementSprite>>,
+    /// Whether the sprite of each element shown last update was the additive one, `None` if
+    /// the element was hidden.
+    shown: Vec<Option<bool>>,
+    /// Time each trigger group of each element was last started at.
+    triggered: Vec<Vec<Option<f32>>>,
+    passing: bool,
+}
+
+impl StoryboardRenderer {
+    pub fn new(
+        gfx: &GraphicsContext,
+        storyboard: Storyboard,
+        textures: Vec<(String, RawTextureData)>,
+    ) -> Self {
+        let max_size = gfx.device.limits().max_texture_dimension_2d;
+        let mut groups: Vec<Vec<(String, RawTextureData)>> = Vec::new();
+        let mut area = 0;
+        for (path, texture) in textures {
+            if texture.size.x > max_size || texture.size.y > max_size {
+                println!(
+                    "Storyboard image '{}' is larger than the {} pixels supported",
+                    path, max_size
+                );
+                continue;
+            }
+            let texture_area = texture.size.x * texture.size.y;
+            if groups.is_empty() || area + texture_area > MAX_ATLAS_AREA {
+                groups.push(Vec::new());
+                area = 0;
+            }
+            area += texture_area;
+            groups.last_mut().unwrap().push((path, texture));
+        }
+        // Packing can leave gaps, halve the groups until each fits in one texture
+        while let Some(index) = groups
+            .iter()
+            .position(|group| group.len() > 1 && !fits(group, max_size))
+        {
+            let half = groups[index].len() / 2;
+            let second = groups[index].split_off(half);
+            groups.insert(index + 1, second);
+        }
+        let atlases = groups
+            .iter()
+            .map(|group| {
+                Atlas::new(
+                    gfx,
+                    &group
+                        .iter()
+                        .map(|(path, texture)| (path, texture))
+                        .collect::<Vec<_>>(),
+                    wgpu::TextureFormat::Rgba8Unorm,
+                )
+            })
+            .collect::<Vec<_>>();
+        let atlas_of = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(atlas, group)| group.iter().map(move |(path, _)| (path.clone(), atlas)))
+            .collect::<HashMap<_, _>>();
+
+        let placements = storyboard
+            .elements
+            .iter()
+            .map(|element| {
+                let paths = element.frame_paths();
+                let atlas = *atlas_of.get(&paths[0])?;
+                let frames = paths
+                    .iter()
+                    .map(|path| {
+                        if atlas_of.get(path) == Some(&atlas) {
+                            path.clone()
+                        } else {
+                            paths[0].clone()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Some((element.layer, atlas, frames, element.has_additive()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut capacities = BTreeMap::new();
+        for (layer, atlas, _, additive) in placements.iter().flatten() {
+            *capacities.entry((*layer, *atlas, false)).or_insert(0) += 1;
+            if *additive {
+                *capacities.entry((*layer, *atlas, true)).or_insert(0) += 1;
+            }
+        }
+        let mut batches = capacities
+            .into_iter()
+            .map(|(key, capacity)| {
+                (
+                    key,
+                    SpriteBatch::new(gfx, atlases[key.1].texture.clone(), capacity),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        // Sprites are inserted in the order of the elements and never removed,
+        // so every batch draws them in that order
+        let hidden = Transform {
+            color: cgmath::vec4(1.0, 1.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        let sprites = placements
+            .into_iter()
+            .map(|placement| {
+                placement.map(|(layer, atlas, frames, additive)| ElementSprite {
+                    atlas,
+                    layer,
+                    sprite: batches
+                        .get_mut(&(layer, atlas, false))
+                        .unwrap()
+                        .insert(hidden),
+                    additive_sprite: additive.then(|| {
+                        batches
+                            .get_mut(&(layer, atlas, true))
+                            .unwrap()
+                            .insert(hidden)
+                    }),
+                    frames,
+                })
+            })
+            .collect::<Vec<_>>();
+        let shown = vec![None; sprites.len()];
+
+        let shader = Shader::new(
+            gfx,
+            include_str!("../../../ogfx/src/shaders/shader.wgsl"),
+            "vs_main",
+            "fs_main",
+        );
+        let additive_pipeline = Pipeline::with_blend(gfx, &shader, ADDITIVE_BLENDING);
+
+        let triggered = storyboard
+            .elements
+            .iter()
+            .map(|element| vec![None; element.triggers.len()])
+            .collect();
+
+        StoryboardRenderer {
+            storyboard,
+            atlases,
+            additive_pipeline,
+            batches,
+            sprites,
+            shown,
+            triggered,
+            passing: true,
+        }
+    }
+
+    /// Starts the trigger groups running at `time` whose name satisfies `matches`.
+    fn trigger(&mut self, time: f32, matches: impl Fn(&str) -> bool) {
+        for (element, triggered) in self.storyboard.elements.iter().zip(&mut self.triggered) {
+            for (group, triggered) in element.triggers.iter().zip(triggered) {
+                if time >= group.start && time <= group.end && matches(&group.name) {
+                    *triggered = Some(time);
+                }
+            }
+        }
+    }
+
+    /// Starts the trigger groups listening to a hit playing `samples` at `time`.
+    pub fn trigger_hitsound(&mut self, samples: &[Sample], time: f32) {
+        self.trigger(time, |name| {
+            storyboard::hitsound_trigger_matches(name, samples)
+        });
+    }
+
+    fn transform(&self, sprite: &ElementSprite, origin: Origin, state: &ElementState) -> Transform {
+        let atlas = &self.atlases[sprite.atlas];
+        let key = &sprite.frames[state.frame.min(sprite.frames.len() - 1)];
+        let size = atlas.pixel_size(key) * OSU_PIXEL_SCALE;
+        let scaled = size.mul_element_wise(state.scale);
+        let rotation = cgmath::Rad(state.rotation);
+        // The origin stays in place when flipped, only the image is mirrored
+        let origin = cgmath::Basis2::from_angle(rotation).rotate_vector(
+            size.mul_element_wise(state.scale.map(f32::abs))
+                .mul_element_wise(origin.offset()),
+        );
+        Transform {
+            position: (state.position - STORYBOARD_SIZE / 2.0) * OSU_PIXEL_SCALE - origin,
+            scale: atlas.scale_for(scaled),
+            rotation,
+            source: atlas.sub_textures[key].cast(),
+            color: state.colour.extend(state.alpha),
+            ..Default::default()
+        }
+    }
+
+    /// Moves every element to where it is at `time`, with the Pass or Fail layer shown
+    /// depending on whether the player is `passing`.
+    pub fn update(&mut self, gfx: &GraphicsContext, time: f32, passing: bool) {
+        if passing != self.passing {
+            self.passing = passing;
+            self.trigger(time, |name| {
+                name == if passing { "Passing" } else { "Failing" }
+            });
+        }
+
+        for (index, sprite) in self.sprites.iter().enumerate() {
+            let sprite = match sprite {
+                Some(sprite) => sprite,
+                None => continue,
+            };
+            let element = &self.storyboard.elements[index];
+            let state = element.state(time, &self.triggered[index]);
+            let additive = state.map(|state| state.additive && sprite.additive_sprite.is_some());
+            // Elements staying hidden leave their batches untouched
+            if additive.is_none() && self.shown[index].is_none() {
+                continue;
+            }
+            let key = |additive: bool| {
+                let sprite_idx = match additive {
+                    true => sprite.additive_sprite.unwrap(),
+                    false => sprite.sprite,
+                };
+                ((sprite.layer, sprite.atlas, additive), sprite_idx)
+            };
+
+            if let Some(previous) = self.shown[index].filter(|&previous| Some(previous) != additive)
+            {
+                let (batch, sprite_idx) = key(previous);
+                if let Some(batch) = self.batches.get_mut(&batch) {
+                    batch.set_sprite_color(sprite_idx, cgmath::vec4(1.0, 1.0, 1.0, 0.0));
+                }
+            }
+            if let (Some(state), Some(additive)) = (state, additive) {
+                let transform = self.transform(sprite, element.origin, &state);
+                let (batch, sprite_idx) = key(additive);
+                if let Some(current) = self
+                    .batches
+                    .get_mut(&batch)
+                    .and_then(|batch| batch.get_mut(sprite_idx))
+                {
+                    *current = transform;
+                }
+            }
+            self.shown[index] = additive;
+        }
+
+        // Only batches with changed sprites are uploaded again
+        for batch in self.batches.values_mut() {
+            batch.update(gfx);
+        }
+    }
+
+    fn render_layer<'data>(
+        &'data self,
+        layer: Layer,
+        rctx: &RenderContext<'data>,
+        pass: &mut wgpu::RenderPass<'data>,
+    ) {
+        for (&(_, _, additive), batch) in self
+            .batches
+            .range((layer, 0, false)..=(layer, usize::MAX, true))
+        {
+            if additive {
+                rctx.with_pipeline(&self.additive_pipeline.pipeline, pass, |pass| {
+                    batch.render(rctx, pass)
+                });
+            } else {
+                batch.render(rctx, pass);
+            }
+        }
+    }
+
+    /// Draws the layers below the hit objects, in playfield units.
+    pub fn render_background<'data>(
+        &'data self,
+        rctx: &RenderContext<'data>,
+        pass: &mut wgpu::RenderPass<'data>,
+    ) {
+        self.render_layer(Layer::Background, rctx, pass);
+        self.render_layer(
+            if self.passing {
+                Layer::Pass
+            } else {
+                Layer::Fail
+            },
+            rctx,
+            pass,
+        );
+        self.render_layer(Layer::Foreground, rctx, pass);
+    }
+
+    /// Draws the layer over the hit objects, in playfield units.
+    pub fn render_overlay<'data>(
+        &'data self,
+        rctx: &RenderContext<'data>,
+        pass: &mut wgpu::RenderPass<'data>,
+    ) {
+        self.render_layer(Layer::Overlay, rctx, pass);
+    }
+}
@@ -0,0 +1,64 @@
+use super::judgement::Judgement;
+
+/// Health drained per second at drain rate 10.
+const MAX_DRAIN_PER_SECOND: f32 = 0.1;
+/// Health above which the player is passing.
+const PASSING_HEALTH: f32 = 0.5;
+
+/// Health of the player, from 0 to 1. Drains over time and gets restored by hitting objects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Health(f32);
+
+impl Default for Health {
+    fn default() -> Self {
+        Health(1.0)
+    }
+}
+
+impl Health {
+    pub fn value(&self) -> f32 {
+        self.0
+    }
+
+    pub fn is_passing(&self) -> bool {
+        self.0 >= PASSING_HEALTH
+    }
+
+    fn change(&mut self, amount: f32) {
+        self.0 = (self.0 + amount).clamp(0.0, 1.0);
+    }
+
+    /// Drains health for `seconds` of play at `drain_rate`.
+    pub fn drain(&mut self, drain_rate: f32, seconds: f32) {
+        self.change(-MAX_DRAIN_PER_SECOND * drain_rate / 10.0 * seconds);
+    }
+
+    /// Restores health for a hit, or takes it away for a miss, scaled by `drain_rate`.
+    pub fn judge(&mut self, judgement: Judgement, drain_rate: f32) {
+        let amount = match judgement {
+            Judgement::Great => 0.1,
+            Judgement::Good => 0.05,
+            Judgement::Meh => 0.01,
+            Judgement::Miss => -0.05 - 0.01 * drain_rate,
+        };
+        self.change(amount);
+    }
+}
+
+#[test]
+fn test_health() {
+    let mut health = Health::default();
+    health.drain(5.0, 2.0);
+    assert!((health.value() - 0.9).abs() < 1e-6);
+    health.judge(Judgement::Great, 5.0);
+    assert_eq!(health.value(), 1.0);
+
+    for _ in 0..6 {
+        health.judge(Judgement::Miss, 5.0);
+    }
+    assert!(!health.is_passing());
+    for _ in 0..10 {
+        health.judge(Judgement::Miss, 5.0);
+    }
+    assert_eq!(health.value(), 0.0);
+}
@@ -0,0 +1,497 @@
+//! Hitsound data of a chart. The beatmap parser doesn't keep sample sets, additions or slider
+//! edge sounds, so they are read from the raw `.osu` file.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::chart;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleSet {
+    Normal,
+    Soft,
+    Drum,
+}
+
+impl SampleSet {
+    /// Parses a sample set number, `None` for 0 which means it's inherited.
+    pub fn from_osu(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(SampleSet::Normal),
+            2 => Some(SampleSet::Soft),
+            3 => Some(SampleSet::Drum),
+            _ => None,
+        }
+    }
+
+    /// Sample set number of `set`, 0 if it's inherited.
+    pub fn to_osu(set: Option<Self>) -> u32 {
+        match set {
+            None => 0,
+            Some(SampleSet::Normal) => 1,
+            Some(SampleSet::Soft) => 2,
+            Some(SampleSet::Drum) => 3,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Normal" => Some(SampleSet::Normal),
+            "Soft" => Some(SampleSet::Soft),
+            "Drum" => Some(SampleSet::Drum),
+            _ => None,
+        }
+    }
+
+    /// Prefix of the sample files of this set.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleSet::Normal => "normal",
+            SampleSet::Soft => "soft",
+            SampleSet::Drum => "drum",
+        }
+    }
+}
+
+pub const WHISTLE: u8 = 1 << 1;
+pub const FINISH: u8 = 1 << 2;
+pub const CLAP: u8 = 1 << 3;
+
+const ADDITIONS: [(u8, &str); 3] = [
+    (WHISTLE, "hitwhistle"),
+    (FINISH, "hitfinish"),
+    (CLAP, "hitclap"),
+];
+
+/// Samples of a hit object or slider edge.
+/// Unset values are taken from the sample point active when it's played.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HitSamples {
+    /// Bit set of [`WHISTLE`], [`FINISH`] and [`CLAP`].
+    pub additions: u8,
+    pub normal_set: Option<SampleSet>,
+    pub addition_set: Option<SampleSet>,
+    /// Custom sample index, 0 to use the sample point's.
+    pub index: u32,
+    /// Volume between 0 and 1, 0 to use the sample point's.
+    pub volume: f32,
+    /// File in the beatmap directory replacing the normal sample.
+    pub file_name: Option<String>,
+}
+
+impl HitSamples {
+    /// Parses the `normalSet:additionSet:index:volume:filename` field of a hit object.
+    fn parse(additions: u8, hit_sample: Option<&str>) -> Self {
+        let mut fields = hit_sample.unwrap_or("").splitn(5, ':');
+        let mut next = || {
+            fields
+                .next()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(0)
+        };
+        let normal_set = SampleSet::from_osu(next());
+        let addition_set = SampleSet::from_osu(next());
+        let index = next();
+        let volume = next() as f32 / 100.0;
+        let file_name = fields
+            .next()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(normalize_path);
+        HitSamples {
+            additions,
+            normal_set,
+            addition_set,
+            index,
+            volume,
+            file_name,
+        }
+    }
+
+    /// Writes the `normalSet:additionSet:index:volume:filename` field of a hit object.
+    pub fn to_osu(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            SampleSet::to_osu(self.normal_set),
+            SampleSet::to_osu(self.addition_set),
+            self.index,
+            (self.volume * 100.0).round() as u32,
+            self.file_name.as_deref().unwrap_or("")
+        )
+    }
+}
+
+/// Sample set, index and volume of the hitsounds from a timing point onwards.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SamplePoint {
+    pub time: f32,
+    pub sample_set: SampleSet,
+    pub index: u32,
+    pub volume: f32,
+}
+
+/// Sample to play, with everything inherited resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// File name of the sample without the index, like `soft-hitclap`.
+    pub name: String,
+    pub index: u32,
+    pub volume: f32,
+}
+
+impl Sample {
+    /// Name of the file replacing this sample in the beatmap directory, `None` if only the
+    /// skin's sample should be used.
+    pub fn beatmap_name(&self) -> Option<String> {
+        match self.index {
+            0 => None,
+            1 => Some(self.name.clone()),
+            index => Some(format!("{}{}", self.name, index)),
+        }
+    }
+}
+
+/// Sample triggered by the storyboard.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoryboardSample {
+    pub time: f32,
+    /// Path of the file relative to the beatmap directory.
+    pub path: String,
+    pub volume: f32,
+}
+
+/// Turns a quoted path from an osu! file into a path relative to the beatmap directory.
+pub fn normalize_path(path: &str) -> String {
+    path.trim_matches('"').replace('\\', "/")
+}
+
+/// Reads the `Sample` events from the `[Events]` of a `.osu` or `.osb` file, in order.
+pub fn parse_storyboard_samples(content: &str) -> Vec<StoryboardSample> {
+    let mut samples = chart::osu_section_lines(content, "Events")
+        .filter_map(|line| {
+            // Sample,time,layer,"path",volume
+            let fields = line.split(',').map(|v| v.trim()).collect::<Vec<_>>();
+            if !matches!(fields.get(0), Some(&"Sample" | &"5")) {
+                return None;
+            }
+            Some(StoryboardSample {
+                time: fields.get(1)?.parse::<f32>().ok()? / 1000.0,
+                path: normalize_path(fields.get(3)?),
+                volume: fields
+                    .get(4)
+                    .and_then(|v| v.parse::<f32>().ok())
+                    .unwrap_or(100.0)
+                    / 100.0,
+            })
+        })
+        .collect::<Vec<_>>();
+    samples.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+    samples
+}
+
+/// How far ahead of an object a sample point may start and still apply to it.
+const SAMPLE_POINT_LENIENCY: f32 = 0.005;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Hitsounds {
+    pub sample_points: Vec<SamplePoint>,
+}
+
+impl Hitsounds {
+    /// Reads the sample points from the `[TimingPoints]` of a `.osu` file.
+    pub fn parse(content: &str) -> Self {
+        let default_set = chart::osu_section_lines(content, "General")
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == "SampleSet")
+            .and_then(|(_, value)| SampleSet::from_name(value.trim()))
+            .unwrap_or(SampleSet::Normal);
+        let sample_points = chart::osu_section_lines(content, "TimingPoints")
+            .filter_map(|line| {
+                let fields = line.split(',').map(|v| v.trim()).collect::<Vec<_>>();
+                let time = fields.get(0)?.parse::<f32>().ok()?;
+                let field = |idx: usize| fields.get(idx).and_then(|v| v.parse::<u32>().ok());
+                Some(SamplePoint {
+                    time: time / 1000.0,
+                    sample_set: field(3)
+                        .and_then(SampleSet::from_osu)
+                        .unwrap_or(default_set),
+                    index: field(4).unwrap_or(0),
+                    volume: field(5).unwrap_or(100) as f32 / 100.0,
+                })
+            })
+            .collect();
+        Hitsounds { sample_points }
+    }
+
+    /// Returns the sample point active at `time`.
+    pub fn sample_point(&self, time: f32) -> SamplePoint {
+        self.sample_points
+            .iter()
+            .take_while(|point| point.time <= time + SAMPLE_POINT_LENIENCY)
+            .last()
+            .or_else(|| self.sample_points.first())
+            .copied()
+            .unwrap_or(SamplePoint {
+                time: 0.0,
+                sample_set: SampleSet::Normal,
+                index: 0,
+                volume: 1.0,
+            })
+    }
+
+    fn resolve_common(&self, samples: &HitSamples, time: f32) -> (SampleSet, u32, f32) {
+        let point = self.sample_point(time);
+        (
+            samples.normal_set.unwrap_or(point.sample_set),
+            if samples.index != 0 {
+                samples.index
+            } else {
+                point.index
+            },
+            if samples.volume != 0.0 {
+                samples.volume
+            } else {
+                point.volume
+            },
+        )
+    }
+
+    /// Returns the samples played when `samples` is hit at `time`.
+    /// A custom sample file replaces the hit normal and every addition.
+    pub fn resolve(&self, samples: &HitSamples, time: f32) -> Vec<Sample> {
+        let (normal_set, index, volume) = self.resolve_common(samples, time);
+        if let Some(file_name) = &samples.file_name {
+            return vec![Sample {
+                name: file_name.clone(),
+                index: 1,
+                volume,
+            }];
+        }
+        let addition_set = samples.addition_set.unwrap_or(normal_set);
+        let normal = Sample {
+            name: format!("{}-hitnormal", normal_set.name()),
+            index,
+            volume,
+        };
+        std::iter::once(normal)
+            .chain(
+                ADDITIONS
+                    .iter()
+                    .filter(|(flag, _)| samples.additions & flag != 0)
+                    .map(|(_, name)| Sample {
+                        name: format!("{}-{}", addition_set.name(), name),
+                        index,
+                        volume,
+                    }),
+            )
+            .collect()
+    }
+
+    /// Returns the sample played by a slider tick at `time`, `samples` being the ones of the slider body.
+    pub fn resolve_tick(&self, samples: &HitSamples, time: f32) -> Sample {
+        let (normal_set, index, volume) = self.resolve_common(samples, time);
+        Sample {
+            name: format!("{}-slidertick", normal_set.name()),
+            index,
+            volume,
+        }
+    }
+}
+
+/// Samples of one line of the `[HitObjects]` section.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectSamples {
+    /// Samples of a circle, or of the body of a slider.
+    pub samples: HitSamples,
+    /// Samples of the head, repeats and tail of a slider, empty for other objects.
+    pub edges: Vec<HitSamples>,
+}
+
+impl ObjectSamples {
+    pub fn parse(line: &str) -> Self {
+        let fields = line.split(',').collect::<Vec<_>>();
+        let additions = fields
+            .get(4)
+            .and_then(|v| v.trim().parse::<u8>().ok())
+            .unwrap_or(0);
+        let object_type = fields
+            .get(3)
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .unwrap_or(0);
+        if object_type & (1 << 1) == 0 {
+            // Spinners have their end time before the samples
+            let hit_sample = if object_type & (1 << 3) != 0 { 6 } else { 5 };
+            return ObjectSamples {
+                samples: HitSamples::parse(additions, fields.get(hit_sample).copied()),
+                edges: Vec::new(),
+            };
+        }
+
+        // x,y,time,type,hitSound,curve,slides,length,edgeSounds,edgeSets,hitSample
+        let samples = HitSamples::parse(additions, fields.get(10).copied());
+        let slides = fields
+            .get(6)
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .unwrap_or(1);
+        let edge_sounds = fields
+            .get(8)
+            .map(|v| v.split('|').collect::<Vec<_>>())
+            .unwrap_or_default();
+        let edge_sets = fields
+            .get(9)
+            .map(|v| v.split('|').collect::<Vec<_>>())
+            .unwrap_or_default();
+        let edges = (0..=slides)
+            .map(|idx| {
+                let mut sets = edge_sets.get(idx).copied().unwrap_or("").split(':');
+                let mut next_set = || {
+                    sets.next()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .and_then(SampleSet::from_osu)
+                };
+                let normal_set = next_set();
+                let addition_set = next_set();
+                HitSamples {
+                    additions: edge_sounds
+                        .get(idx)
+                        .and_then(|v| v.parse::<u8>().ok())
+                        .unwrap_or(additions),
+                    normal_set: normal_set.or(samples.normal_set),
+                    addition_set: addition_set.or(samples.addition_set),
+                    ..samples.clone()
+                }
+            })
+            .collect();
+        ObjectSamples { samples, edges }
+    }
+
+    /// Reads the samples of every line of the `[HitObjects]` of a `.osu` file, keyed by the
+    /// position and time of the object, so they can be matched with the parsed objects whatever
+    /// lines the parser skips. Objects at the same spot and time are kept in the order of the file.
+    pub fn parse_all(content: &str) -> HashMap<(i32, i32, i32), VecDeque<ObjectSamples>> {
+        let mut objects = HashMap::<_, VecDeque<_>>::new();
+        for line in chart::osu_section_lines(content, "HitObjects") {
+            let mut fields = line
+                .split(',')
+                .map(|v| v.trim().parse::<f32>().ok().map(|v| v as i32));
+            if let (Some(Some(x)), Some(Some(y)), Some(Some(time))) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                objects
+                    .entry((x, y, time))
+                    .or_default()
+                    .push_back(ObjectSamples::parse(line));
+            }
+        }
+        objects
+    }
+}
+
+#[test]
+fn test_hitsounds() {
+    let content = include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu");
+    let hitsounds = Hitsounds::parse(content);
+    assert_eq!(
+        hitsounds.sample_points[2],
+        SamplePoint {
+            time: 15.835,
+            sample_set: SampleSet::Soft,
+            index: 12,
+            volume: 0.2,
+        }
+    );
+    assert_eq!(hitsounds.sample_point(16.0).volume, 0.2);
+    assert_eq!(hitsounds.sample_point(16.163).volume, 0.4);
+
+    let slider = ObjectSamples::parse(
+        "135,118,4068,6,0,P|162:81|242:72,1,113.999996520996,0|2,0:0|0:0,0:0:0:0:",
+    );
+    assert_eq!(slider.edges.len(), 2);
+    assert_eq!(slider.edges[0].additions, 0);
+    assert_eq!(slider.edges[1].additions, WHISTLE);
+    assert_eq!(
+        hitsounds.resolve(&slider.edges[1], 4.068),
+        vec![
+            Sample {
+                name: "soft-hitnormal".to_owned(),
+                index: 12,
+                volume: 0.4,
+            },
+            Sample {
+                name: "soft-hitwhistle".to_owned(),
+                index: 12,
+                volume: 0.4,
+            },
+        ]
+    );
+
+    let circle = ObjectSamples::parse("206,213,6056,1,10,3:1:2:70:");
+    assert!(circle.edges.is_empty());
+    assert_eq!(
+        hitsounds
+            .resolve(&circle.samples, 6.056)
+            .into_iter()
+            .map(|sample| (sample.name, sample.index, sample.volume))
+            .collect::<Vec<_>>(),
+        vec![
+            ("drum-hitnormal".to_owned(), 2, 0.7),
+            ("normal-hitwhistle".to_owned(), 2, 0.7),
+            ("normal-hitclap".to_owned(), 2, 0.7),
+        ]
+    );
+
+    // Lines that aren't objects don't shift the samples of the ones after them
+    let objects =
+        ObjectSamples::parse_all("[HitObjects]\nnot an object\n206,213,6056,1,10,3:1:2:70:\n");
+    assert_eq!(objects.len(), 1);
+    assert_eq!(objects[&(206, 213, 6056)], [circle]);
+}
+
+#[test]
+fn test_custom_samples() {
+    let hitsounds = Hitsounds {
+        sample_points: vec![SamplePoint {
+            time: 0.0,
+            sample_set: SampleSet::Normal,
+            index: 3,
+            volume: 0.5,
+        }],
+    };
+    let samples = hitsounds.resolve(&HitSamples::default(), 1.0);
+    assert_eq!(
+        samples[0].beatmap_name().as_deref(),
+        Some("normal-hitnormal3")
+    );
+
+    let circle = ObjectSamples::parse("256,192,1000,1,8,0:0:0:0:keys\\kick.wav");
+    let samples = hitsounds.resolve(&circle.samples, 1.0);
+    // Only the custom file plays, without the clap
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].beatmap_name().as_deref(), Some("keys/kick.wav"));
+
+    let storyboard = parse_storyboard_samples(
+        "[Events]
+//Storyboard Sound Samples
+Sample,2000,0,\"sfx\\boom.wav\",60
+Sample,500,0,\"intro.ogg\"
+[TimingPoints]
+Sample,0,0,\"not an event.wav\",100
+",
+    );
+    assert_eq!(
+        storyboard,
+        vec![
+            StoryboardSample {
+                time: 0.5,
+                path: "intro.ogg".to_owned(),
+                volume: 1.0,
+            },
+            StoryboardSample {
+                time: 2.0,
+                path: "sfx/boom.wav".to_owned(),
+                volume: 0.6,
+            },
+        ]
+    );
+}
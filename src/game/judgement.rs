@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Great,
+    Good,
+    Meh,
+    Miss,
+}
+
+impl Judgement {
+    pub fn score(&self) -> u32 {
+        match self {
+            Judgement::Great => 300,
+            Judgement::Good => 100,
+            Judgement::Meh => 50,
+            Judgement::Miss => 0,
+        }
+    }
+
+    /// Name of the texture shown when the judgement is made.
+    pub fn texture_name(&self) -> &'static str {
+        match self {
+            Judgement::Great => "hit300",
+            Judgement::Good => "hit100",
+            Judgement::Meh => "hit50",
+            Judgement::Miss => "hit0",
+        }
+    }
+}
+
+/// How many objects got each judgement in a play.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JudgementCounts {
+    pub great: u32,
+    pub good: u32,
+    pub meh: u32,
+    pub miss: u32,
+}
+
+impl JudgementCounts {
+    pub fn add(&mut self, judgement: Judgement) {
+        match judgement {
+            Judgement::Great => self.great += 1,
+            Judgement::Good => self.good += 1,
+            Judgement::Meh => self.meh += 1,
+            Judgement::Miss => self.miss += 1,
+        }
+    }
+
+    /// Amount of objects judged.
+    pub fn total(&self) -> u32 {
+        self.great + self.good + self.meh + self.miss
+    }
+
+    /// Accuracy from 0 to 1, the score of the judgements relative to all of them being great.
+    /// A play without any judgements is perfectly accurate.
+    pub fn accuracy(&self) -> f32 {
+        if self.total() == 0 {
+            return 1.0;
+        }
+        let score = self.great * Judgement::Great.score()
+            + self.good * Judgement::Good.score()
+            + self.meh * Judgement::Meh.score();
+        score as f32 / (self.total() * Judgement::Great.score()) as f32
+    }
+}
+
+/// Maximum offset in seconds from the object time allowed for each judgement.
+#[derive(Debug, Clone, Copy)]
+pub struct HitWindows {
+    pub great: f32,
+    pub good: f32,
+    pub meh: f32,
+}
+
+impl HitWindows {
+    pub fn from_od(od: f32) -> Self {
+        HitWindows {
+            great: (80.0 - 6.0 * od) / 1000.0,
+            good: (140.0 - 8.0 * od) / 1000.0,
+            meh: (200.0 - 10.0 * od) / 1000.0,
+        }
+    }
+
+    /// Judges a hit `offset` seconds away from the object time.
+    /// Returns `None` if the offset is outside of every window.
+    pub fn judge(&self, offset: f32) -> Option<Judgement> {
+        let offset = offset.abs();
+        if offset <= self.great {
+            Some(Judgement::Great)
+        } else if offset <= self.good {
+            Some(Judgement::Good)
+        } else if offset <= self.meh {
+            Some(Judgement::Meh)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_hit_windows() {
+    let windows = HitWindows::from_od(5.0);
+    assert!((windows.great - 0.050).abs() <= f32::EPSILON);
+    assert!((windows.good - 0.100).abs() <= f32::EPSILON);
+    assert!((windows.meh - 0.150).abs() <= f32::EPSILON);
+
+    assert_eq!(windows.judge(0.0), Some(Judgement::Great));
+    assert_eq!(windows.judge(-0.049), Some(Judgement::Great));
+    assert_eq!(windows.judge(0.075), Some(Judgement::Good));
+    assert_eq!(windows.judge(-0.149), Some(Judgement::Meh));
+    assert_eq!(windows.judge(0.2), None);
+}
+
+#[test]
+fn test_judgement_counts() {
+    let mut counts = JudgementCounts::default();
+    assert_eq!(counts.accuracy(), 1.0);
+    for judgement in [
+        Judgement::Great,
+        Judgement::Great,
+        Judgement::Good,
+        Judgement::Meh,
+        Judgement::Miss,
+    ] {
+        counts.add(judgement);
+    }
+    assert_eq!(counts.total(), 5);
+    assert_eq!(
+        (counts.great, counts.good, counts.meh, counts.miss),
+        (2, 1, 1, 1)
+    );
+    assert!((counts.accuracy() - 750.0 / 1500.0).abs() < 1e-6);
+}
@@ -1,18 +1,45 @@
 use self::{
+    audio::SampleBank,
+    beat::Beat,
     chart::{ChartData, ChartInfo},
+    clock::GameClock,
     graphics::atlas::Atlas,
+    health::Health,
+    judgement::{Judgement, JudgementCounts},
+    offset::Offsets,
+    profile::Profiles,
+    score::{self, Score, ScoreDatabase},
+    settings::{PlayerSettings, Settings},
+    skin::Skin,
 };
 use kira::{instance::handle::InstanceHandle, manager::AudioManager};
 use ogfx::{ArcTexture, GraphicsContext};
 use resources::{Resource, Resources};
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU8, Ordering},
     Arc, Mutex,
 };
 
+pub mod audio;
+pub mod beat;
 pub mod chart;
+pub mod chart_file;
+pub mod clock;
+pub mod difficulty;
 pub mod graphics;
+pub mod health;
+pub mod hitsound;
+pub mod judgement;
+pub mod mods;
+pub mod offset;
+pub mod osu_export;
+pub mod performance;
+pub mod profile;
+pub mod score;
 pub mod screen;
+pub mod settings;
+pub mod skin;
+pub mod storyboard;
 pub mod ui;
 
 #[macro_export]
@@ -25,23 +52,66 @@ macro_rules! llog {
 }
 
 pub struct GameResources {
+    pub skin: Skin,
     pub hitobject_atlas: Atlas<String>,
+    /// Digits used for combo numbers, keyed by their value.
+    pub number_atlas: Atlas<u8>,
     pub playfield: ArcTexture,
+    pub cursor: ArcTexture,
+    /// Hitsound samples of the skin.
+    pub samples: SampleBank,
 }
 
-struct Song(pub InstanceHandle);
+pub const DEFAULT_BACKGROUND_DIM: u8 = 60;
+/// Beatmap selected on the first start.
+pub const DEFAULT_BEATMAP: &str = "positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu";
 
 #[derive(Copy, Clone)]
 pub struct ChartProgress {
     pub pending_start: usize,
 
     pub combo: u32,
+    /// Score points so far, without the mod multiplier.
+    pub score: u64,
+    /// Highest combo reached so far.
+    pub max_combo: u32,
+    pub judgements: JudgementCounts,
     pub progress: f32,
+    pub health: Health,
+    /// Seconds left in the current break, `None` outside of breaks.
+    pub break_remaining: Option<f32>,
+    /// Song time skipping seeks to, `None` if there is nothing to skip.
+    pub skip_target: Option<f32>,
+    /// Strength of the kiai pulse, from 1 on a beat in kiai time to 0.
+    pub kiai_pulse: f32,
+    /// Beat of the chart at the current song time, for anything drawn along with the music.
+    pub beat: Option<Beat>,
+}
+
+impl ChartProgress {
+    /// Counts the judgement of an object, a hit adds to the combo and a miss breaks it.
+    pub fn judge(&mut self, judgement: Judgement) {
+        self.judgements.add(judgement);
+        self.score += score::hit_points(judgement, self.combo);
+        if judgement == Judgement::Miss {
+            self.combo = 0;
+        } else {
+            self.add_combo();
+        }
+    }
+
+    /// Adds to the combo without judging an object, for slider ticks, repeats and tails.
+    pub fn add_combo(&mut self) {
+        self.combo += 1;
+        self.max_combo = self.max_combo.max(self.combo);
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum LogLayer {
     Playfield,
+    /// Samples failing to play.
+    Audio,
 }
 
 pub struct GameContext {
@@ -51,26 +121,134 @@ pub struct GameContext {
     pub game_resources: Arc<Mutex<Option<GameResources>>>,
     active_log_layers: Mutex<Vec<LogLayer>>,
 
+    /// Play beatmaps with the active skin only, ignoring the elements they ship with.
+    pub ignore_beatmap_skins: AtomicBool,
+    /// How much the beatmap background is darkened, in percent.
+    pub background_dim: AtomicU8,
+    /// Universal and per beatmap audio offsets.
+    pub offsets: Mutex<Offsets>,
+    /// Settings of this machine, including the chart selected to play.
+    pub settings: Mutex<Settings>,
+    /// Whether the settings screen is open, the UI shows the settings instead of the HUD.
+    pub settings_open: AtomicBool,
+    /// Scores of every finished play.
+    pub scores: Mutex<ScoreDatabase>,
+    /// Profiles on this machine, the settings above follow the active one.
+    pub profiles: Mutex<Profiles>,
     pub dirty: AtomicBool,
 }
 
 impl GameContext {
-    pub fn new(gfx: GraphicsContext, audio: AudioManager) -> Self {
+    pub fn new(gfx: GraphicsContext, audio: AudioManager, settings: Settings) -> Self {
         let mut resources = Resources::new();
-        resources.insert::<Option<Song>>(None);
+        resources.insert::<Option<GameClock>>(None);
         resources.insert::<Option<ChartInfo>>(None);
         resources.insert::<Option<ChartData>>(None);
         resources.insert::<Option<ChartProgress>>(None);
+        resources.insert::<Option<Score>>(None);
+        let profiles = Profiles::load();
+        let profile = profiles.active();
+        println!("Playing as '{}'", profile.name);
+        let player_settings = &profile.settings;
         GameContext {
             resources,
             gfx: Arc::new(gfx),
             audio: Mutex::new(audio),
             game_resources: Arc::new(Mutex::new(None)),
             active_log_layers: Mutex::new(Vec::new()),
+            ignore_beatmap_skins: AtomicBool::new(player_settings.ignore_beatmap_skins),
+            background_dim: AtomicU8::new(player_settings.background_dim.min(100)),
+            offsets: Mutex::new(Offsets::load()),
+            settings: Mutex::new(settings),
+            settings_open: AtomicBool::new(false),
+            scores: Mutex::new(ScoreDatabase::load()),
+            profiles: Mutex::new(profiles),
             dirty: AtomicBool::new(true),
         }
     }
 
+    /// Switches to the profile `offset` places away and applies its settings.
+    pub fn switch_profile(&self, offset: isize) {
+        let mut profiles = self.profiles.lock().unwrap();
+        profiles.switch(offset);
+        profiles.save();
+        self.apply_profile(&profiles);
+    }
+
+    /// Adds a profile named `name` and switches to it, returns `false` if the name can't be used.
+    pub fn add_profile(&self, name: &str) -> bool {
+        let mut profiles = self.profiles.lock().unwrap();
+        if profiles.add(name).is_none() {
+            return false;
+        }
+        profiles.save();
+        self.apply_profile(&profiles);
+        true
+    }
+
+    fn apply_profile(&self, profiles: &Profiles) {
+        let profile = profiles.active();
+        println!("Playing as '{}'", profile.name);
+        self.apply_player_settings(&profile.settings);
+    }
+
+    fn apply_player_settings(&self, settings: &PlayerSettings) {
+        self.ignore_beatmap_skins
+            .store(settings.ignore_beatmap_skins, Ordering::Relaxed);
+        self.background_dim
+            .store(settings.background_dim.min(100), Ordering::Relaxed);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Changes the settings of this machine, saves them and applies what can be right away.
+    pub fn update_settings(&self, update: impl FnOnce(&mut Settings)) {
+        let mut settings = self.settings.lock().unwrap();
+        let present_mode = settings.present_mode();
+        update(&mut settings);
+        settings.save();
+        if settings.present_mode() != present_mode {
+            self.gfx.set_present_mode(settings.present_mode());
+        }
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Changes the settings of the active profile, saves them and applies what can be right
+    /// away. A new skin is loaded by the caller, see [`GameContext::skin_outdated`].
+    pub fn update_player_settings(&self, update: impl FnOnce(&mut PlayerSettings)) {
+        let mut profiles = self.profiles.lock().unwrap();
+        update(&mut profiles.active_mut().settings);
+        profiles.save();
+        self.apply_player_settings(&profiles.active().settings);
+    }
+
+    pub fn player_settings(&self) -> PlayerSettings {
+        self.profiles.lock().unwrap().active().settings.clone()
+    }
+
+    /// Whether the key named `key_name` hits objects for the active profile.
+    pub fn is_hit_key(&self, key_name: &str) -> bool {
+        self.profiles
+            .lock()
+            .unwrap()
+            .active()
+            .settings
+            .is_hit_key(key_name)
+    }
+
+    /// Whether the loaded skin isn't the one of the active profile, after switching profiles or
+    /// skins.
+    pub fn skin_outdated(&self) -> bool {
+        let profiles = self.profiles.lock().unwrap();
+        let skin = Skin::directory(profiles.active().settings.skin.as_deref());
+        self.game_resources
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(false, |resources| {
+                resources.skin.directory.as_deref() != skin
+            })
+    }
+
     pub fn enable_log_layer(&self, layer: LogLayer) {
         self.active_log_layers.lock().unwrap().push(layer);
     }
@@ -79,13 +257,39 @@ impl GameContext {
         self.active_log_layers.lock().unwrap().contains(&layer)
     }
 
+    /// Starts timing gameplay from `song`.
     pub fn set_song(&self, song: InstanceHandle) {
         self.dirty.store(true, Ordering::SeqCst);
-        *self.resources.get_mut::<Option<Song>>().unwrap() = Some(Song(song));
+        *self.resources.get_mut::<Option<GameClock>>().unwrap() = Some(GameClock::new(song));
+    }
+
+    /// Stops the song gameplay was timed from, before another one is started.
+    pub fn stop_song(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+        if let Some(clock) = self.clock_mut().take() {
+            clock.stop();
+        }
+    }
+
+    pub fn clock(&self) -> resources::Ref<Option<GameClock>> {
+        self.get_raw_opt::<GameClock>()
+    }
+
+    pub fn clock_mut(&self) -> resources::RefMut<Option<GameClock>> {
+        self.resources.get_mut::<Option<GameClock>>().unwrap()
     }
 
-    pub fn song(&self) -> Option<InstanceHandle> {
-        self.get_raw_opt::<Song>().as_ref().map(|s| s.0.clone())
+    /// Current song time in seconds, `None` if no song is playing.
+    pub fn song_time(&self) -> Option<f32> {
+        self.clock().as_ref().map(|clock| clock.time() as f32)
+    }
+
+    /// Skips the intro or break being played, if it is long enough.
+    pub fn skip(&self) {
+        let target = self.chart_progress().and_then(|p| p.skip_target);
+        if let (Some(target), Some(clock)) = (target, self.clock_mut().as_mut()) {
+            clock.seek(target as f64);
+        }
     }
 
     pub fn set_chart_info(&self, chart: ChartInfo) {
@@ -111,10 +315,26 @@ impl GameContext {
         *self.resources.get_mut::<Option<ChartProgress>>().unwrap() = Some(chart_progress);
     }
 
+    /// Clears the progress of the play when leaving it.
+    pub fn clear_chart_progress(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+        *self.resources.get_mut::<Option<ChartProgress>>().unwrap() = None;
+    }
+
     pub fn chart_progress(&self) -> Option<ChartProgress> {
         self.get_raw_opt::<ChartProgress>().as_ref().map(|&s| s)
     }
 
+    /// Sets the score of the finished play, `None` while playing.
+    pub fn set_score(&self, score: Option<Score>) {
+        self.dirty.store(true, Ordering::SeqCst);
+        *self.resources.get_mut::<Option<Score>>().unwrap() = score;
+    }
+
+    pub fn score(&self) -> resources::Ref<Option<Score>> {
+        self.get_raw_opt::<Score>()
+    }
+
     fn get_raw_opt<T: Resource>(&self) -> resources::Ref<Option<T>> {
         self.resources.get::<Option<T>>().unwrap()
     }
@@ -0,0 +1,131 @@
+//! Mods a play was made with, changing how much it is worth. Playing with mods isn't supported
+//! yet, so plays are made without any.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ModsError {
+    Unknown(String),
+    /// Easy and Hard Rock undo each other and can't be combined.
+    Incompatible,
+}
+
+impl std::fmt::Display for ModsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModsError::Unknown(acronym) => write!(f, "unknown mod '{}'", acronym),
+            ModsError::Incompatible => write!(f, "Easy and Hard Rock can't be combined"),
+        }
+    }
+}
+
+/// Stored as their osu! acronyms, like `HDHR`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Mods {
+    /// Larger circles, slower approach and wider hit windows.
+    pub easy: bool,
+    /// No approach circles, and objects fade out before they are hit.
+    pub hidden: bool,
+    /// Smaller circles, faster approach and tighter hit windows, with the playfield flipped.
+    pub hard_rock: bool,
+}
+
+impl Mods {
+    /// Parses mods from their osu! acronyms run together, like `HDHR`, or `NM` for none.
+    pub fn parse(acronyms: &str) -> Result<Self, ModsError> {
+        let mut mods = Mods::default();
+        let acronyms = acronyms.to_ascii_uppercase();
+        if acronyms == "NM" {
+            return Ok(mods);
+        }
+        for acronym in acronyms.as_bytes().chunks(2) {
+            match acronym {
+                b"EZ" => mods.easy = true,
+                b"HD" => mods.hidden = true,
+                b"HR" => mods.hard_rock = true,
+                _ => {
+                    return Err(ModsError::Unknown(
+                        String::from_utf8_lossy(acronym).into_owned(),
+                    ))
+                }
+            }
+        }
+        if mods.easy && mods.hard_rock {
+            return Err(ModsError::Incompatible);
+        }
+        Ok(mods)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Mods::default()
+    }
+
+    /// Factor the score points of a play with these mods are multiplied by.
+    pub fn score_multiplier(&self) -> f32 {
+        let mut multiplier = 1.0;
+        if self.easy {
+            multiplier *= 0.5;
+        }
+        if self.hidden {
+            multiplier *= 1.06;
+        }
+        if self.hard_rock {
+            multiplier *= 1.06;
+        }
+        multiplier
+    }
+}
+
+impl std::fmt::Display for Mods {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "NM");
+        }
+        for (enabled, acronym) in [
+            (self.easy, "EZ"),
+            (self.hidden, "HD"),
+            (self.hard_rock, "HR"),
+        ] {
+            if enabled {
+                write!(f, "{}", acronym)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Mods {
+    type Error = ModsError;
+
+    fn try_from(acronyms: String) -> Result<Self, Self::Error> {
+        Mods::parse(&acronyms)
+    }
+}
+
+impl From<Mods> for String {
+    fn from(mods: Mods) -> Self {
+        mods.to_string()
+    }
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(Mods::parse(""), Ok(Mods::default()));
+    assert_eq!(Mods::parse("NM"), Ok(Mods::default()));
+    let mods = Mods::parse("hdHR").unwrap();
+    assert!(mods.hidden && mods.hard_rock && !mods.easy);
+    assert_eq!(mods.to_string(), "HDHR");
+    assert_eq!(Mods::parse("EZHR"), Err(ModsError::Incompatible));
+    assert_eq!(
+        Mods::parse("XXEZ"),
+        Err(ModsError::Unknown("XX".to_owned()))
+    );
+    assert_eq!(Mods::default().score_multiplier(), 1.0);
+    assert!((mods.score_multiplier() - 1.1236).abs() < 1e-6);
+
+    let saved = ron::ser::to_string(&mods).unwrap();
+    assert_eq!(saved, "\"HDHR\"");
+    assert_eq!(ron::from_str::<Mods>(&saved).unwrap(), mods);
+    assert!(ron::from_str::<Mods>("\"EZHR\"").is_err());
+}
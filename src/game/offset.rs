@@ -0,0 +1,105 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// File the offsets are kept in, in the working directory.
+const OFFSETS_FILE: &str = "offsets.txt";
+/// Key of the universal offset in the offsets file, beatmaps are keyed by their file name.
+const UNIVERSAL_KEY: &str = "*";
+
+/// Audio offsets in seconds, positive when the audio is heard late.
+///
+/// The universal offset compensates the latency of the player's hardware, the beatmap ones
+/// fix beatmaps that are timed slightly off. Both add up to delay the gameplay clock.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Offsets {
+    pub universal: f32,
+    beatmaps: BTreeMap<String, f32>,
+}
+
+impl Offsets {
+    /// Parses `key = milliseconds` lines, ignoring the malformed ones.
+    pub fn parse(content: &str) -> Self {
+        let mut offsets = Offsets::default();
+        for line in content.lines() {
+            let parsed = line.rsplit_once('=').and_then(|(key, value)| {
+                Some((key.trim(), value.trim().parse::<f32>().ok()? / 1000.0))
+            });
+            match parsed {
+                Some((UNIVERSAL_KEY, offset)) => offsets.universal = offset,
+                Some((key, offset)) if !key.is_empty() => {
+                    offsets.beatmaps.insert(key.to_owned(), offset);
+                }
+                _ => {}
+            }
+        }
+        offsets
+    }
+
+    fn path() -> PathBuf {
+        PathBuf::from(OFFSETS_FILE)
+    }
+
+    /// Loads the offsets saved by [`Offsets::save`], all zero if there are none.
+    pub fn load() -> Self {
+        match std::fs::read_to_string(Self::path()) {
+            Ok(content) => Offsets::parse(&content),
+            Err(_) => Offsets::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = std::fs::write(Self::path(), self.to_string()) {
+            println!("Failed to save offsets: {}", e);
+        }
+    }
+
+    /// Offset of the beatmap `key`, without the universal offset.
+    pub fn beatmap(&self, key: &str) -> f32 {
+        self.beatmaps.get(key).copied().unwrap_or(0.0)
+    }
+
+    pub fn set_beatmap(&mut self, key: &str, offset: f32) {
+        if offset == 0.0 {
+            self.beatmaps.remove(key);
+        } else {
+            self.beatmaps.insert(key.to_owned(), offset);
+        }
+    }
+
+    /// Offset applied to the gameplay clock while playing the beatmap `key`.
+    pub fn total(&self, key: &str) -> f32 {
+        self.universal + self.beatmap(key)
+    }
+}
+
+impl std::fmt::Display for Offsets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} = {}",
+            UNIVERSAL_KEY,
+            (self.universal * 1000.0).round()
+        )?;
+        for (key, offset) in &self.beatmaps {
+            writeln!(f, "{} = {}", key, (offset * 1000.0).round())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_offsets() {
+    let offsets = Offsets::parse(
+        "* = 25\nnot an offset\npositive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu = -10\n",
+    );
+    assert_eq!(offsets.universal, 0.025);
+    let key = "positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu";
+    assert_eq!(offsets.beatmap(key), -0.01);
+    assert!((offsets.total(key) - 0.015).abs() < 1e-6);
+    assert_eq!(offsets.total("unknown.osu"), 0.025);
+
+    assert_eq!(Offsets::parse(&offsets.to_string()), offsets);
+
+    let mut offsets = offsets;
+    offsets.set_beatmap(key, 0.0);
+    assert_eq!(offsets.total(key), 0.025);
+}
@@ -0,0 +1,333 @@
+//! Writes charts back to .osu files, so they can be played in osu!.
+
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::math;
+
+use super::{
+    chart::{self, ChartData, ChartInfo, CurveType, HitObject, HitObjectData, OSU_PIXEL_SCALE},
+    hitsound::{SamplePoint, SampleSet},
+};
+
+const FORMAT_VERSION: u32 = 14;
+
+/// Converts a position in playfield units back to osu!pixels.
+fn osu_position(position: cgmath::Vector2<f32>) -> (i32, i32) {
+    (
+        math::remap(-320.0, 320.0, 0.0, 512.0, position.x).round() as i32,
+        math::remap(-240.0, 240.0, 0.0, 384.0, position.y).round() as i32,
+    )
+}
+
+/// Rounds a difficulty setting to three decimals, dropping the error of converting it back and forth.
+fn setting(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
+
+fn ms(seconds: f32) -> i32 {
+    (seconds * 1000.0).round() as i32
+}
+
+/// Sample point active at `time`, without the leniency given to hit objects.
+fn sample_point(data: &ChartData, time: f32) -> SamplePoint {
+    let points = &data.hitsounds.sample_points;
+    points
+        .iter()
+        .take_while(|point| point.time <= time)
+        .last()
+        .or_else(|| points.first())
+        .copied()
+        .unwrap_or(SamplePoint {
+            time: 0.0,
+            sample_set: SampleSet::Normal,
+            index: 0,
+            volume: 1.0,
+        })
+}
+
+/// Writes the timing points of the chart.
+///
+/// Slider velocities are baked into the sliders, so every slider gets an inherited point
+/// setting its velocity, unless it starts on an uninherited point which sets it on its own.
+/// Sample points and the start and end of kiai sections get a point as well.
+fn write_timing_points(out: &mut String, info: &ChartInfo, data: &ChartData) {
+    let timing_points = data.beats.timing_points();
+    let mut times = BTreeSet::new();
+    times.extend(timing_points.iter().map(|point| ms(point.time)));
+    times.extend(
+        data.hitsounds
+            .sample_points
+            .iter()
+            .map(|point| ms(point.time)),
+    );
+    times.extend(
+        data.kiai_sections
+            .iter()
+            .flat_map(|section| [section.start, section.end])
+            .filter(|time| time.is_finite())
+            .map(ms),
+    );
+    times.extend(
+        data.objects
+            .iter()
+            .filter(|obj| matches!(obj.data, HitObjectData::Slider(_)))
+            .map(|obj| ms(obj.time)),
+    );
+
+    let mut beat_length = timing_points
+        .first()
+        .map_or(1.0, |point| point.beat_length * 1000.0);
+    for time_ms in times {
+        let time = time_ms as f32 / 1000.0;
+        let sample = sample_point(data, time);
+        let effects = data.is_kiai(time) as u32;
+        let mut line = |beat_length: f64, meter: u32, uninherited: bool| {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{}",
+                time_ms,
+                beat_length,
+                meter,
+                SampleSet::to_osu(Some(sample.sample_set)),
+                sample.index,
+                (sample.volume * 100.0).round() as u32,
+                uninherited as u32,
+                effects
+            )
+            .unwrap();
+        };
+
+        let uninherited = timing_points.iter().find(|point| ms(point.time) == time_ms);
+        if let Some(point) = uninherited {
+            beat_length = point.beat_length * 1000.0;
+            line(beat_length as f64, point.meter, true);
+            continue;
+        }
+        let meter = data.beats.beat_at(time).map_or(4, |beat| beat.meter);
+        let slider = data
+            .objects
+            .iter()
+            .find(|obj| ms(obj.time) == time_ms)
+            .and_then(|obj| match &obj.data {
+                HitObjectData::Slider(slider) => Some(slider),
+                _ => None,
+            });
+        // Slider velocity relative to the one of the uninherited point, as a negative beat length
+        let velocity = match slider {
+            Some(slider) => {
+                let base = info.slider_multiplier * 100.0 * 1000.0 / beat_length;
+                -100.0 * base as f64 / slider.velocity as f64
+            }
+            None => -100.0,
+        };
+        line(velocity, meter, false);
+    }
+}
+
+fn write_hit_object(out: &mut String, info: &ChartInfo, obj: &HitObject) {
+    // Stacking is applied again when the file is loaded
+    let stack_offset = chart::osu_cs_to_radius(info.modifiers.circle_size) / 10.0 * OSU_PIXEL_SCALE;
+    let unstack = cgmath::vec2(1.0, 1.0) * obj.stack_height as f32 * stack_offset;
+    let (x, y) = osu_position(obj.position + unstack);
+    let combo = if obj.new_combo {
+        (1 << 2) | ((obj.combo_colour_skip & 0b111) << 4)
+    } else {
+        0
+    };
+    match &obj.data {
+        HitObjectData::Circle => writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            x,
+            y,
+            ms(obj.time),
+            1 | combo,
+            obj.samples.additions,
+            obj.samples.to_osu()
+        ),
+        HitObjectData::Slider(slider) => {
+            let curve = match slider.curve_type {
+                CurveType::Bezier => "B",
+                CurveType::Perfect => "P",
+                CurveType::Linear => "L",
+            };
+            let points = slider
+                .control_points
+                .iter()
+                .map(|&point| {
+                    let (x, y) = osu_position(point + unstack);
+                    format!("|{}:{}", x, y)
+                })
+                .collect::<String>();
+            let edge_sounds = slider
+                .edge_samples
+                .iter()
+                .map(|samples| samples.additions.to_string())
+                .collect::<Vec<_>>()
+                .join("|");
+            let edge_sets = slider
+                .edge_samples
+                .iter()
+                .map(|samples| {
+                    format!(
+                        "{}:{}",
+                        SampleSet::to_osu(samples.normal_set),
+                        SampleSet::to_osu(samples.addition_set)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("|");
+            writeln!(
+                out,
+                "{},{},{},{},{},{}{},{},{},{},{},{}",
+                x,
+                y,
+                ms(obj.time),
+                2 | combo,
+                obj.samples.additions,
+                curve,
+                points,
+                slider.repeat + 1,
+                slider.length,
+                edge_sounds,
+                edge_sets,
+                obj.samples.to_osu()
+            )
+        }
+    }
+    .unwrap();
+}
+
+/// Writes a chart as a .osu file.
+pub fn write_osu(info: &ChartInfo, data: &ChartData) -> String {
+    let mut out = String::new();
+    let modifiers = &info.modifiers;
+    writeln!(out, "osu file format v{}", FORMAT_VERSION).unwrap();
+
+    writeln!(out, "\n[General]").unwrap();
+    writeln!(out, "AudioFilename: {}", info.audio_file).unwrap();
+    writeln!(out, "AudioLeadIn: {}", ms(info.audio_lead_in)).unwrap();
+    writeln!(out, "PreviewTime: {}", info.preview_time.map_or(-1, ms)).unwrap();
+    writeln!(out, "StackLeniency: {}", info.stack_leniency).unwrap();
+    writeln!(out, "Mode: 0").unwrap();
+
+    writeln!(out, "\n[Metadata]").unwrap();
+    writeln!(out, "Title:{}", info.title).unwrap();
+    writeln!(out, "Artist:{}", info.artist).unwrap();
+    writeln!(out, "Creator:{}", info.creator).unwrap();
+    writeln!(out, "Version:{}", info.version).unwrap();
+
+    writeln!(out, "\n[Difficulty]").unwrap();
+    writeln!(out, "HPDrainRate:{}", modifiers.drain_rate).unwrap();
+    writeln!(out, "CircleSize:{}", modifiers.circle_size).unwrap();
+    writeln!(out, "OverallDifficulty:{}", modifiers.overall_difficulty).unwrap();
+    writeln!(
+        out,
+        "ApproachRate:{}",
+        setting(chart::secs_to_osu_ar(modifiers.approach_seconds()))
+    )
+    .unwrap();
+    writeln!(out, "SliderMultiplier:{}", info.slider_multiplier).unwrap();
+    writeln!(out, "SliderTickRate:{}", info.slider_tick_rate).unwrap();
+
+    writeln!(out, "\n[Events]").unwrap();
+    if let Some(background) = &data.background {
+        writeln!(out, "0,0,\"{}\",0,0", background).unwrap();
+    }
+    for period in &data.breaks {
+        writeln!(out, "2,{},{}", ms(period.start), ms(period.end)).unwrap();
+    }
+    for sample in &data.storyboard_samples {
+        writeln!(
+            out,
+            "Sample,{},0,\"{}\",{}",
+            ms(sample.time),
+            sample.path,
+            (sample.volume * 100.0).round() as u32
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\n[TimingPoints]").unwrap();
+    write_timing_points(&mut out, info, data);
+
+    if !data.combo_colours.is_empty() {
+        writeln!(out, "\n[Colours]").unwrap();
+        for (idx, colour) in data.combo_colours.iter().enumerate() {
+            let colour = (colour * 255.0).map(|c| c.round() as u8);
+            writeln!(
+                out,
+                "Combo{} : {},{},{}",
+                idx + 1,
+                colour.x,
+                colour.y,
+                colour.z
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(out, "\n[HitObjects]").unwrap();
+    for obj in &data.objects {
+        write_hit_object(&mut out, info, obj);
+    }
+    out
+}
+
+#[test]
+fn test_export_round_trip() {
+    for content in [
+        include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu"),
+        include_str!("../../positive MAD-crew - Mynarco Addiction (Okoratu) [test].osu"),
+    ] {
+        let (info, data) = chart::load_test_chart(content);
+        let exported = write_osu(&info, &data);
+        let (exported_info, exported_data) = chart::load_test_chart(&exported);
+
+        assert_eq!(exported_data.objects.len(), data.objects.len());
+        for (exported, original) in exported_data.objects.iter().zip(&data.objects) {
+            assert_eq!(exported.time, original.time);
+            assert_eq!(exported.position, original.position);
+            assert_eq!(exported.stack_height, original.stack_height);
+            assert_eq!(exported.new_combo, original.new_combo);
+            assert_eq!(exported.combo_number, original.combo_number);
+            assert_eq!(exported.samples, original.samples);
+            assert!((exported.end_time() - original.end_time()).abs() < 0.001);
+            if let (HitObjectData::Slider(exported), HitObjectData::Slider(original)) =
+                (&exported.data, &original.data)
+            {
+                assert_eq!(exported.control_points, original.control_points);
+                assert_eq!(exported.repeat, original.repeat);
+                assert_eq!(exported.length, original.length);
+                assert_eq!(exported.edge_samples, original.edge_samples);
+            }
+        }
+
+        assert_eq!(exported_info.title, info.title);
+        assert_eq!(exported_info.version, info.version);
+        assert_eq!(exported_info.modifiers, info.modifiers);
+        assert_eq!(exported_info.preview_time, info.preview_time);
+        let timing_points = data.beats.timing_points();
+        assert_eq!(
+            exported_data.beats.timing_points().len(),
+            timing_points.len()
+        );
+        for (exported, original) in exported_data
+            .beats
+            .timing_points()
+            .iter()
+            .zip(timing_points)
+        {
+            assert_eq!(exported.time, original.time);
+            assert_eq!(exported.meter, original.meter);
+            assert!((exported.beat_length - original.beat_length).abs() < 1e-6);
+        }
+        assert_eq!(exported_data.kiai_sections, data.kiai_sections);
+        assert_eq!(exported_data.breaks, data.breaks);
+        assert_eq!(exported_data.background, data.background);
+        assert_eq!(exported_data.combo_colours, data.combo_colours);
+
+        // Exporting again doesn't change anything
+        assert_eq!(write_osu(&exported_info, &exported_data), exported);
+    }
+}
@@ -0,0 +1,233 @@
+//! Performance points, how much a play is worth, after osu!'s standard performance model.
+
+use serde::{Deserialize, Serialize};
+
+use super::{difficulty::DifficultyAttributes, judgement::JudgementCounts, mods::Mods};
+
+/// Applied to the total on top of the components.
+const PERFORMANCE_MULTIPLIER: f32 = 1.12;
+/// Aim and speed difficulty is divided by this to get back to raw strain.
+const STRAIN_SCALE: f32 = 0.0675;
+/// Plays of this many objects get the full length bonus, longer ones a little more.
+const LENGTH_BONUS_OBJECTS: f32 = 2000.0;
+
+/// Performance of a play and what it's made of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PerformanceAttributes {
+    pub total: f32,
+    pub aim: f32,
+    pub speed: f32,
+    pub accuracy: f32,
+}
+
+/// What a play got on a chart, all performance depends on.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayStats {
+    pub judgements: JudgementCounts,
+    pub max_combo: u32,
+    pub mods: Mods,
+}
+
+impl PlayStats {
+    fn total_hits(&self) -> f32 {
+        self.judgements.total() as f32
+    }
+
+    /// Longer charts are worth more, as it's harder to keep it up.
+    fn length_bonus(&self) -> f32 {
+        let total_hits = self.total_hits();
+        let mut bonus = 0.95 + 0.4 * (total_hits / LENGTH_BONUS_OBJECTS).min(1.0);
+        if total_hits > LENGTH_BONUS_OBJECTS {
+            bonus += (total_hits / LENGTH_BONUS_OBJECTS).log10() * 0.5;
+        }
+        bonus
+    }
+
+    /// Every miss takes away more the fewer objects there are.
+    fn miss_penalty(&self) -> f32 {
+        let misses = self.judgements.miss;
+        if misses == 0 {
+            return 1.0;
+        }
+        0.97 * (1.0 - (misses as f32 / self.total_hits()).powf(0.775)).powi(misses as i32)
+    }
+
+    /// Breaking combo takes away what the play would be worth if it was held to the end.
+    fn combo_scaling(&self, difficulty: &DifficultyAttributes) -> f32 {
+        if difficulty.max_combo == 0 {
+            return 1.0;
+        }
+        ((self.max_combo as f32).powf(0.8) / (difficulty.max_combo as f32).powf(0.8)).min(1.0)
+    }
+}
+
+fn strain_value(difficulty: f32) -> f32 {
+    (5.0 * (difficulty / STRAIN_SCALE).max(1.0) - 4.0).powi(3) / 100_000.0
+}
+
+fn aim_value(difficulty: &DifficultyAttributes, play: &PlayStats) -> f32 {
+    let ar = difficulty.approach_rate;
+    let mut value = strain_value(difficulty.aim)
+        * play.length_bonus()
+        * play.miss_penalty()
+        * play.combo_scaling(difficulty);
+
+    // Very high and very low approach rates are harder to read
+    let ar_factor = if ar > 10.33 {
+        0.3 * (ar - 10.33)
+    } else if ar < 8.0 {
+        0.01 * (8.0 - ar)
+    } else {
+        0.0
+    };
+    value *= 1.0 + ar_factor.min(ar_factor * play.total_hits() / 1000.0);
+    if play.mods.hidden {
+        value *= 1.0 + 0.04 * (12.0 - ar);
+    }
+
+    value *= 0.5 + play.judgements.accuracy() / 2.0;
+    value * (0.98 + difficulty.overall_difficulty.powi(2) / 2500.0)
+}
+
+fn speed_value(difficulty: &DifficultyAttributes, play: &PlayStats) -> f32 {
+    let ar = difficulty.approach_rate;
+    let od = difficulty.overall_difficulty;
+    let mut value = strain_value(difficulty.speed)
+        * play.length_bonus()
+        * play.miss_penalty()
+        * play.combo_scaling(difficulty);
+
+    let ar_factor = if ar > 10.33 { 0.3 * (ar - 10.33) } else { 0.0 };
+    value *= 1.0 + ar_factor.min(ar_factor * play.total_hits() / 1000.0);
+    if play.mods.hidden {
+        value *= 1.0 + 0.04 * (12.0 - ar);
+    }
+
+    value *=
+        (0.95 + od.powi(2) / 750.0) * play.judgements.accuracy().powf((14.5 - od.max(8.0)) / 2.0);
+    // Mashing through streams gets a lot of mehs
+    let allowed_mehs = play.total_hits() / 500.0;
+    let mehs = play.judgements.meh as f32;
+    if mehs >= allowed_mehs {
+        value *= 0.98f32.powf(mehs - allowed_mehs);
+    }
+    value
+}
+
+/// Only circles are judged on timing alone, slider heads are lenient in osu!, so the accuracy
+/// is taken from the circles with the worst judgements counted against them.
+fn accuracy_value(difficulty: &DifficultyAttributes, play: &PlayStats) -> f32 {
+    let circles = difficulty.circle_count as f32;
+    if circles == 0.0 {
+        return 0.0;
+    }
+    let judgements = &play.judgements;
+    let sliders = play.total_hits() - circles;
+    let circle_accuracy = (((judgements.great as f32 - sliders) * 6.0
+        + judgements.good as f32 * 2.0
+        + judgements.meh as f32)
+        / (circles * 6.0))
+        .max(0.0);
+
+    let mut value =
+        1.52163f32.powf(difficulty.overall_difficulty) * circle_accuracy.powi(24) * 2.83;
+    value *= (circles / 1000.0).powf(0.3).min(1.15);
+    if play.mods.hidden {
+        value *= 1.08;
+    }
+    value
+}
+
+/// Performance of `play` on a chart of `difficulty`, calculated with the mods of the play.
+pub fn calculate(difficulty: &DifficultyAttributes, play: &PlayStats) -> PerformanceAttributes {
+    let aim = aim_value(difficulty, play);
+    let speed = speed_value(difficulty, play);
+    let accuracy = accuracy_value(difficulty, play);
+    let mut multiplier = PERFORMANCE_MULTIPLIER;
+    if play.mods.easy {
+        multiplier *= 0.9;
+    }
+    let total = (aim.powf(1.1) + speed.powf(1.1) + accuracy.powf(1.1)).powf(1.0 / 1.1) * multiplier;
+    PerformanceAttributes {
+        total,
+        aim,
+        speed,
+        accuracy,
+    }
+}
+
+#[cfg(test)]
+fn sample_difficulty() -> DifficultyAttributes {
+    DifficultyAttributes {
+        star_rating: 5.0,
+        aim: 2.5,
+        speed: 2.0,
+        max_combo: 1000,
+        approach_rate: 9.0,
+        overall_difficulty: 8.0,
+        circle_count: 400,
+        slider_count: 200,
+    }
+}
+
+#[cfg(test)]
+fn full_combo(difficulty: &DifficultyAttributes) -> PlayStats {
+    PlayStats {
+        judgements: JudgementCounts {
+            great: difficulty.circle_count + difficulty.slider_count,
+            ..Default::default()
+        },
+        max_combo: difficulty.max_combo,
+        mods: Mods::default(),
+    }
+}
+
+#[test]
+fn test_performance() {
+    let difficulty = sample_difficulty();
+    let best = full_combo(&difficulty);
+    let perfect = calculate(&difficulty, &best);
+    assert!(perfect.aim > perfect.speed && perfect.speed > 0.0 && perfect.accuracy > 0.0);
+    let components = (perfect.aim.powf(1.1) + perfect.speed.powf(1.1) + perfect.accuracy.powf(1.1))
+        .powf(1.0 / 1.1);
+    assert!((perfect.total - components * PERFORMANCE_MULTIPLIER).abs() < 1e-3);
+
+    // Misses, breaking combo and worse judgements are all worth less
+    let mut missed = best;
+    missed.judgements.great -= 5;
+    missed.judgements.miss += 5;
+    missed.max_combo = difficulty.max_combo / 2;
+    let missed = calculate(&difficulty, &missed);
+    assert!(missed.aim < perfect.aim && missed.speed < perfect.speed);
+    assert!(missed.accuracy < perfect.accuracy);
+    let mut inaccurate = best;
+    inaccurate.judgements.great -= 20;
+    inaccurate.judgements.good += 20;
+    let inaccurate = calculate(&difficulty, &inaccurate);
+    assert!(inaccurate.accuracy < perfect.accuracy);
+    assert!(inaccurate.total > missed.total);
+
+    // Hidden is worth more, Easy less
+    let mut hidden = best;
+    hidden.mods = Mods::parse("HD").unwrap();
+    assert!(calculate(&difficulty, &hidden).total > perfect.total);
+    let mut easy = best;
+    easy.mods = Mods::parse("EZ").unwrap();
+    assert!(calculate(&difficulty, &easy).total < perfect.total);
+}
+
+#[test]
+fn test_length_bonus() {
+    let mut short = sample_difficulty();
+    let mut long = sample_difficulty();
+    long.circle_count *= 4;
+    long.slider_count *= 4;
+    long.max_combo *= 4;
+    short.circle_count /= 4;
+    short.slider_count /= 4;
+    short.max_combo /= 4;
+    let short = calculate(&short, &full_combo(&short));
+    let long = calculate(&long, &full_combo(&long));
+    assert!(long.aim > short.aim);
+    assert!(long.speed > short.speed);
+}
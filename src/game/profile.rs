@@ -0,0 +1,292 @@
+//! Local player profiles, each with their own settings and stats derived from their scores.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    score::{Grade, Score},
+    settings::PlayerSettings,
+    DEFAULT_BACKGROUND_DIM,
+};
+
+/// File the profiles are kept in, in the working directory.
+const PROFILES_FILE: &str = "profiles.ron";
+/// Profile created on the first start, which scores saved before profiles belong to.
+pub const DEFAULT_PROFILE: &str = "Player";
+/// Plays the accuracy trend goes back.
+const ACCURACY_TREND_PLAYS: usize = 10;
+
+/// Someone playing on this machine, and how they like to play.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredProfile")]
+pub struct Profile {
+    pub name: String,
+    pub settings: PlayerSettings,
+}
+
+impl Profile {
+    pub fn new(name: impl Into<String>) -> Self {
+        Profile {
+            name: name.into(),
+            settings: PlayerSettings::default(),
+        }
+    }
+
+    /// The default profile, taking its settings from the `SKIN`, `BACKGROUND_DIM` and
+    /// `IGNORE_BEATMAP_SKINS` environment variables that configured the game before profiles.
+    fn from_env() -> Self {
+        Profile {
+            settings: PlayerSettings {
+                skin: std::env::var("SKIN").ok().map(PathBuf::from),
+                background_dim: std::env::var("BACKGROUND_DIM")
+                    .ok()
+                    .and_then(|dim| dim.parse::<u8>().ok())
+                    .unwrap_or(DEFAULT_BACKGROUND_DIM)
+                    .min(100),
+                ignore_beatmap_skins: std::env::var("IGNORE_BEATMAP_SKINS").is_ok(),
+                ..PlayerSettings::default()
+            },
+            ..Profile::new(DEFAULT_PROFILE)
+        }
+    }
+}
+
+fn default_background_dim() -> u8 {
+    DEFAULT_BACKGROUND_DIM
+}
+
+/// Reads a field written without `Some`, telling it apart from a missing one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// A profile as saved, also read from files written before the settings of a profile were
+/// grouped, when they were kept next to its name.
+#[derive(Deserialize)]
+struct StoredProfile {
+    name: String,
+    #[serde(default, deserialize_with = "present")]
+    settings: Option<PlayerSettings>,
+    #[serde(default)]
+    skin: Option<PathBuf>,
+    #[serde(default = "default_background_dim")]
+    background_dim: u8,
+    #[serde(default)]
+    ignore_beatmap_skins: bool,
+}
+
+impl From<StoredProfile> for Profile {
+    fn from(stored: StoredProfile) -> Self {
+        let settings = stored.settings.unwrap_or_else(|| PlayerSettings {
+            skin: stored.skin,
+            background_dim: stored.background_dim.min(100),
+            ignore_beatmap_skins: stored.ignore_beatmap_skins,
+            ..PlayerSettings::default()
+        });
+        Profile {
+            name: stored.name,
+            settings,
+        }
+    }
+}
+
+/// Every profile on this machine, and the one playing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profiles {
+    active: usize,
+    profiles: Vec<Profile>,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Profiles {
+            active: 0,
+            profiles: vec![Profile::from_env()],
+        }
+    }
+}
+
+impl Profiles {
+    fn path() -> PathBuf {
+        PathBuf::from(PROFILES_FILE)
+    }
+
+    /// Loads the profiles saved by [`Profiles::save`], only the default one if there are none.
+    pub fn load() -> Self {
+        let profiles = match std::fs::read_to_string(Self::path()) {
+            Ok(content) => ron::from_str(&content).unwrap_or_else(|e| {
+                println!("Failed to read profiles: {}", e);
+                Profiles::default()
+            }),
+            Err(_) => Profiles::default(),
+        };
+        // Hand edited files could leave nothing to play with
+        if profiles.profiles.is_empty() {
+            return Profiles::default();
+        }
+        Profiles {
+            active: profiles.active.min(profiles.profiles.len() - 1),
+            ..profiles
+        }
+    }
+
+    pub fn save(&self) {
+        // Profiles only hold types that serialize to RON
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()).unwrap();
+        if let Err(e) = std::fs::write(Self::path(), content) {
+            println!("Failed to save profiles: {}", e);
+        }
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.active]
+    }
+
+    /// Switches to the profile `offset` places away, wrapping around.
+    pub fn switch(&mut self, offset: isize) -> &Profile {
+        let len = self.profiles.len() as isize;
+        self.active = (self.active as isize + offset).rem_euclid(len) as usize;
+        self.active()
+    }
+
+    /// Adds a profile named `name` with default settings and switches to it.
+    /// Returns `None` if the name is empty or already taken.
+    pub fn add(&mut self, name: &str) -> Option<&Profile> {
+        let name = name.trim();
+        if name.is_empty() || self.profiles.iter().any(|profile| profile.name == name) {
+            return None;
+        }
+        self.profiles.push(Profile::new(name));
+        self.active = self.profiles.len() - 1;
+        Some(self.active())
+    }
+}
+
+/// What a profile has done, derived from its scores.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProfileStats {
+    pub total_plays: u32,
+    /// Seconds spent playing.
+    pub play_time: f32,
+    /// Sum of the best score on each chart.
+    pub ranked_score: u64,
+    /// Accuracy of the latest plays, oldest first.
+    pub accuracy_trend: Vec<f32>,
+    /// Grades of the best score on each chart.
+    pub grades: BTreeMap<Grade, u32>,
+    /// Times each chart was played, by beatmap hash.
+    pub map_plays: HashMap<String, u32>,
+}
+
+impl ProfileStats {
+    /// Derives the stats from every score of a profile, in the order they were played.
+    pub fn from_scores<'a>(scores: impl IntoIterator<Item = &'a Score>) -> Self {
+        let mut stats = ProfileStats::default();
+        let mut bests = HashMap::<&str, &Score>::new();
+        let mut accuracies = Vec::new();
+        for score in scores {
+            stats.total_plays += 1;
+            stats.play_time += score.play_time;
+            *stats
+                .map_plays
+                .entry(score.beatmap_hash.clone())
+                .or_default() += 1;
+            accuracies.push(score.accuracy);
+            let best = bests.entry(&score.beatmap_hash).or_insert(score);
+            if score.total_score > best.total_score {
+                *best = score;
+            }
+        }
+        for best in bests.values() {
+            stats.ranked_score += best.total_score;
+            *stats.grades.entry(best.grade()).or_default() += 1;
+        }
+        let trend_start = accuracies.len().saturating_sub(ACCURACY_TREND_PLAYS);
+        stats.accuracy_trend = accuracies.split_off(trend_start);
+        stats
+    }
+
+    /// Times the chart `beatmap_hash` was played.
+    pub fn map_plays(&self, beatmap_hash: &str) -> u32 {
+        self.map_plays.get(beatmap_hash).copied().unwrap_or(0)
+    }
+}
+
+#[test]
+fn test_profiles() {
+    let mut profiles = Profiles {
+        active: 0,
+        profiles: vec![Profile::new(DEFAULT_PROFILE)],
+    };
+    assert!(profiles.add(DEFAULT_PROFILE).is_none());
+    assert!(profiles.add("  ").is_none());
+    assert_eq!(profiles.add(" Second ").unwrap().name, "Second");
+    assert_eq!(profiles.active().name, "Second");
+    assert_eq!(profiles.switch(1).name, DEFAULT_PROFILE);
+    assert_eq!(profiles.switch(-1).name, "Second");
+
+    let saved = ron::ser::to_string(&profiles).unwrap();
+    assert_eq!(ron::from_str::<Profiles>(&saved).unwrap(), profiles);
+}
+
+#[test]
+fn test_legacy_profiles() {
+    // Settings kept next to the name, before they had their own struct
+    let profiles = ron::from_str::<Profiles>(
+        r#"(
+            active: 1,
+            profiles: [
+                (name: "Player", skin: Some("skins/dark"), background_dim: 80, ignore_beatmap_skins: true),
+                (name: "Second"),
+            ],
+        )"#,
+    )
+    .unwrap();
+    assert_eq!(profiles.active().name, "Second");
+    let settings = &profiles.profiles[0].settings;
+    assert_eq!(settings.skin, Some(PathBuf::from("skins/dark")));
+    assert_eq!(settings.background_dim, 80);
+    assert!(settings.ignore_beatmap_skins);
+    assert_eq!(settings.hit_keys, PlayerSettings::default().hit_keys);
+    assert_eq!(profiles.profiles[1].settings, PlayerSettings::default());
+}
+
+#[test]
+fn test_stats() {
+    use super::{judgement::JudgementCounts, score::test_score};
+
+    let mut scores = vec![
+        test_score("a", 1000, 0),
+        test_score("a", 3000, 1),
+        test_score("b", 2000, 2),
+    ];
+    scores[0].accuracy = 0.9;
+    scores[1].judgements = JudgementCounts {
+        great: 90,
+        good: 10,
+        ..Default::default()
+    };
+    let stats = ProfileStats::from_scores(&scores);
+    assert_eq!(stats.total_plays, 3);
+    assert!((stats.play_time - 180.0).abs() < 1e-3);
+    assert_eq!(stats.ranked_score, 5000);
+    assert_eq!(stats.accuracy_trend, vec![0.9, 1.0, 1.0]);
+    assert_eq!(
+        stats.grades.into_iter().collect::<Vec<_>>(),
+        vec![(Grade::Ss, 1), (Grade::A, 1)]
+    );
+    assert_eq!(stats.map_plays("a"), 2);
+    assert_eq!(stats.map_plays("c"), 0);
+}
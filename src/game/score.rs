@@ -0,0 +1,295 @@
+//! Results of finished plays, and the local database they are kept in.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    difficulty::DifficultyAttributes,
+    judgement::{Judgement, JudgementCounts},
+    mods::Mods,
+    performance::{self, PerformanceAttributes, PlayStats},
+    profile,
+};
+
+/// File the scores are kept in, in the working directory.
+const SCORES_FILE: &str = "scores.ron";
+/// Each step of combo adds this fraction of a hit's score on top of it.
+const COMBO_BONUS_DIVISOR: u64 = 25;
+
+/// Identifies a chart by its file content, so scores follow it when it's renamed and are kept
+/// apart when it's edited.
+pub fn beatmap_hash(content: &str) -> String {
+    format!("{:x}", md5::compute(content))
+}
+
+/// Score points a hit is worth when it's made on top of `combo`.
+pub fn hit_points(judgement: Judgement, combo: u32) -> u64 {
+    let points = judgement.score() as u64;
+    points + points * combo as u64 / COMBO_BONUS_DIVISOR
+}
+
+/// Letter grade of a play, from its judgements like in osu!.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Grade {
+    /// Every object hit perfectly.
+    Ss,
+    S,
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Grade {
+    pub const ALL: [Grade; 6] = [Grade::Ss, Grade::S, Grade::A, Grade::B, Grade::C, Grade::D];
+
+    pub fn from_judgements(judgements: &JudgementCounts) -> Self {
+        let total = judgements.total();
+        if total == 0 || judgements.great == total {
+            return Grade::Ss;
+        }
+        let greats = judgements.great as f32 / total as f32;
+        let mehs = judgements.meh as f32 / total as f32;
+        let no_misses = judgements.miss == 0;
+        if greats > 0.9 && mehs <= 0.01 && no_misses {
+            Grade::S
+        } else if greats > 0.9 || (greats > 0.8 && no_misses) {
+            Grade::A
+        } else if greats > 0.8 || (greats > 0.7 && no_misses) {
+            Grade::B
+        } else if greats > 0.6 {
+            Grade::C
+        } else {
+            Grade::D
+        }
+    }
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Grade::Ss => write!(f, "SS"),
+            grade => write!(f, "{:?}", grade),
+        }
+    }
+}
+
+fn default_player() -> String {
+    profile::DEFAULT_PROFILE.to_owned()
+}
+
+/// A finished play on a chart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    /// [`beatmap_hash`] of the chart played.
+    pub beatmap_hash: String,
+    /// Name of the profile that played. Scores saved before profiles belong to the default one.
+    #[serde(default = "default_player")]
+    pub player: String,
+    /// Score points, with the multiplier of the mods applied.
+    pub total_score: u64,
+    pub judgements: JudgementCounts,
+    pub max_combo: u32,
+    pub mods: Mods,
+    /// Accuracy from 0 to 1.
+    pub accuracy: f32,
+    pub performance: PerformanceAttributes,
+    pub played_at: DateTime<Utc>,
+    /// Seconds from the start of the play to its end, pauses included.
+    #[serde(default)]
+    pub play_time: f32,
+    /// File the replay of the play is saved in. Replays aren't recorded yet, so it's always
+    /// `None` for now.
+    #[serde(default)]
+    pub replay: Option<PathBuf>,
+}
+
+impl Score {
+    /// Scores a play of `player` on a chart of `difficulty`, which has to be calculated with the
+    /// mods of the play applied. `points` are the score points before the mod multiplier.
+    pub fn new(
+        beatmap_hash: String,
+        player: String,
+        points: u64,
+        play_time: f32,
+        difficulty: &DifficultyAttributes,
+        play: PlayStats,
+    ) -> Self {
+        Score {
+            beatmap_hash,
+            player,
+            total_score: (points as f64 * play.mods.score_multiplier() as f64).round() as u64,
+            judgements: play.judgements,
+            max_combo: play.max_combo,
+            mods: play.mods,
+            accuracy: play.judgements.accuracy(),
+            performance: performance::calculate(difficulty, &play),
+            played_at: Utc::now(),
+            play_time,
+            replay: None,
+        }
+    }
+
+    pub fn grade(&self) -> Grade {
+        Grade::from_judgements(&self.judgements)
+    }
+}
+
+/// Every play made on this machine.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreDatabase {
+    scores: Vec<Score>,
+}
+
+impl ScoreDatabase {
+    fn path() -> PathBuf {
+        PathBuf::from(SCORES_FILE)
+    }
+
+    /// Loads the scores saved by [`ScoreDatabase::save`], empty if there are none.
+    pub fn load() -> Self {
+        match std::fs::read_to_string(Self::path()) {
+            Ok(content) => ron::from_str(&content).unwrap_or_else(|e| {
+                println!("Failed to read scores: {}", e);
+                ScoreDatabase::default()
+            }),
+            Err(_) => ScoreDatabase::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        // Scores only hold types that serialize to RON
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()).unwrap();
+        if let Err(e) = std::fs::write(Self::path(), content) {
+            println!("Failed to save scores: {}", e);
+        }
+    }
+
+    /// Adds the score of a finished play and saves it right away.
+    pub fn add(&mut self, score: Score) {
+        self.scores.push(score);
+        self.save();
+    }
+
+    /// Scores on the chart `beatmap_hash`, best first. Ties go to the earlier play.
+    pub fn leaderboard(&self, beatmap_hash: &str) -> Vec<&Score> {
+        let mut scores = self
+            .scores
+            .iter()
+            .filter(|score| score.beatmap_hash == beatmap_hash)
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| {
+            b.total_score
+                .cmp(&a.total_score)
+                .then(a.played_at.cmp(&b.played_at))
+        });
+        scores
+    }
+
+    /// Best score of `player` on the chart `beatmap_hash`.
+    pub fn personal_best(&self, beatmap_hash: &str, player: &str) -> Option<&Score> {
+        self.leaderboard(beatmap_hash)
+            .into_iter()
+            .find(|score| score.player == player)
+    }
+
+    /// Every score of `player`, in the order they were played.
+    pub fn player_scores<'a>(&'a self, player: &'a str) -> impl Iterator<Item = &'a Score> {
+        self.scores
+            .iter()
+            .filter(move |score| score.player == player)
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_score(beatmap_hash: &str, total_score: u64, minutes: i64) -> Score {
+    Score {
+        beatmap_hash: beatmap_hash.to_owned(),
+        player: default_player(),
+        total_score,
+        judgements: JudgementCounts::default(),
+        max_combo: 0,
+        mods: Mods::default(),
+        accuracy: 1.0,
+        performance: PerformanceAttributes::default(),
+        played_at: "2022-02-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap()
+            + chrono::Duration::minutes(minutes),
+        play_time: 60.0,
+        replay: None,
+    }
+}
+
+#[test]
+fn test_leaderboard() {
+    let database = ScoreDatabase {
+        scores: vec![
+            test_score("a", 1000, 0),
+            test_score("b", 5000, 1),
+            test_score("a", 3000, 2),
+            test_score("a", 3000, 3),
+            Score {
+                player: "Other".to_owned(),
+                ..test_score("a", 4000, 4)
+            },
+        ],
+    };
+    let leaderboard = database.leaderboard("a");
+    assert_eq!(
+        leaderboard
+            .iter()
+            .map(|score| (score.total_score, score.played_at.format("%M").to_string()))
+            .collect::<Vec<_>>(),
+        vec![
+            (4000, "04".to_owned()),
+            (3000, "02".to_owned()),
+            (3000, "03".to_owned()),
+            (1000, "00".to_owned())
+        ]
+    );
+    assert_eq!(
+        database.personal_best("a", profile::DEFAULT_PROFILE),
+        Some(&database.scores[2])
+    );
+    assert_eq!(
+        database.personal_best("a", "Other"),
+        Some(&database.scores[4])
+    );
+    assert_eq!(database.personal_best("c", profile::DEFAULT_PROFILE), None);
+    assert_eq!(database.player_scores("Other").count(), 1);
+
+    let saved = ron::ser::to_string(&database).unwrap();
+    assert_eq!(ron::from_str::<ScoreDatabase>(&saved).unwrap(), database);
+}
+
+#[test]
+fn test_points() {
+    assert_eq!(hit_points(Judgement::Great, 0), 300);
+    assert_eq!(hit_points(Judgement::Great, 50), 900);
+    assert_eq!(hit_points(Judgement::Meh, 10), 70);
+    assert_eq!(hit_points(Judgement::Miss, 100), 0);
+    assert_eq!(beatmap_hash(""), "d41d8cd98f00b204e9800998ecf8427e");
+}
+
+#[test]
+fn test_grade() {
+    let grade = |great, good, meh, miss| {
+        Grade::from_judgements(&JudgementCounts {
+            great,
+            good,
+            meh,
+            miss,
+        })
+    };
+    assert_eq!(grade(100, 0, 0, 0), Grade::Ss);
+    assert_eq!(grade(95, 5, 0, 0), Grade::S);
+    assert_eq!(grade(95, 3, 2, 0), Grade::A);
+    assert_eq!(grade(95, 4, 0, 1), Grade::A);
+    assert_eq!(grade(85, 15, 0, 0), Grade::A);
+    assert_eq!(grade(85, 14, 0, 1), Grade::B);
+    assert_eq!(grade(75, 24, 0, 1), Grade::C);
+    assert_eq!(grade(50, 50, 0, 0), Grade::D);
+    assert_eq!(Grade::Ss.to_string(), "SS");
+    assert_eq!(Grade::B.to_string(), "B");
+}
@@ -0,0 +1,223 @@
+use std::time::Instant;
+
+use ogfx::{RenderContext, Renderable, Sprite, Transform};
+
+use crate::{
+    game::{
+        audio::SampleBank, beat::BeatTracker, chart::TimingPoint, hitsound::Sample, GameContext,
+        LogLayer,
+    },
+    job::{spawn_job, JobHandle},
+    llog, math,
+};
+
+use super::{Screen, Updatable};
+
+/// Time between two metronome clicks, 120 BPM.
+const BEAT_SECONDS: f32 = 0.5;
+const BEATS_PER_MEASURE: u32 = 4;
+/// Taps needed before an offset is suggested.
+const MIN_TAPS: usize = 8;
+/// Only the most recent taps are kept, so the suggestion follows the player settling in.
+const MAX_TAPS: usize = 32;
+const CLICK_SAMPLE: &str = "normal-hitnormal";
+const FLASH_SECONDS: f32 = 0.15;
+/// How much the flash grows on a click, more on the first beat of each measure.
+const FLASH_GROWTH: f32 = 0.1;
+const DOWNBEAT_FLASH_GROWTH: f32 = 0.25;
+
+/// Suggests an audio offset from the tap timings `deltas`, in seconds relative to the clicks.
+///
+/// The quarter of the taps furthest on each side are discarded before averaging,
+/// so a few mistimed taps don't skew the result.
+pub fn suggest_offset(deltas: &[f32]) -> Option<f32> {
+    if deltas.len() < MIN_TAPS {
+        return None;
+    }
+    let mut sorted = deltas.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let trim = sorted.len() / 4;
+    let kept = &sorted[trim..sorted.len() - trim];
+    Some(kept.iter().sum::<f32>() / kept.len() as f32)
+}
+
+/// Plays a metronome and measures when the player taps to it, to suggest a universal offset.
+pub struct CalibrationScreen {
+    samples: SampleBank,
+    /// Flashes on every click.
+    flash: Sprite,
+    flash_scale: cgmath::Vector2<f32>,
+    start: Instant,
+    metronome: BeatTracker,
+    next_beat: i64,
+    /// Whether the last click was on the first beat of a measure.
+    downbeat: bool,
+    /// Seconds since `start` at which the clicks were actually played.
+    clicks: Vec<f32>,
+    /// Tap timings relative to the nearest click, in seconds.
+    deltas: Vec<f32>,
+}
+
+impl Screen for CalibrationScreen {
+    type LoadingResource = ();
+    type LoadingError = std::convert::Infallible;
+
+    fn load(_ctx: std::sync::Arc<GameContext>) -> JobHandle<Result<(), std::convert::Infallible>> {
+        spawn_job(|| Ok(()))
+    }
+
+    fn new(ctx: &GameContext, _loading_res: ()) -> Self {
+        if let Some(clock) = ctx.clock_mut().as_mut() {
+            clock.pause();
+        }
+
+        let game_resources = ctx.game_resources.lock().unwrap();
+        let game_resources = game_resources.as_ref().unwrap();
+        let atlas = &game_resources.hitobject_atlas;
+        let name = "overlay";
+        let flash_scale = atlas.scale_for(atlas.logical_size(name) * 2.0);
+        let flash = Sprite::new(
+            &ctx.gfx,
+            atlas.texture.clone(),
+            Transform {
+                position: ctx.gfx.dimensions.cast::<f32>().unwrap() / 2.0,
+                scale: flash_scale,
+                source: atlas.sub_textures[name].cast(),
+                color: cgmath::vec4(1.0, 1.0, 1.0, 0.0),
+                ..Default::default()
+            },
+        );
+
+        let mut samples = game_resources.samples.clone();
+        samples.set_volume(ctx.settings.lock().unwrap().effects_volume());
+        CalibrationScreen {
+            samples,
+            flash,
+            flash_scale,
+            start: Instant::now(),
+            metronome: BeatTracker::new(vec![TimingPoint {
+                time: 0.0,
+                beat_length: BEAT_SECONDS,
+                meter: BEATS_PER_MEASURE,
+            }]),
+            next_beat: 0,
+            downbeat: false,
+            clicks: Vec::new(),
+            deltas: Vec::new(),
+        }
+    }
+}
+
+impl CalibrationScreen {
+    fn elapsed(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+
+    /// Records a tap against the closest metronome click.
+    pub fn on_tap(&mut self) {
+        let time = self.elapsed();
+        let nearest = self
+            .clicks
+            .iter()
+            .map(|click| time - click)
+            .min_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap());
+        if let Some(delta) = nearest {
+            self.deltas.push(delta);
+            if self.deltas.len() > MAX_TAPS {
+                self.deltas.remove(0);
+            }
+        }
+    }
+
+    /// Offset suggested from the taps so far, in seconds.
+    pub fn suggested_offset(&self) -> Option<f32> {
+        suggest_offset(&self.deltas)
+    }
+
+    /// Makes the suggested offset the universal offset and saves it.
+    pub fn apply(&self, ctx: &GameContext) {
+        if let Some(offset) = self.suggested_offset() {
+            let mut offsets = ctx.offsets.lock().unwrap();
+            offsets.universal = (offset * 1000.0).round() / 1000.0;
+            offsets.save();
+            println!(
+                "Universal offset: {}ms",
+                (offsets.universal * 1000.0).round()
+            );
+        }
+    }
+
+    /// Instructions and the current measurement, shown over the screen.
+    pub fn text(&self, ctx: &GameContext) -> String {
+        let current = ctx.offsets.lock().unwrap().universal;
+        let suggestion = match self.suggested_offset() {
+            Some(offset) => format!(
+                "Suggested offset: {}ms, press Enter to apply",
+                (offset * 1000.0).round()
+            ),
+            None => format!("Tap {} more times", MIN_TAPS - self.deltas.len()),
+        };
+        let [first_key, second_key] = ctx.player_settings().hit_keys;
+        format!(
+            "Tap {} or {} along with the clicks\nCurrent offset: {}ms\n{}",
+            first_key,
+            second_key,
+            (current * 1000.0).round(),
+            suggestion
+        )
+    }
+}
+
+impl Updatable for CalibrationScreen {
+    fn update(&mut self, ctx: &GameContext) {
+        let time = self.elapsed();
+        let beat = self.metronome.beat_at(time).unwrap();
+        if beat.index >= self.next_beat {
+            // Taps are measured against when the click was played, not when it was due,
+            // so frame timing doesn't end up in the offset
+            self.clicks.push(time);
+            if self.clicks.len() > 2 {
+                self.clicks.remove(0);
+            }
+            let click = Sample {
+                name: CLICK_SAMPLE.to_owned(),
+                index: 0,
+                volume: 1.0,
+            };
+            if let Err(e) = self.samples.play(&click) {
+                llog!(ctx, LogLayer::Audio, "{}", e);
+            }
+            self.next_beat = beat.index + 1;
+            self.downbeat = beat.is_downbeat();
+        }
+
+        let since_click = self.clicks.last().map_or(f32::MAX, |click| time - click);
+        let transform = self.flash.get_transform_mut();
+        transform.color.w = math::clamped_remap(0.0, FLASH_SECONDS, 1.0, 0.0, since_click);
+        let growth = if self.downbeat {
+            DOWNBEAT_FLASH_GROWTH
+        } else {
+            FLASH_GROWTH
+        };
+        transform.scale = self.flash_scale
+            * math::clamped_remap(0.0, FLASH_SECONDS, 1.0 + growth, 1.0, since_click);
+        self.flash.update(&ctx.gfx);
+    }
+}
+
+impl Renderable for CalibrationScreen {
+    fn render<'data>(&'data self, rctx: &RenderContext<'data>, pass: &mut wgpu::RenderPass<'data>) {
+        self.flash.render(rctx, pass);
+    }
+}
+
+#[test]
+fn test_suggest_offset() {
+    assert_eq!(suggest_offset(&[0.02; MIN_TAPS - 1]), None);
+    // Consistently late taps, with a couple of stray ones
+    let deltas = [
+        0.03, 0.028, 0.032, 0.03, -0.2, 0.031, 0.029, 0.25, 0.03, 0.03,
+    ];
+    let offset = suggest_offset(&deltas).unwrap();
+    assert!((offset - 0.03).abs() < 0.002);
+}
@@ -1,10 +1,16 @@
 use crate::job::JobHandle;
 
-use self::playing::PlayingScreen;
+use self::{
+    calibration::CalibrationScreen, playing::PlayingScreen, settings::SettingsScreen,
+    song_select::SongSelectScreen,
+};
 
 use super::GameContext;
 
+pub mod calibration;
 pub mod playing;
+pub mod settings;
+pub mod song_select;
 
 pub trait Updatable {
     fn update(&mut self, ctx: &GameContext);
@@ -12,15 +18,28 @@ pub trait Updatable {
 
 pub trait Screen {
     type LoadingResource;
+    /// Why loading the screen failed, `Infallible` for screens that always load.
+    type LoadingError: std::fmt::Display;
 
-    fn load(ctx: std::sync::Arc<GameContext>) -> JobHandle<Self::LoadingResource>;
+    fn load(
+        ctx: std::sync::Arc<GameContext>,
+    ) -> JobHandle<Result<Self::LoadingResource, Self::LoadingError>>;
     fn new(ctx: &GameContext, loading_res: Self::LoadingResource) -> Self;
 }
 
+pub type LoadingJob<S> =
+    JobHandle<Result<<S as Screen>::LoadingResource, <S as Screen>::LoadingError>>;
+
 pub enum GameScreen {
     Playing(PlayingScreen),
+    Calibration(CalibrationScreen),
+    SongSelect(SongSelectScreen),
+    Settings(SettingsScreen),
 }
 
 pub enum GameLoadingResource {
-    Playing(JobHandle<<PlayingScreen as Screen>::LoadingResource>),
+    Playing(LoadingJob<PlayingScreen>),
+    Calibration(LoadingJob<CalibrationScreen>),
+    SongSelect(LoadingJob<SongSelectScreen>),
+    Settings(LoadingJob<SettingsScreen>),
 }
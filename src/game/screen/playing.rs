@@ -1,8 +1,36 @@
-use ogfx::{Buffer, Rect, RenderContext, Renderable, Sprite, Transform};
-use slotmap::SlotMap;
+use cgmath::InnerSpace;
+use kira::sound::handle::SoundHandle;
+use ogfx::{
+    spritebatch::SpriteIdx, texture::RawTextureData, Buffer, Rect, RenderContext, Renderable,
+    Sprite, SpriteBatch, Transform,
+};
+use slotmap::{SecondaryMap, SlotMap};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::Instant,
+};
 
 use crate::{
-    game::{chart, graphics::slider, ChartProgress, GameContext, LogLayer},
+    game::{
+        audio::{self, SampleBank},
+        chart::{self, SliderEvent},
+        chart_file,
+        difficulty::{self, DifficultyAttributes},
+        graphics::{
+            self, atlas::Atlas, cursor_trail::CursorTrail, follow_points::FollowPoints, slider,
+            storyboard::StoryboardRenderer,
+        },
+        health::Health,
+        hitsound,
+        judgement::Judgement,
+        mods::Mods,
+        performance::PlayStats,
+        score::{self, Score},
+        skin::{self, SkinOverrides},
+        storyboard::Storyboard,
+        ChartProgress, GameContext, LogLayer,
+    },
     job::{spawn_job, JobHandle},
     llog, math,
 };
@@ -11,7 +39,18 @@ use super::{Screen, Updatable};
 
 pub struct PlayingResources {
     sound: kira::sound::Sound,
-    beatmap: osu_parser::Beatmap,
+    /// File name of the beatmap, its offset is saved under it.
+    beatmap_key: String,
+    beatmap_hash: String,
+    chart_info: chart::ChartInfo,
+    chart_data: chart::ChartData,
+    difficulty: DifficultyAttributes,
+    /// Skin elements found in the beatmap directory.
+    skin_overrides: SkinOverrides,
+    /// Hitsound and storyboard samples found in the beatmap directory.
+    beatmap_samples: Vec<(String, kira::sound::Sound)>,
+    background_path: Option<PathBuf>,
+    storyboard: Option<StoryboardRenderer>,
 }
 
 #[derive(Clone, Copy)]
@@ -34,63 +73,349 @@ pub enum VisibleHitObjectRef {
 pub struct VisibleHitObject {
     hitobject_index: usize,
     refs: VisibleHitObjectRef,
+    judgement: Option<Judgement>,
+    /// Index of the next slider tick, repeat or tail to play the sample of.
+    next_slider_event: usize,
+}
+
+/// Scale of the playfield projection, window pixels per playfield unit.
+const PLAYFIELD_SCALE: f32 = 1.18;
+
+/// Logical size of a hit circle texture, circle elements are drawn relative to it.
+const HIT_CIRCLE_TEXTURE_SIZE: f32 = 128.0;
+
+/// Storyboard samples further behind than this were skipped over and aren't played.
+/// Skipping jumps well past it, while samples only late because of a slow frame still play.
+const MAX_SAMPLE_LATENESS_SECONDS: f32 = 1.0;
+
+const BURST_SECONDS: f32 = 0.24;
+/// How much bigger the playfield and background get on a beat in kiai time.
+const KIAI_PULSE_SCALE: f32 = 0.02;
+/// Strength of the playfield and background pulse on every measure outside of kiai time,
+/// relative to the kiai pulse.
+const MEASURE_PULSE: f32 = 0.4;
+/// How much bigger the cursor gets on every beat.
+const CURSOR_PULSE_SCALE: f32 = 0.15;
+/// How far towards white the bursts of hits in kiai time are.
+const KIAI_FLASH: f32 = 0.6;
+const JUDGEMENT_SECONDS: f32 = 0.6;
+
+#[derive(Clone, Copy)]
+enum EffectKind {
+    /// Circle growing and fading out after being hit.
+    Burst,
+    /// Judgement popping up at the object.
+    Judgement,
+    /// Miss marker falling down from the object.
+    Miss,
+}
+
+/// Short lived sprite animating on its own after its object is gone.
+struct Effect {
+    sprite: Sprite,
+    kind: EffectKind,
+    start_time: f32,
+    base_transform: Transform,
+}
+
+impl Effect {
+    fn new(sprite: Sprite, kind: EffectKind, start_time: f32) -> Self {
+        let base_transform = *sprite.get_transform();
+        Effect {
+            sprite,
+            kind,
+            start_time,
+            base_transform,
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        match self.kind {
+            EffectKind::Burst => BURST_SECONDS,
+            EffectKind::Judgement | EffectKind::Miss => JUDGEMENT_SECONDS,
+        }
+    }
+
+    fn finished(&self, time: f32) -> bool {
+        time - self.start_time >= self.duration()
+    }
+
+    fn update(&mut self, gfx: &ogfx::GraphicsContext, time: f32) {
+        let progress = math::clamped_remap(0.0, self.duration(), 0.0, 1.0, time - self.start_time);
+        let mut transform = self.base_transform;
+        match self.kind {
+            EffectKind::Burst => {
+                let eased = 1.0 - (1.0 - progress).powi(2);
+                transform.scale *= math::lerp(1.0, 1.4, eased);
+                transform.color.w = 1.0 - progress;
+            }
+            EffectKind::Judgement => {
+                transform.scale *= math::clamped_remap(0.0, 0.15, 0.6, 1.0, progress);
+                transform.color.w = math::clamped_remap(0.5, 1.0, 1.0, 0.0, progress);
+            }
+            EffectKind::Miss => {
+                transform.position.y += math::lerp(0.0, 20.0, progress.powi(2));
+                transform.rotation = cgmath::Rad(math::lerp(0.0, 0.3, progress));
+                transform.color.w = math::clamped_remap(0.5, 1.0, 1.0, 0.0, progress);
+            }
+        }
+        *self.sprite.get_transform_mut() = transform;
+        self.sprite.update(gfx);
+    }
+}
+
+/// Reads and decodes the background image at `path`.
+fn load_background(path: &Path) -> Option<RawTextureData> {
+    let data = std::fs::read(path)
+        .map_err(|e| println!("Failed to read background '{}': {}", path.display(), e))
+        .ok()?;
+    RawTextureData::try_from_raw_image(&data)
+        .map_err(|e| println!("Failed to load background '{}': {}", path.display(), e))
+        .ok()
 }
 
 pub struct PlayingScreen {
+    /// Shown when the beatmap has no background.
     playfield: Sprite,
+    /// Beatmap background, once it is loaded.
+    background: Option<Sprite>,
+    background_job: Option<JobHandle<Option<RawTextureData>>>,
+    /// Dim the background was drawn with, in percent.
+    background_dim: u8,
+    /// Scales of the playfield and background sprites when they aren't pulsing.
+    playfield_scale: cgmath::Vector2<f32>,
+    background_scale: cgmath::Vector2<f32>,
+    /// Pulse the playfield and background were last scaled for.
+    backdrop_pulse: f32,
+    cursor: Sprite,
+    /// Scale of the cursor between beats.
+    cursor_scale: cgmath::Vector2<f32>,
+    cursor_trail: CursorTrail,
     tinted: SlotMap<slotmap::DefaultKey, Sprite>,
     overlay: SlotMap<slotmap::DefaultKey, Sprite>,
     slider_bodies: SlotMap<slotmap::DefaultKey, slider::Slider>,
     approach: SlotMap<slotmap::DefaultKey, Sprite>,
+    numbers: SpriteBatch,
+    follow_points: FollowPoints,
 
     visible_objects: SlotMap<slotmap::DefaultKey, VisibleHitObject>,
+    combo_numbers: SecondaryMap<slotmap::DefaultKey, Vec<SpriteIdx>>,
+    /// Times of the ticks, repeats and tail of visible sliders.
+    slider_events: SecondaryMap<slotmap::DefaultKey, Vec<(f32, SliderEvent)>>,
+    effects: Vec<Effect>,
+    /// Song of the chart, removed from the audio manager when leaving the screen.
+    song: SoundHandle,
+    samples: SampleBank,
+    storyboard: Option<StoryboardRenderer>,
+    /// Index of the next storyboard sample to play.
+    next_storyboard_sample: usize,
+    /// Atlases used for this play, the active skin's with the beatmap's elements layered over them.
+    hitobject_atlas: Atlas<String>,
+    number_atlas: Atlas<u8>,
+    /// Diameter of a hit circle in playfield units.
+    circle_size: f32,
     #[allow(dead_code)]
     playfield_projection_buffer: Buffer,
     playfield_projection_binding: wgpu::BindGroup,
 
     end_time: f32,
+    beatmap_key: String,
+    beatmap_hash: String,
+    /// Difficulty of the chart, the performance of the play is calculated from it.
+    difficulty: DifficultyAttributes,
+    /// When the play started, for the time spent playing.
+    started_at: Instant,
+    /// Song time of the previous update, health drains over the time since then.
+    last_update_time: f32,
+}
+
+/// Why the chart to play couldn't be loaded.
+#[derive(Debug)]
+pub enum PlayingLoadError {
+    Read(PathBuf, std::io::Error),
+    Chart(PathBuf, chart_file::ChartFileError),
+    /// The chart has no objects to play.
+    Empty(PathBuf),
+    Audio(PathBuf, String),
+}
+
+impl std::fmt::Display for PlayingLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayingLoadError::Read(path, e) => {
+                write!(f, "Failed to read chart '{}': {}", path.display(), e)
+            }
+            PlayingLoadError::Chart(path, e) => {
+                write!(f, "Failed to load chart '{}': {}", path.display(), e)
+            }
+            PlayingLoadError::Empty(path) => {
+                write!(f, "Chart '{}' has no objects", path.display())
+            }
+            PlayingLoadError::Audio(path, e) => {
+                write!(f, "Failed to load song '{}': {}", path.display(), e)
+            }
+        }
+    }
 }
 
 impl Screen for PlayingScreen {
     type LoadingResource = PlayingResources;
+    type LoadingError = PlayingLoadError;
 
-    fn load(_ctx: std::sync::Arc<GameContext>) -> JobHandle<PlayingResources> {
-        spawn_job(|| {
-            let sound = kira::sound::Sound::from_file(
-                "Mynarco Addiction.mp3",
-                kira::sound::SoundSettings::default(),
-            )
-            .unwrap();
-            let beatmap = osu_parser::load_file(
-                "positive MAD-crew - Mynarco Addiction (Okoratu) [Ex].osu",
-                //"positive MAD-crew - Mynarco Addiction (Okoratu) [test].osu",
-                //"positive MAD-crew - Mynarco Addiction (Okoratu) [corner].osu",
-                osu_parser::BeatmapParseOptions::default(),
-            )
-            .unwrap();
-            PlayingResources { sound, beatmap }
+    fn load(
+        ctx: std::sync::Arc<GameContext>,
+    ) -> JobHandle<Result<PlayingResources, PlayingLoadError>> {
+        spawn_job(move || {
+            // A .osu file or a native chart file
+            let beatmap_path = ctx.settings.lock().unwrap().beatmap.clone();
+            let beatmap_path = beatmap_path.as_path();
+            let beatmap_content = std::fs::read_to_string(beatmap_path)
+                .map_err(|e| PlayingLoadError::Read(beatmap_path.to_owned(), e))?;
+            let (chart_info, mut chart_data) =
+                chart_file::load_chart(beatmap_path, &beatmap_content)
+                    .map_err(|e| PlayingLoadError::Chart(beatmap_path.to_owned(), e))?;
+            if chart_data.objects.is_empty() {
+                return Err(PlayingLoadError::Empty(beatmap_path.to_owned()));
+            }
+            let beatmap_hash = score::beatmap_hash(&beatmap_content);
+
+            let directory = match beatmap_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let audio_path = directory.join(&chart_info.audio_file);
+            let sound =
+                kira::sound::Sound::from_file(&audio_path, kira::sound::SoundSettings::default())
+                    .map_err(|e| PlayingLoadError::Audio(audio_path.clone(), e.to_string()))?;
+            let mut storyboard = Storyboard::default();
+            // The storyboard shared by every difficulty of the beatmap set
+            if let Some(storyboard_path) = std::fs::read_dir(directory)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .find(|path| path.extension().map_or(false, |ext| ext == "osb"))
+            {
+                match std::fs::read_to_string(&storyboard_path) {
+                    Ok(content) => {
+                        storyboard = Storyboard::parse(&content);
+                        chart_data
+                            .storyboard_samples
+                            .extend(hitsound::parse_storyboard_samples(&content));
+                        chart_data
+                            .storyboard_samples
+                            .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+                    }
+                    Err(e) => println!(
+                        "Failed to read storyboard '{}': {}",
+                        storyboard_path.display(),
+                        e
+                    ),
+                }
+            }
+
+            // Elements of the difficulty are drawn over the shared ones
+            storyboard.extend(Storyboard::parse(&beatmap_content));
+            let storyboard = if storyboard.is_empty() {
+                None
+            } else {
+                let textures = graphics::storyboard::load_textures(&storyboard, directory);
+                Some(StoryboardRenderer::new(&ctx.gfx, storyboard, textures))
+            };
+
+            let ignore_beatmap_skin = ctx.ignore_beatmap_skins.load(Ordering::Relaxed);
+            let skin_overrides = if ignore_beatmap_skin {
+                SkinOverrides::default()
+            } else {
+                SkinOverrides::load(directory)
+            };
+            // Keysounds and storyboard samples are part of the beatmap, not of its skin
+            let sample_names = chart_data.beatmap_sample_names();
+            let (beatmap_samples, sample_errors) = audio::load_beatmap_samples(
+                directory,
+                sample_names
+                    .iter()
+                    .map(|name| name.as_str())
+                    .filter(|name| !(ignore_beatmap_skin && audio::is_skin_sample(name))),
+            );
+            for e in sample_errors {
+                println!("{}", e);
+            }
+
+            let beatmap_key = beatmap_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+
+            let difficulty = difficulty::calculate(&chart_info, &chart_data);
+
+            let background_path = chart_data
+                .background
+                .as_ref()
+                .map(|background| directory.join(background));
+
+            Ok(PlayingResources {
+                sound,
+                beatmap_key,
+                beatmap_hash,
+                background_path,
+                chart_info,
+                chart_data,
+                difficulty,
+                skin_overrides,
+                beatmap_samples,
+                storyboard,
+            })
         })
     }
 
     fn new(ctx: &GameContext, loading_res: PlayingResources) -> Self {
-        let mut sound_handle = ctx
+        let mut song = ctx
             .audio
             .lock()
             .unwrap()
             .add_sound(loading_res.sound)
             .unwrap();
-        let instance_handle = sound_handle
-            .play(kira::instance::InstanceSettings::default().playback_rate(1.0))
+        let instance_handle = song
+            .play(
+                kira::instance::InstanceSettings::default()
+                    .playback_rate(1.0)
+                    .volume(ctx.settings.lock().unwrap().music_volume() as f64),
+            )
             .unwrap();
 
         ctx.set_song(instance_handle);
-        let (chart_info, chart_data) = chart::load_osu_beatmap(&loading_res.beatmap);
+        let offset = ctx.offsets.lock().unwrap().total(&loading_res.beatmap_key);
+        let PlayingResources {
+            beatmap_key,
+            beatmap_hash,
+            chart_info,
+            chart_data,
+            difficulty,
+            skin_overrides,
+            beatmap_samples,
+            background_path,
+            storyboard,
+            ..
+        } = loading_res;
+
+        // Decoding a large background takes a while, play starts without it
+        let background_job = background_path.map(|path| spawn_job(move || load_background(&path)));
+
+        let start_time =
+            chart_info.start_time(chart_data.objects.first().map_or(0.0, |obj| obj.time));
+        if let Some(clock) = ctx.clock_mut().as_mut() {
+            clock.set_offset(offset as f64);
+            clock.seek(start_time as f64);
+        }
 
         let end_time = chart_data
             .objects
             .iter()
             .map(|el| el.end_time())
             .max_by(|a, b| a.partial_cmp(b).unwrap())
+            // Empty charts are rejected when loading
             .unwrap();
 
         ctx.set_chart_info(chart_info);
@@ -98,14 +423,27 @@ impl Screen for PlayingScreen {
         ctx.set_chart_progress(ChartProgress {
             pending_start: 0,
             combo: 0,
+            score: 0,
+            max_combo: 0,
+            judgements: Default::default(),
             progress: 0.0,
+            health: Health::default(),
+            break_remaining: None,
+            skip_target: None,
+            kiai_pulse: 0.0,
+            beat: None,
         });
 
         println!("Playing chart '{:#?}'", ctx.chart().as_ref().unwrap());
+        ctx.set_score(None);
 
         let game_resources = ctx.game_resources.lock().unwrap();
         let game_resources = game_resources.as_ref().unwrap();
 
+        let playfield_scale = cgmath::vec2(
+            ctx.gfx.dimensions.x as f32 / game_resources.playfield.size.x as f32,
+            ctx.gfx.dimensions.y as f32 / game_resources.playfield.size.y as f32,
+        );
         let playfield = Sprite::new(
             &ctx.gfx,
             game_resources.playfield.clone(),
@@ -115,12 +453,10 @@ impl Screen for PlayingScreen {
                     ctx.gfx.dimensions.y as f32 / 2.0,
                 ),
                 layer: 0,
-                scale: cgmath::vec2(
-                    ctx.gfx.dimensions.x as f32 / 2732.0,
-                    ctx.gfx.dimensions.y as f32 / 1572.0,
-                ),
+                scale: playfield_scale,
                 rotation: cgmath::Rad(0.0),
                 source: Rect::new(0.0, 0.0, 1.0, 1.0),
+                ..Default::default()
             },
         );
 
@@ -131,7 +467,7 @@ impl Screen for PlayingScreen {
                     ctx.gfx.dimensions.x as f32 / 2.0,
                     ctx.gfx.dimensions.y as f32 / 2.0,
                 ),
-                scale: cgmath::vec2(1.18, 1.18),
+                scale: cgmath::vec2(PLAYFIELD_SCALE, PLAYFIELD_SCALE),
                 ..Default::default()
             })],
             wgpu::BufferUsages::UNIFORM,
@@ -148,40 +484,378 @@ impl Screen for PlayingScreen {
                     label: None,
                 });
 
+        let (hitobject_atlas, number_atlas) = if skin_overrides.is_empty() {
+            (
+                game_resources.hitobject_atlas.clone(),
+                game_resources.number_atlas.clone(),
+            )
+        } else {
+            println!("Using skin elements from the beatmap directory");
+            let SkinOverrides {
+                hitobject: hitobject_overrides,
+                numbers: number_overrides,
+            } = skin_overrides;
+            let mut hitobject_textures = game_resources.skin.hitobject_textures();
+            skin::layer_textures(&mut hitobject_textures, hitobject_overrides);
+            let mut number_textures = game_resources.skin.number_textures();
+            skin::layer_textures(&mut number_textures, number_overrides);
+            (
+                Atlas::new(
+                    &ctx.gfx,
+                    &hitobject_textures
+                        .iter()
+                        .map(|(key, texture)| (key, texture))
+                        .collect::<Vec<_>>(),
+                    wgpu::TextureFormat::Rgba8Unorm,
+                ),
+                Atlas::new(
+                    &ctx.gfx,
+                    &number_textures
+                        .iter()
+                        .map(|(key, texture)| (key, texture))
+                        .collect::<Vec<_>>(),
+                    wgpu::TextureFormat::Rgba8Unorm,
+                ),
+            )
+        };
+
+        let mut samples = game_resources.samples.clone();
+        samples.set_volume(ctx.settings.lock().unwrap().effects_volume());
+        for e in samples.add_beatmap(&mut ctx.audio.lock().unwrap(), beatmap_samples) {
+            println!("{}", e);
+        }
+
+        let numbers = SpriteBatch::new(&ctx.gfx, number_atlas.texture.clone(), 256);
+        let follow_points = FollowPoints::new(
+            &ctx.gfx,
+            ctx.chart_data().as_ref().unwrap(),
+            &hitobject_atlas,
+            "followpoint",
+        );
+
+        let cursor_scale = cgmath::vec2(1.0, 1.0) * ctx.player_settings().cursor_size
+            / game_resources.cursor.scale_factor;
+        let cursor = Sprite::new(
+            &ctx.gfx,
+            game_resources.cursor.clone(),
+            Transform {
+                position: cgmath::vec2(-100.0, -100.0),
+                scale: cursor_scale,
+                ..Default::default()
+            },
+        );
+        let cursor_trail = CursorTrail::new(&ctx.gfx, game_resources.cursor.clone());
+
         PlayingScreen {
             playfield,
+            background: None,
+            background_job,
+            background_dim: 0,
+            playfield_scale,
+            background_scale: cgmath::vec2(1.0, 1.0),
+            backdrop_pulse: 0.0,
+            cursor,
+            cursor_scale,
+            cursor_trail,
 
             tinted: SlotMap::new(),
             overlay: SlotMap::new(),
             slider_bodies: SlotMap::new(),
             approach: SlotMap::new(),
+            numbers,
+            follow_points,
 
             visible_objects: SlotMap::new(),
+            combo_numbers: SecondaryMap::new(),
+            slider_events: SecondaryMap::new(),
+            effects: Vec::new(),
+            song,
+            samples,
+            storyboard,
+            next_storyboard_sample: 0,
+            circle_size: ctx.chart().as_ref().unwrap().modifiers.circle_radius() * 2.0,
+            hitobject_atlas,
+            number_atlas,
 
             playfield_projection_buffer,
             playfield_projection_binding,
 
             end_time,
+            beatmap_key,
+            beatmap_hash,
+            difficulty,
+            started_at: Instant::now(),
+            last_update_time: start_time,
         }
     }
 }
 
+impl PlayingScreen {
+    /// Converts a position in the window to a position on the playfield.
+    fn to_playfield(
+        &self,
+        gfx: &ogfx::GraphicsContext,
+        point: cgmath::Vector2<f32>,
+    ) -> cgmath::Vector2<f32> {
+        (point - gfx.dimensions.cast::<f32>().unwrap() / 2.0) / PLAYFIELD_SCALE
+    }
+
+    /// Playfield units per logical pixel of the textures drawn relative to the hit circle.
+    fn circle_texture_scale(&self) -> f32 {
+        self.circle_size / HIT_CIRCLE_TEXTURE_SIZE
+    }
+
+    /// Scale of a sprite showing the hit object atlas `entry` at the current circle size.
+    fn circle_element_scale(&self, entry: &str) -> cgmath::Vector2<f32> {
+        self.hitobject_atlas
+            .scale_for(self.hitobject_atlas.logical_size(entry) * self.circle_texture_scale())
+    }
+
+    fn judgement_sprite(
+        &self,
+        ctx: &GameContext,
+        atlas: &Atlas<String>,
+        judgement: Judgement,
+        position: cgmath::Vector2<f32>,
+    ) -> Sprite {
+        let name = judgement.texture_name();
+        let size = atlas.logical_size(name) * chart::OSU_PIXEL_SCALE;
+        Sprite::new(
+            &ctx.gfx,
+            atlas.texture.clone(),
+            Transform {
+                position,
+                scale: atlas.scale_for(size),
+                source: atlas.sub_textures[name].cast(),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn on_cursor_moved(&mut self, ctx: &GameContext, cursor: cgmath::Vector2<f32>) {
+        self.cursor.get_transform_mut().position = cursor;
+        self.cursor.update(&ctx.gfx);
+    }
+
+    /// Stops the song and removes it and the samples of the beatmap from the audio manager,
+    /// before leaving the screen.
+    pub fn close(&mut self, ctx: &GameContext) {
+        ctx.stop_song();
+        if let Err(e) = ctx.audio.lock().unwrap().remove_sound(self.song.id()) {
+            println!("Failed to remove song: {}", e);
+        }
+        for e in self.samples.remove_beatmap(&mut ctx.audio.lock().unwrap()) {
+            println!("{}", e);
+        }
+    }
+
+    /// Changes the offset of the beatmap being played by `delta` seconds and saves it.
+    pub fn adjust_offset(&mut self, ctx: &GameContext, delta: f32) {
+        let mut offsets = ctx.offsets.lock().unwrap();
+        let offset = offsets.beatmap(&self.beatmap_key) + delta;
+        // Keep whole milliseconds so repeated steps don't accumulate float error
+        offsets.set_beatmap(&self.beatmap_key, (offset * 1000.0).round() / 1000.0);
+        offsets.save();
+        println!(
+            "Beatmap offset: {}ms",
+            (offsets.beatmap(&self.beatmap_key) * 1000.0).round()
+        );
+        if let Some(clock) = ctx.clock_mut().as_mut() {
+            clock.set_offset(offsets.total(&self.beatmap_key) as f64);
+        }
+    }
+
+    /// Creates the background sprite once its job is done and keeps its dim up to date.
+    fn update_background(&mut self, ctx: &GameContext) {
+        if let Some(job) = &mut self.background_job {
+            if let Some(texture_data) = job.poll().unwrap() {
+                self.background_job = None;
+                self.background = texture_data.map(|texture_data| {
+                    let texture = ogfx::Texture::from_texture_data(
+                        &ctx.gfx,
+                        &texture_data,
+                        wgpu::TextureFormat::Rgba8Unorm,
+                    );
+                    let dimensions = ctx.gfx.dimensions.cast::<f32>().unwrap();
+                    let scale =
+                        math::letterbox_scale(texture_data.size.cast().unwrap(), dimensions);
+                    self.background_scale = cgmath::vec2(scale, scale);
+                    Sprite::new(
+                        &ctx.gfx,
+                        std::sync::Arc::new(texture),
+                        Transform {
+                            position: dimensions / 2.0,
+                            scale: self.background_scale * self.pulse_scale(),
+                            ..Default::default()
+                        },
+                    )
+                });
+                // Force the dim to be applied to the new sprite
+                self.background_dim = u8::MAX;
+            }
+        }
+
+        let dim = ctx.background_dim.load(Ordering::Relaxed);
+        if let Some(background) = &mut self.background {
+            if dim != self.background_dim {
+                let brightness = 1.0 - dim as f32 / 100.0;
+                background.get_transform_mut().color =
+                    cgmath::vec4(brightness, brightness, brightness, 1.0);
+                background.update(&ctx.gfx);
+                self.background_dim = dim;
+            }
+        }
+    }
+
+    /// Scale of the playfield and background relative to their resting size for the current pulse.
+    fn pulse_scale(&self) -> f32 {
+        1.0 + KIAI_PULSE_SCALE * self.backdrop_pulse
+    }
+
+    /// Pulses the playfield and background along with the beat.
+    fn update_backdrop_pulse(&mut self, ctx: &GameContext, pulse: f32) {
+        if pulse == self.backdrop_pulse {
+            return;
+        }
+        self.backdrop_pulse = pulse;
+        let scale = self.pulse_scale();
+        self.playfield.get_transform_mut().scale = self.playfield_scale * scale;
+        self.playfield.update(&ctx.gfx);
+        if let Some(background) = &mut self.background {
+            background.get_transform_mut().scale = self.background_scale * scale;
+            background.update(&ctx.gfx);
+        }
+    }
+
+    /// Judges the earliest object that hasn't been judged yet if `cursor` is on top of it.
+    pub fn on_hit(&mut self, ctx: &GameContext, cursor: cgmath::Vector2<f32>) {
+        if ctx.clock().as_ref().map_or(true, |clock| clock.is_paused()) {
+            return;
+        }
+        let song_position = ctx.song_time();
+        let chart = ctx.chart();
+        let chart_data = ctx.chart_data();
+        let chart_progress = ctx.chart_progress();
+        if song_position.is_none()
+            || chart.is_none()
+            || chart_data.is_none()
+            || chart_progress.is_none()
+        {
+            return;
+        }
+        let song_position = song_position.unwrap();
+        let chart = chart.as_ref().unwrap();
+        let chart_data = chart_data.as_ref().unwrap();
+        let mut chart_progress = chart_progress.unwrap();
+
+        let position = self.to_playfield(&ctx.gfx, cursor);
+        let circle_radius = self.circle_size / 2.0;
+        if let Some((_, visible_hitobject)) = self
+            .visible_objects
+            .iter_mut()
+            .filter(|(_, obj)| obj.judgement.is_none())
+            .min_by_key(|(_, obj)| obj.hitobject_index)
+        {
+            let hitobject = &chart_data.objects[visible_hitobject.hitobject_index];
+            if (hitobject.position - position).magnitude() > circle_radius {
+                return;
+            }
+            if let Some(judgement) = chart
+                .modifiers
+                .hit_windows()
+                .judge(song_position - hitobject.time)
+            {
+                llog!(
+                    ctx,
+                    LogLayer::Playfield,
+                    "Hit {:?} (time={}ms) at {}",
+                    judgement,
+                    hitobject.time * 1000.0,
+                    song_position
+                );
+                visible_hitobject.judgement = Some(judgement);
+                let samples = chart_data
+                    .hitsounds
+                    .resolve(hitobject.head_samples(), hitobject.time);
+                for sample in &samples {
+                    if let Err(e) = self.samples.play(sample) {
+                        llog!(ctx, LogLayer::Audio, "{}", e);
+                    }
+                }
+                if let Some(storyboard) = &mut self.storyboard {
+                    storyboard.trigger_hitsound(&samples, song_position);
+                }
+                chart_progress.judge(judgement);
+                chart_progress
+                    .health
+                    .judge(judgement, chart.modifiers.drain_rate);
+                ctx.set_chart_progress(chart_progress);
+            }
+        }
+    }
+}
+
+/// Lays out the digits of `number` centered on `position`, `scale` units per logical texture pixel.
+/// Neighbouring digits overlap by `overlap` logical pixels.
+fn combo_number_transforms(
+    atlas: &Atlas<u8>,
+    number: u32,
+    position: cgmath::Vector2<f32>,
+    scale: f32,
+    overlap: f32,
+) -> Vec<Transform> {
+    let digits = number
+        .to_string()
+        .bytes()
+        .map(|c| c - b'0')
+        .collect::<Vec<_>>();
+    let sizes = digits
+        .iter()
+        .map(|digit| (atlas.logical_size(digit) * scale, overlap * scale))
+        .collect::<Vec<_>>();
+    let total_width: f32 = sizes
+        .iter()
+        .map(|(size, overlap)| size.x - overlap)
+        .sum::<f32>()
+        + sizes.last().map(|(_, overlap)| *overlap).unwrap_or(0.0);
+
+    let mut x = position.x - total_width / 2.0;
+    digits
+        .iter()
+        .zip(sizes)
+        .map(|(digit, (size, overlap))| {
+            let transform = Transform {
+                position: cgmath::vec2(x + size.x / 2.0, position.y),
+                scale: atlas.scale_for(size),
+                source: atlas.sub_textures[digit].cast(),
+                ..Default::default()
+            };
+            x += size.x - overlap;
+            transform
+        })
+        .collect()
+}
+
 impl Updatable for PlayingScreen {
     fn update(&mut self, ctx: &GameContext) {
-        let song = ctx.song();
+        self.update_background(ctx);
+
+        let song_position = ctx.song_time();
         let chart = ctx.chart();
         let chart_data = ctx.chart_data();
         let chart_progress = ctx.chart_progress();
-        if song.is_none() || chart.is_none() || chart_data.is_none() || chart_progress.is_none() {
+        if song_position.is_none()
+            || chart.is_none()
+            || chart_data.is_none()
+            || chart_progress.is_none()
+        {
             return;
         }
-        let song = song.unwrap();
+        let song_position = song_position.unwrap();
         let chart = chart.as_ref().unwrap();
         let chart_data = chart_data.as_ref().unwrap();
         let mut chart_progress = chart_progress.unwrap();
 
-        let song_position = song.position() as f32;
-
         let display_objects = {
             if let Some(start_idx) = chart_data.objects[chart_progress.pending_start..]
                 .iter()
@@ -226,11 +900,58 @@ impl Updatable for PlayingScreen {
             llog!(ctx, LogLayer::Playfield, "acitve_object (index {})", i);
         }
 
+        let game_resources = ctx.game_resources.lock().unwrap();
+        let game_resources = game_resources.as_ref().unwrap();
+
+        let hit_windows = chart.modifiers.hit_windows();
+        let approach_scale = self.circle_element_scale("approach");
         let mut to_remove = vec![];
 
-        for (idx, visible_hitobject) in self.visible_objects.iter() {
+        for (idx, visible_hitobject) in self.visible_objects.iter_mut() {
             let hitobject = &chart_data.objects[visible_hitobject.hitobject_index];
-            if song_position > hitobject.end_time() {
+            if visible_hitobject.judgement.is_none()
+                && song_position > hitobject.time + hit_windows.meh
+            {
+                visible_hitobject.judgement = Some(Judgement::Miss);
+                chart_progress.judge(Judgement::Miss);
+                chart_progress
+                    .health
+                    .judge(Judgement::Miss, chart.modifiers.drain_rate);
+            }
+            if let (Some(events), chart::HitObjectData::Slider(slider)) =
+                (self.slider_events.get(idx), &hitobject.data)
+            {
+                let head_hit = !matches!(visible_hitobject.judgement, None | Some(Judgement::Miss));
+                while let Some(&(time, event)) = events.get(visible_hitobject.next_slider_event) {
+                    if time > song_position {
+                        break;
+                    }
+                    if head_hit {
+                        chart_progress.add_combo();
+                        let samples = match event {
+                            SliderEvent::Tick => {
+                                vec![chart_data.hitsounds.resolve_tick(&hitobject.samples, time)]
+                            }
+                            SliderEvent::Edge(edge) => {
+                                let samples =
+                                    slider.edge_samples.get(edge).unwrap_or(&hitobject.samples);
+                                chart_data.hitsounds.resolve(samples, time)
+                            }
+                        };
+                        for sample in &samples {
+                            if let Err(e) = self.samples.play(sample) {
+                                llog!(ctx, LogLayer::Audio, "{}", e);
+                            }
+                        }
+                    }
+                    visible_hitobject.next_slider_event += 1;
+                }
+            }
+            let finished = match visible_hitobject.refs {
+                VisibleHitObjectRef::Slider { .. } => song_position > hitobject.end_time(),
+                _ => visible_hitobject.judgement.is_some(),
+            };
+            if finished {
                 llog!(
                     ctx,
                     LogLayer::Playfield,
@@ -242,34 +963,85 @@ impl Updatable for PlayingScreen {
                 to_remove.push(idx);
                 continue;
             }
-            if let VisibleHitObjectRef::Circle { approach, .. }
-            | VisibleHitObjectRef::Slider { approach, .. } = visible_hitobject.refs
+
+            let appear_time = hitobject.time - chart.modifiers.approach_seconds();
+            let alpha = math::clamped_remap(
+                appear_time,
+                appear_time + chart.modifiers.fade_in_seconds(),
+                0.0,
+                1.0,
+                song_position,
+            );
+            // The head of a slider disappears once it has been judged
+            let head_alpha = if visible_hitobject.judgement.is_some() {
+                0.0
+            } else {
+                alpha
+            };
+            if let VisibleHitObjectRef::Circle {
+                tinted,
+                overlay,
+                approach,
+            }
+            | VisibleHitObjectRef::Slider {
+                tinted,
+                overlay,
+                approach,
+                ..
+            } = visible_hitobject.refs
             {
                 let scale = math::clamped_remap(
                     hitobject.time - chart.modifiers.approach_seconds(),
                     hitobject.time,
-                    0.5,
-                    0.125,
+                    4.0,
+                    1.0,
                     song_position,
                 );
-                self.approach[approach].get_transform_mut().scale = cgmath::vec2(scale, scale);
-                self.approach[approach].update(&ctx.gfx);
+                self.approach[approach].get_transform_mut().scale = approach_scale * scale;
+                for sprite in [
+                    &mut self.tinted[tinted],
+                    &mut self.overlay[overlay],
+                    &mut self.approach[approach],
+                ] {
+                    if sprite.get_transform().color.w != head_alpha {
+                        sprite.set_alpha(head_alpha);
+                    }
+                    sprite.update(&ctx.gfx);
+                }
+            }
+            if let VisibleHitObjectRef::Slider { slider, .. } = visible_hitobject.refs {
+                let slider = &mut self.slider_bodies[slider];
+                let mut color = slider.color();
+                if color.w != alpha {
+                    color.w = alpha;
+                    slider.set_color(&ctx.gfx, color);
+                }
+            }
+            for &digit in &self.combo_numbers[idx] {
+                if self.numbers.get(digit).map(|t| t.color.w) != Some(head_alpha) {
+                    self.numbers
+                        .set_sprite_color(digit, cgmath::vec4(1.0, 1.0, 1.0, head_alpha));
+                }
             }
         }
 
+        let in_kiai = chart_data.is_kiai(song_position);
         for idx in to_remove {
-            chart_progress.combo += 1;
-
             let visible_hitobject = self.visible_objects.remove(idx).unwrap();
-            match visible_hitobject.refs {
+            let hitobject = &chart_data.objects[visible_hitobject.hitobject_index];
+            let judgement = visible_hitobject.judgement.unwrap_or(Judgement::Miss);
+            self.slider_events.remove(idx);
+            for digit in self.combo_numbers.remove(idx).unwrap_or_default() {
+                self.numbers.remove(digit);
+            }
+            let (tinted, overlay) = match visible_hitobject.refs {
                 VisibleHitObjectRef::Circle {
                     tinted,
                     overlay,
                     approach,
                 } => {
-                    self.tinted.remove(tinted);
-                    self.overlay.remove(overlay);
                     self.approach.remove(approach);
+                    (tinted, overlay)
                 }
                 VisibleHitObjectRef::Slider {
                     tinted,
@@ -277,18 +1049,45 @@ impl Updatable for PlayingScreen {
                     approach,
                     slider,
                 } => {
-                    self.tinted.remove(tinted);
-                    self.overlay.remove(overlay);
                     self.approach.remove(approach);
                     self.slider_bodies.remove(slider);
+                    (tinted, overlay)
                 }
                 _ => panic!(),
+            };
+            let end_position = hitobject.end_position();
+            for mut sprite in [
+                self.tinted.remove(tinted).unwrap(),
+                self.overlay.remove(overlay).unwrap(),
+            ] {
+                if judgement != Judgement::Miss {
+                    let transform = sprite.get_transform_mut();
+                    transform.position = end_position;
+                    transform.color.w = 1.0;
+                    // Hits flash white in kiai time
+                    if in_kiai {
+                        for channel in 0..3 {
+                            transform.color[channel] =
+                                math::lerp(transform.color[channel], 1.0, KIAI_FLASH);
+                        }
+                    }
+                    self.effects
+                        .push(Effect::new(sprite, EffectKind::Burst, song_position));
+                }
             }
+            let judgement_sprite =
+                self.judgement_sprite(ctx, &self.hitobject_atlas, judgement, end_position);
+            self.effects.push(Effect::new(
+                judgement_sprite,
+                if judgement == Judgement::Miss {
+                    EffectKind::Miss
+                } else {
+                    EffectKind::Judgement
+                },
+                song_position,
+            ));
         }
 
-        let game_resources = ctx.game_resources.lock().unwrap();
-        let game_resources = game_resources.as_ref().unwrap();
-
         for display_object in display_objects {
             let hitobject = &chart_data.objects[display_object];
             llog!(
@@ -301,53 +1100,76 @@ impl Updatable for PlayingScreen {
             );
             let trans = Transform {
                 position: cgmath::vec2(hitobject.position.x, hitobject.position.y),
-                scale: cgmath::vec2(0.125, 0.125),
                 ..Default::default()
             };
             let tinted = self.tinted.insert(Sprite::new(
                 &ctx.gfx,
-                game_resources.hitobject_atlas.texture.clone(),
+                self.hitobject_atlas.texture.clone(),
                 Transform {
-                    source: game_resources.hitobject_atlas.sub_textures["tinted"].cast(),
+                    scale: self.circle_element_scale("tinted"),
+                    source: self.hitobject_atlas.sub_textures["tinted"].cast(),
+                    color: chart_data
+                        .combo_colour(hitobject, &game_resources.skin.config.combo_colours)
+                        .extend(1.0),
                     ..trans
                 },
             ));
             let overlay = self.overlay.insert(Sprite::new(
                 &ctx.gfx,
-                game_resources.hitobject_atlas.texture.clone(),
+                self.hitobject_atlas.texture.clone(),
                 Transform {
-                    source: game_resources.hitobject_atlas.sub_textures["overlay"].cast(),
+                    scale: self.circle_element_scale("overlay"),
+                    source: self.hitobject_atlas.sub_textures["overlay"].cast(),
                     ..trans
                 },
             ));
             let approach = self.approach.insert(Sprite::new(
                 &ctx.gfx,
-                game_resources.hitobject_atlas.texture.clone(),
+                self.hitobject_atlas.texture.clone(),
                 Transform {
-                    source: game_resources.hitobject_atlas.sub_textures["approach"].cast(),
+                    scale: self.circle_element_scale("approach") * 4.0,
+                    source: self.hitobject_atlas.sub_textures["approach"].cast(),
                     ..trans
                 },
             ));
-            match &hitobject.data {
-                chart::HitObjectData::Circle => {
-                    self.visible_objects.insert(VisibleHitObject {
-                        hitobject_index: display_object,
-                        refs: VisibleHitObjectRef::Circle {
-                            tinted,
-                            overlay,
-                            approach,
-                        },
-                    });
-                }
+            let digits = combo_number_transforms(
+                &self.number_atlas,
+                hitobject.combo_number,
+                hitobject.position,
+                self.circle_texture_scale(),
+                game_resources.skin.config.hit_circle_overlap as f32,
+            )
+            .into_iter()
+            .map(|transform| self.numbers.insert(transform))
+            .collect::<Vec<_>>();
+            let visible_idx = match &hitobject.data {
+                chart::HitObjectData::Circle => self.visible_objects.insert(VisibleHitObject {
+                    hitobject_index: display_object,
+                    refs: VisibleHitObjectRef::Circle {
+                        tinted,
+                        overlay,
+                        approach,
+                    },
+                    judgement: None,
+                    next_slider_event: 0,
+                }),
                 chart::HitObjectData::Slider(slider) => {
-                    let slider = self.slider_bodies.insert(slider::Slider::new(
+                    let mut slider = slider::Slider::new(
                         &ctx.gfx,
                         slider.curve_type,
                         hitobject.position,
                         &slider.control_points,
-                        &game_resources.hitobject_atlas,
+                        self.circle_size,
+                        &self.hitobject_atlas,
                         "track",
-                    ));
+                    );
+                    slider.set_color(
+                        &ctx.gfx,
+                        chart_data
+                            .combo_colour(hitobject, &game_resources.skin.config.combo_colours)
+                            .extend(1.0),
+                    );
+                    let slider = self.slider_bodies.insert(slider);
                     self.visible_objects.insert(VisibleHitObject {
                         hitobject_index: display_object,
                         refs: VisibleHitObjectRef::Slider {
@@ -356,12 +1178,103 @@ impl Updatable for PlayingScreen {
                             approach,
                             slider,
                         },
-                    });
+                        judgement: None,
+                        next_slider_event: 0,
+                    })
                 }
+            };
+            self.combo_numbers.insert(visible_idx, digits);
+            if let chart::HitObjectData::Slider(slider) = &hitobject.data {
+                self.slider_events
+                    .insert(visible_idx, slider.events(hitobject.time));
             }
         }
+        self.numbers.update(&ctx.gfx);
+
+        // Every object has been judged and is gone
+        let finished = ctx.score().is_none()
+            && chart_progress.pending_start == chart_data.objects.len()
+            && self.visible_objects.is_empty();
+        if finished {
+            let score = Score::new(
+                self.beatmap_hash.clone(),
+                ctx.profiles.lock().unwrap().active().name.clone(),
+                chart_progress.score,
+                self.started_at.elapsed().as_secs_f32(),
+                &self.difficulty,
+                PlayStats {
+                    judgements: chart_progress.judgements,
+                    max_combo: chart_progress.max_combo,
+                    mods: Mods::default(),
+                },
+            );
+            println!(
+                "Finished with {} points, {:.2}% accuracy, {}x combo and {:.0}pp",
+                score.total_score,
+                score.accuracy * 100.0,
+                score.max_combo,
+                score.performance.total
+            );
+            ctx.scores.lock().unwrap().add(score.clone());
+            ctx.set_score(Some(score));
+        }
 
-        chart_progress.progress = song_position / self.end_time;
+        while let Some(sample) = chart_data
+            .storyboard_samples
+            .get(self.next_storyboard_sample)
+        {
+            if sample.time > song_position {
+                break;
+            }
+            if song_position - sample.time < MAX_SAMPLE_LATENESS_SECONDS {
+                if let Err(e) = self.samples.play_beatmap_file(&sample.path, sample.volume) {
+                    llog!(ctx, LogLayer::Audio, "{}", e);
+                }
+            }
+            self.next_storyboard_sample += 1;
+        }
+
+        chart_progress.beat = chart_data.beats.beat_at(song_position);
+        chart_progress.kiai_pulse = chart_data.kiai_pulse(song_position);
+        // Every beat in kiai time, every measure and more subtly otherwise
+        let measure_pulse = chart_progress
+            .beat
+            .map_or(0.0, |beat| MEASURE_PULSE * beat.measure_pulse());
+        self.update_backdrop_pulse(ctx, chart_progress.kiai_pulse.max(measure_pulse));
+        let beat_pulse = chart_progress.beat.map_or(0.0, |beat| beat.pulse());
+        self.cursor.get_transform_mut().scale =
+            self.cursor_scale * (1.0 + CURSOR_PULSE_SCALE * beat_pulse);
+        self.cursor.update(&ctx.gfx);
+        self.cursor_trail
+            .update(&ctx.gfx, self.cursor.get_transform(), beat_pulse);
+
+        if let Some(storyboard) = &mut self.storyboard {
+            storyboard.update(&ctx.gfx, song_position, chart_progress.health.is_passing());
+        }
+        self.follow_points.update(&ctx.gfx, song_position);
+
+        for effect in &mut self.effects {
+            effect.update(&ctx.gfx, song_position);
+        }
+        self.effects
+            .retain(|effect| !effect.finished(song_position));
+
+        chart_progress.progress = (song_position / self.end_time).max(0.0);
+        // Seeks over a break or the intro don't drain anything
+        if chart_data.drains_health(self.last_update_time)
+            && chart_data.drains_health(song_position)
+        {
+            chart_progress.health.drain(
+                chart.modifiers.drain_rate,
+                (song_position - self.last_update_time).max(0.0),
+            );
+        }
+        self.last_update_time = song_position;
+        chart_progress.break_remaining = chart_data
+            .break_at(song_position)
+            .map(|period| period.end - song_position);
+        chart_progress.skip_target =
+            chart_data.skip_target(song_position, chart.modifiers.approach_seconds());
 
         ctx.set_chart_progress(chart_progress);
     }
@@ -369,7 +1282,17 @@ impl Updatable for PlayingScreen {
 
 impl Renderable for PlayingScreen {
     fn render<'data>(&'data self, rctx: &RenderContext<'data>, pass: &mut wgpu::RenderPass<'data>) {
+        if let Some(background) = &self.background {
+            background.render(rctx, pass);
+        }
+
         rctx.with_projection(&self.playfield_projection_binding, pass, |pass| {
+            if let Some(storyboard) = &self.storyboard {
+                storyboard.render_background(rctx, pass);
+            }
+
+            self.follow_points.render(rctx, pass);
+
             for visible_hitobject in self.visible_objects.values().copied() {
                 match visible_hitobject.refs {
                     VisibleHitObjectRef::Circle {
@@ -394,6 +1317,8 @@ impl Renderable for PlayingScreen {
                 }
             }
 
+            self.numbers.render(rctx, pass);
+
             for visible_hitobject in self.visible_objects.values().copied() {
                 match visible_hitobject.refs {
                     VisibleHitObjectRef::Circle { approach, .. } => {
@@ -405,8 +1330,20 @@ impl Renderable for PlayingScreen {
                     _ => panic!(),
                 }
             }
+
+            for effect in &self.effects {
+                effect.sprite.render(rctx, pass);
+            }
+
+            if let Some(storyboard) = &self.storyboard {
+                storyboard.render_overlay(rctx, pass);
+            }
         });
 
-        self.playfield.render(rctx, pass);
+        if self.background.is_none() {
+            self.playfield.render(rctx, pass);
+        }
+        self.cursor_trail.render(rctx, pass);
+        self.cursor.render(rctx, pass);
     }
 }
@@ -0,0 +1,49 @@
+use std::sync::atomic::Ordering;
+
+use ogfx::{RenderContext, Renderable};
+
+use crate::{
+    game::GameContext,
+    job::{spawn_job, JobHandle},
+};
+
+use super::{Screen, Updatable};
+
+/// Shows the settings, which the UI draws and changes while this screen is open.
+pub struct SettingsScreen;
+
+impl Screen for SettingsScreen {
+    type LoadingResource = ();
+    type LoadingError = std::convert::Infallible;
+
+    fn load(_ctx: std::sync::Arc<GameContext>) -> JobHandle<Result<(), std::convert::Infallible>> {
+        spawn_job(|| Ok(()))
+    }
+
+    fn new(ctx: &GameContext, _loading_res: ()) -> Self {
+        ctx.settings_open.store(true, Ordering::SeqCst);
+        ctx.dirty.store(true, Ordering::SeqCst);
+        SettingsScreen
+    }
+}
+
+impl SettingsScreen {
+    /// Hides the settings again before leaving the screen.
+    pub fn close(&self, ctx: &GameContext) {
+        ctx.settings_open.store(false, Ordering::SeqCst);
+        ctx.dirty.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Updatable for SettingsScreen {
+    fn update(&mut self, _ctx: &GameContext) {}
+}
+
+impl Renderable for SettingsScreen {
+    fn render<'data>(
+        &'data self,
+        _rctx: &RenderContext<'data>,
+        _pass: &mut wgpu::RenderPass<'data>,
+    ) {
+    }
+}
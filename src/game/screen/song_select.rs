@@ -0,0 +1,452 @@
+use std::path::{Path, PathBuf};
+
+use kira::{
+    instance::{handle::InstanceHandle, InstanceSettings, StopInstanceSettings},
+    sound::{handle::SoundHandle, Sound, SoundSettings},
+};
+use ogfx::{RenderContext, Renderable};
+
+use crate::{
+    game::{
+        beat::BeatTracker,
+        chart::ChartInfo,
+        chart_file,
+        difficulty::{self, DifficultyAttributes},
+        profile::ProfileStats,
+        score::{self, Grade},
+        GameContext,
+    },
+    job::{spawn_job, JobHandle},
+    math,
+};
+
+use super::{Screen, Updatable};
+
+/// Scores shown on the leaderboard of the selected chart.
+const LEADERBOARD_SIZE: usize = 10;
+const TEXT_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const SELECTED_COLOUR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const PERSONAL_BEST_COLOUR: [f32; 4] = [0.4, 1.0, 0.4, 1.0];
+/// How far towards white the selected chart flashes on every beat of its preview.
+const SELECTED_PULSE: f32 = 0.6;
+/// Longest name a new profile can have.
+const MAX_PROFILE_NAME: usize = 24;
+
+/// A chart that can be picked.
+pub struct SongEntry {
+    path: PathBuf,
+    info: ChartInfo,
+    difficulty: DifficultyAttributes,
+    /// Scores are looked up by it.
+    beatmap_hash: String,
+    /// Song of the chart, previewed while it's selected.
+    audio_path: PathBuf,
+    /// Beats of the song, the selected chart pulses along with its preview.
+    beats: BeatTracker,
+}
+
+impl SongEntry {
+    fn load(path: PathBuf) -> Option<Self> {
+        let content = std::fs::read_to_string(&path).ok()?;
+        let (info, data) = chart_file::load_chart(&path, &content)
+            .map_err(|e| println!("Failed to load chart '{}': {}", path.display(), e))
+            .ok()?;
+        let audio_path = path.with_file_name(&info.audio_file);
+        Some(SongEntry {
+            difficulty: difficulty::calculate(&info, &data),
+            beatmap_hash: score::beatmap_hash(&content),
+            audio_path,
+            beats: data.beats,
+            path,
+            info,
+        })
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} - {} [{}]",
+            self.info.artist, self.info.title, self.info.version
+        )
+    }
+}
+
+/// Song of the selected chart, playing from its preview point.
+struct Preview {
+    audio_path: PathBuf,
+    sound: SoundHandle,
+    instance: InstanceHandle,
+}
+
+/// Lists the charts in the directory of the selected beatmap, with the leaderboard of the one
+/// picked.
+pub struct SongSelectScreen {
+    songs: Vec<SongEntry>,
+    selected: usize,
+    /// Name of the profile being created, `None` when not naming one.
+    new_profile: Option<String>,
+    preview: Option<Preview>,
+    /// Song of the selected chart being decoded, with the time to preview it from.
+    preview_job: Option<(PathBuf, JobHandle<Option<(Sound, f32)>>)>,
+}
+
+impl Screen for SongSelectScreen {
+    type LoadingResource = Vec<SongEntry>;
+    type LoadingError = std::convert::Infallible;
+
+    fn load(
+        ctx: std::sync::Arc<GameContext>,
+    ) -> JobHandle<Result<Vec<SongEntry>, std::convert::Infallible>> {
+        let directory = match ctx.settings.lock().unwrap().beatmap.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        spawn_job(move || {
+            let mut songs = std::fs::read_dir(&directory)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    chart_file::is_chart_file(path)
+                        || path.extension().map_or(false, |ext| ext == "osu")
+                })
+                .filter_map(SongEntry::load)
+                .collect::<Vec<_>>();
+            songs.sort_by(|a, b| {
+                (&a.info.artist, &a.info.title)
+                    .cmp(&(&b.info.artist, &b.info.title))
+                    .then(
+                        a.difficulty
+                            .star_rating
+                            .partial_cmp(&b.difficulty.star_rating)
+                            .unwrap(),
+                    )
+            });
+            Ok(songs)
+        })
+    }
+
+    fn new(ctx: &GameContext, songs: Vec<SongEntry>) -> Self {
+        if let Some(clock) = ctx.clock_mut().as_mut() {
+            clock.pause();
+        }
+        ctx.set_score(None);
+        ctx.clear_chart_progress();
+
+        let settings = ctx.settings.lock().unwrap();
+        let selected = songs
+            .iter()
+            .position(|song| same_file(&song.path, &settings.beatmap))
+            .unwrap_or(0);
+        let mut screen = SongSelectScreen {
+            songs,
+            selected,
+            new_profile: None,
+            preview: None,
+            preview_job: None,
+        };
+        screen.load_preview();
+        screen
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl SongSelectScreen {
+    /// Moves the selection by `offset` charts, wrapping around the list.
+    pub fn select(&mut self, offset: isize) {
+        if !self.songs.is_empty() {
+            let len = self.songs.len() as isize;
+            self.selected = (self.selected as isize + offset).rem_euclid(len) as usize;
+            self.load_preview();
+        }
+    }
+
+    /// Starts decoding the song of the selected chart, unless it's already previewed.
+    /// Charts of the same beatmap set share their song, which keeps playing between them.
+    fn load_preview(&mut self) {
+        let song = match self.songs.get(self.selected) {
+            Some(song) => song,
+            None => return,
+        };
+        let audio_path = &song.audio_path;
+        let previewed = self.preview.as_ref().map(|preview| &preview.audio_path);
+        let loading = self.preview_job.as_ref().map(|(path, _)| path);
+        if previewed == Some(audio_path) || loading == Some(audio_path) {
+            return;
+        }
+        let info = song.info.clone();
+        let path = audio_path.clone();
+        // A job still decoding another song is dropped, and its result with it
+        self.preview_job = Some((
+            audio_path.clone(),
+            spawn_job(move || {
+                let sound = Sound::from_file(&path, SoundSettings::default())
+                    .map_err(|e| println!("Failed to load song '{}': {}", path.display(), e))
+                    .ok()?;
+                let start = info.preview_start(sound.duration() as f32);
+                Some((sound, start))
+            }),
+        ));
+    }
+
+    /// Stops the preview and removes its song from the audio manager.
+    fn stop_preview(&mut self, ctx: &GameContext) {
+        if let Some(mut preview) = self.preview.take() {
+            if let Err(e) = preview
+                .instance
+                .stop(StopInstanceSettings { fade_tween: None })
+            {
+                println!("Failed to stop song preview: {}", e);
+            }
+            if let Err(e) = ctx.audio.lock().unwrap().remove_sound(preview.sound.id()) {
+                println!("Failed to remove song preview: {}", e);
+            }
+        }
+    }
+
+    /// Stops the preview before leaving the screen.
+    pub fn close(&mut self, ctx: &GameContext) {
+        self.preview_job = None;
+        self.stop_preview(ctx);
+    }
+
+    /// Whether keys go to the name of a new profile.
+    pub fn naming_profile(&self) -> bool {
+        self.new_profile.is_some()
+    }
+
+    /// Starts naming a new profile.
+    pub fn start_new_profile(&mut self) {
+        self.new_profile = Some(String::new());
+    }
+
+    /// Types into the name of the new profile, backspace deletes.
+    pub fn type_char(&mut self, c: char) {
+        if let Some(name) = &mut self.new_profile {
+            match c {
+                '\u{8}' => {
+                    name.pop();
+                }
+                c if !c.is_control() && name.chars().count() < MAX_PROFILE_NAME => name.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// Creates the profile named so far and switches to it, keeps naming if the name can't be
+    /// used.
+    pub fn finish_new_profile(&mut self, ctx: &GameContext) {
+        if let Some(name) = &self.new_profile {
+            if ctx.add_profile(name) {
+                self.new_profile = None;
+            }
+        }
+    }
+
+    pub fn cancel_new_profile(&mut self) {
+        self.new_profile = None;
+    }
+
+    /// Selects the chart to be played, returns `false` if there is none.
+    pub fn play(&self, ctx: &GameContext) -> bool {
+        match self.songs.get(self.selected) {
+            Some(song) => {
+                ctx.update_settings(|settings| settings.beatmap = song.path.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Colour of the selected chart, flashing on the beats of its preview.
+    fn selected_colour(&self) -> [f32; 4] {
+        let song = &self.songs[self.selected];
+        let pulse = self
+            .preview
+            .as_ref()
+            .filter(|preview| preview.audio_path == song.audio_path)
+            .and_then(|preview| song.beats.beat_at(preview.instance.position() as f32))
+            .map_or(0.0, |beat| beat.pulse());
+        SELECTED_COLOUR.map(|channel| math::lerp(channel, 1.0, SELECTED_PULSE * pulse))
+    }
+
+    /// The charts and the leaderboard of the selected one, as lines of text with their colour.
+    pub fn text(&self, ctx: &GameContext) -> Vec<(String, [f32; 4])> {
+        let profiles = ctx.profiles.lock().unwrap();
+        let player = &profiles.active().name;
+        let scores = ctx.scores.lock().unwrap();
+        let stats = ProfileStats::from_scores(scores.player_scores(player));
+
+        let mut lines = Vec::new();
+        if let Some(name) = &self.new_profile {
+            lines.push((
+                format!(
+                    "New profile: {}_\nEnter to create, Escape to cancel\n\n",
+                    name
+                ),
+                SELECTED_COLOUR,
+            ));
+        }
+        lines.push((profile_text(player, &stats), TEXT_COLOUR));
+        lines.push((
+            "Up and Down to pick a chart, Enter to play, Tab to switch profiles, \
+            F3 for a new profile, F4 for settings\n\n"
+                .to_owned(),
+            TEXT_COLOUR,
+        ));
+        if self.songs.is_empty() {
+            lines.push(("No charts found\n".to_owned(), TEXT_COLOUR));
+            return lines;
+        }
+
+        for (idx, song) in self.songs.iter().enumerate() {
+            let line = format!(
+                "{} ({:.2}*, played {} times)\n",
+                song.name(),
+                song.difficulty.star_rating,
+                stats.map_plays(&song.beatmap_hash)
+            );
+            if idx == self.selected {
+                lines.push((format!("> {}", line), self.selected_colour()));
+            } else {
+                lines.push((line, TEXT_COLOUR));
+            }
+        }
+
+        let song = &self.songs[self.selected];
+        let difficulty = &song.difficulty;
+        lines.push((
+            format!(
+                "\nAR {:.1}  OD {:.1}  aim {:.2}  speed {:.2}  max combo {}\n\n",
+                difficulty.approach_rate,
+                difficulty.overall_difficulty,
+                difficulty.aim,
+                difficulty.speed,
+                difficulty.max_combo
+            ),
+            TEXT_COLOUR,
+        ));
+
+        let leaderboard = scores.leaderboard(&song.beatmap_hash);
+        if leaderboard.is_empty() {
+            lines.push(("No scores yet\n".to_owned(), TEXT_COLOUR));
+        }
+        let personal_best = scores.personal_best(&song.beatmap_hash, player);
+        for (rank, score) in leaderboard.iter().take(LEADERBOARD_SIZE).enumerate() {
+            let is_personal_best = personal_best.map_or(false, |best| std::ptr::eq(best, *score));
+            lines.push((
+                format!(
+                    "{}. {}  {}  {}  {:.2}%  {}x  {}  {:.0}pp  {}{}\n",
+                    rank + 1,
+                    score.player,
+                    score.grade(),
+                    score.total_score,
+                    score.accuracy * 100.0,
+                    score.max_combo,
+                    score.mods,
+                    score.performance.total,
+                    score
+                        .played_at
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M"),
+                    if is_personal_best {
+                        "  (personal best)"
+                    } else {
+                        ""
+                    }
+                ),
+                if is_personal_best {
+                    PERSONAL_BEST_COLOUR
+                } else {
+                    TEXT_COLOUR
+                },
+            ));
+        }
+        lines
+    }
+}
+
+/// Name and stats of the active profile.
+fn profile_text(player: &str, stats: &ProfileStats) -> String {
+    let minutes = (stats.play_time / 60.0).round() as u32;
+    let grades = Grade::ALL
+        .iter()
+        .map(|grade| format!("{} {}", grade, stats.grades.get(grade).unwrap_or(&0)))
+        .collect::<Vec<_>>()
+        .join("  ");
+    let trend = stats
+        .accuracy_trend
+        .iter()
+        .map(|accuracy| format!("{:.1}%", accuracy * 100.0))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{}: {} plays, {}h {}m played, {} ranked score\n{}\nRecent accuracy: {}\n\n",
+        player,
+        stats.total_plays,
+        minutes / 60,
+        minutes % 60,
+        stats.ranked_score,
+        grades,
+        if trend.is_empty() {
+            "-"
+        } else {
+            trend.as_str()
+        }
+    )
+}
+
+impl Updatable for SongSelectScreen {
+    fn update(&mut self, ctx: &GameContext) {
+        let (audio_path, job) = match &mut self.preview_job {
+            Some(preview_job) => preview_job,
+            None => return,
+        };
+        let (sound, start) = match job.poll().unwrap() {
+            Some(Some(loaded)) => loaded,
+            Some(None) => {
+                // Keep quiet rather than previewing the song of another chart
+                self.preview_job = None;
+                return self.stop_preview(ctx);
+            }
+            None => return,
+        };
+        let audio_path = audio_path.clone();
+        self.preview_job = None;
+        self.stop_preview(ctx);
+
+        let mut audio = ctx.audio.lock().unwrap();
+        let mut sound = match audio.add_sound(sound) {
+            Ok(sound) => sound,
+            Err(e) => return println!("Failed to add song '{}': {}", audio_path.display(), e),
+        };
+        let settings = InstanceSettings::default()
+            .start_position(start as f64)
+            .volume(ctx.settings.lock().unwrap().music_volume() as f64);
+        match sound.play(settings) {
+            Ok(instance) => {
+                self.preview = Some(Preview {
+                    audio_path,
+                    sound,
+                    instance,
+                })
+            }
+            Err(e) => println!("Failed to play song '{}': {}", audio_path.display(), e),
+        }
+    }
+}
+
+impl Renderable for SongSelectScreen {
+    fn render<'data>(
+        &'data self,
+        _rctx: &RenderContext<'data>,
+        _pass: &mut wgpu::RenderPass<'data>,
+    ) {
+    }
+}
@@ -0,0 +1,176 @@
+//! Settings of this machine, kept in a config file, and settings of each player, kept with
+//! their profile.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::{DEFAULT_BACKGROUND_DIM, DEFAULT_BEATMAP};
+
+/// File the settings are kept in, in the working directory.
+const SETTINGS_FILE: &str = "settings.ron";
+/// Resolutions offered in the settings, 16:9 like the playfield.
+pub const RESOLUTIONS: [Resolution; 4] = [
+    Resolution::new(1024, 576),
+    Resolution::new(1280, 720),
+    Resolution::new(1600, 900),
+    Resolution::new(1920, 1080),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    pub const fn new(width: u32, height: u32) -> Self {
+        Resolution { width, height }
+    }
+}
+
+impl std::fmt::Display for Resolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Settings of the display and audio of this machine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Size of the window, applied on the next start.
+    pub resolution: Resolution,
+    /// Borderless fullscreen on the current monitor, applied on the next start.
+    pub fullscreen: bool,
+    /// Waits for the display to show each frame, no tearing but more latency.
+    pub vsync: bool,
+    /// Frames drawn per second at most, `None` to draw as fast as possible.
+    pub frame_limit: Option<u32>,
+    /// Volumes from 0 to 1, the master volume scales the other two.
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub effects_volume: f32,
+    /// Chart selected last, song select starts from its directory.
+    pub beatmap: PathBuf,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            resolution: RESOLUTIONS[0],
+            fullscreen: false,
+            vsync: false,
+            frame_limit: None,
+            master_volume: 1.0,
+            music_volume: 0.8,
+            effects_volume: 1.0,
+            beatmap: PathBuf::from(DEFAULT_BEATMAP),
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> PathBuf {
+        PathBuf::from(SETTINGS_FILE)
+    }
+
+    /// Loads the settings saved by [`Settings::save`], the defaults if there are none.
+    /// Settings missing from the file keep their defaults.
+    pub fn load() -> Self {
+        match std::fs::read_to_string(Self::path()) {
+            Ok(content) => ron::from_str(&content).unwrap_or_else(|e| {
+                println!("Failed to read settings: {}", e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        // Settings only hold types that serialize to RON
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()).unwrap();
+        if let Err(e) = std::fs::write(Self::path(), content) {
+            println!("Failed to save settings: {}", e);
+        }
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Mailbox
+        }
+    }
+
+    pub fn music_volume(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    pub fn effects_volume(&self) -> f32 {
+        self.master_volume * self.effects_volume
+    }
+}
+
+/// How a player likes to play, each profile has their own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerSettings {
+    /// Names of the two keys that hit objects, like `Z`.
+    pub hit_keys: [String; 2],
+    /// Directory of the skin, `None` for the built in skin.
+    pub skin: Option<PathBuf>,
+    /// Play beatmaps with the skin only, ignoring the elements they ship with.
+    pub ignore_beatmap_skins: bool,
+    /// How much the beatmap background is darkened, in percent.
+    pub background_dim: u8,
+    /// Factor the cursor is scaled by.
+    pub cursor_size: f32,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        PlayerSettings {
+            hit_keys: ["Z".to_owned(), "X".to_owned()],
+            skin: None,
+            ignore_beatmap_skins: false,
+            background_dim: DEFAULT_BACKGROUND_DIM,
+            cursor_size: 1.0,
+        }
+    }
+}
+
+impl PlayerSettings {
+    pub fn is_hit_key(&self, key_name: &str) -> bool {
+        self.hit_keys.iter().any(|key| key == key_name)
+    }
+}
+
+#[test]
+fn test_settings() {
+    // Anything missing keeps its default
+    let settings = ron::from_str::<Settings>("(vsync: true, frame_limit: Some(240))").unwrap();
+    assert_eq!(
+        settings,
+        Settings {
+            vsync: true,
+            frame_limit: Some(240),
+            ..Settings::default()
+        }
+    );
+    assert_eq!(settings.present_mode(), wgpu::PresentMode::Fifo);
+    let saved = ron::ser::to_string(&settings).unwrap();
+    assert_eq!(ron::from_str::<Settings>(&saved).unwrap(), settings);
+
+    let quiet = Settings {
+        master_volume: 0.5,
+        ..Settings::default()
+    };
+    assert!((quiet.music_volume() - 0.4).abs() < 1e-6);
+    assert!((quiet.effects_volume() - 0.5).abs() < 1e-6);
+    assert_eq!(RESOLUTIONS[1].to_string(), "1280x720");
+
+    let player = ron::from_str::<PlayerSettings>("(hit_keys: (\"A\", \"S\"))").unwrap();
+    assert!(player.is_hit_key("S") && !player.is_hit_key("Z"));
+    assert_eq!(player.background_dim, DEFAULT_BACKGROUND_DIM);
+}
@@ -0,0 +1,349 @@
+use std::path::{Path, PathBuf};
+
+use ogfx::texture::RawTextureData;
+
+use super::chart;
+
+/// Texture that can be replaced by a skin, with a built in fallback.
+pub struct SkinElement {
+    /// Key of the element in the atlas it's packed into.
+    pub key: &'static str,
+    /// Name of the file in an osu! skin, without the extension. `None` for textures osu! skins
+    /// don't have, which always use the built in one.
+    pub file_name: Option<&'static str>,
+    /// Built in texture, drawn at [`DEFAULT_SCALE_FACTOR`].
+    pub default: &'static [u8],
+}
+
+/// The built in textures are high resolution, like `@2x` skin files.
+const DEFAULT_SCALE_FACTOR: f32 = 2.0;
+
+pub const HITOBJECT_ELEMENTS: &[SkinElement] = &[
+    SkinElement {
+        key: "tinted",
+        file_name: Some("hitcircle"),
+        default: include_bytes!("../../resources/circle/tinted.png"),
+    },
+    SkinElement {
+        key: "overlay",
+        file_name: Some("hitcircleoverlay"),
+        default: include_bytes!("../../resources/circle/overlay.png"),
+    },
+    SkinElement {
+        key: "approach",
+        file_name: Some("approachcircle"),
+        default: include_bytes!("../../resources/circle/approach.png"),
+    },
+    SkinElement {
+        key: "track",
+        // osu! draws slider bodies without a texture
+        file_name: None,
+        default: include_bytes!("../../resources/circle/track.png"),
+    },
+    SkinElement {
+        key: "followpoint",
+        file_name: Some("followpoint"),
+        default: include_bytes!("../../resources/circle/followpoint.png"),
+    },
+    SkinElement {
+        key: "hit300",
+        file_name: Some("hit300"),
+        default: include_bytes!("../../resources/judgement/hit300.png"),
+    },
+    SkinElement {
+        key: "hit100",
+        file_name: Some("hit100"),
+        default: include_bytes!("../../resources/judgement/hit100.png"),
+    },
+    SkinElement {
+        key: "hit50",
+        file_name: Some("hit50"),
+        default: include_bytes!("../../resources/judgement/hit50.png"),
+    },
+    SkinElement {
+        key: "hit0",
+        file_name: Some("hit0"),
+        default: include_bytes!("../../resources/judgement/hit0.png"),
+    },
+];
+
+pub const PLAYFIELD: SkinElement = SkinElement {
+    key: "playfield",
+    file_name: Some("playfield"),
+    default: include_bytes!("../../resources/ui/playfield.png"),
+};
+
+pub const CURSOR: SkinElement = SkinElement {
+    key: "cursor",
+    file_name: Some("cursor"),
+    default: include_bytes!("../../resources/ui/cursor.png"),
+};
+
+const DEFAULT_DIGITS: [&[u8]; 10] = [
+    include_bytes!("../../resources/number/default-0.png"),
+    include_bytes!("../../resources/number/default-1.png"),
+    include_bytes!("../../resources/number/default-2.png"),
+    include_bytes!("../../resources/number/default-3.png"),
+    include_bytes!("../../resources/number/default-4.png"),
+    include_bytes!("../../resources/number/default-5.png"),
+    include_bytes!("../../resources/number/default-6.png"),
+    include_bytes!("../../resources/number/default-7.png"),
+    include_bytes!("../../resources/number/default-8.png"),
+    include_bytes!("../../resources/number/default-9.png"),
+];
+
+/// Settings read from a `skin.ini`.
+#[derive(Debug, Clone)]
+pub struct SkinConfig {
+    pub name: String,
+    pub author: String,
+    pub combo_colours: Vec<cgmath::Vector3<f32>>,
+    /// Path prefix of the combo number textures, relative to the skin directory.
+    pub hit_circle_prefix: String,
+    /// How many pixels neighbouring combo number digits overlap.
+    pub hit_circle_overlap: i32,
+}
+
+impl Default for SkinConfig {
+    fn default() -> Self {
+        SkinConfig {
+            name: "Ouendan".to_owned(),
+            author: String::new(),
+            combo_colours: chart::DEFAULT_COMBO_COLOURS.to_vec(),
+            hit_circle_prefix: "default".to_owned(),
+            hit_circle_overlap: -2,
+        }
+    }
+}
+
+/// Parses a `r,g,b` colour.
+pub fn parse_colour(value: &str) -> Option<cgmath::Vector3<f32>> {
+    let mut it = value.split(',').map(|c| c.trim().parse::<u8>());
+    match (it.next(), it.next(), it.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => {
+            Some(cgmath::vec3(r as f32, g as f32, b as f32) / 255.0)
+        }
+        _ => None,
+    }
+}
+
+impl SkinConfig {
+    pub fn parse(content: &str) -> Self {
+        let mut config = SkinConfig::default();
+        let mut combo_colours = Vec::new();
+        let mut section = "";
+        for line in content.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = &line[1..line.len() - 1];
+                continue;
+            }
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            match (section, key) {
+                ("General", "Name") => config.name = value.to_owned(),
+                ("General", "Author") => config.author = value.to_owned(),
+                ("Colours", key) if key.starts_with("Combo") => {
+                    if let (Ok(idx), Some(colour)) =
+                        (key["Combo".len()..].parse::<u32>(), parse_colour(value))
+                    {
+                        combo_colours.push((idx, colour));
+                    }
+                }
+                ("Fonts", "HitCirclePrefix") => {
+                    config.hit_circle_prefix = value.replace('\\', "/");
+                }
+                ("Fonts", "HitCircleOverlap") => {
+                    if let Ok(overlap) = value.parse() {
+                        config.hit_circle_overlap = overlap;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !combo_colours.is_empty() {
+            combo_colours.sort_by_key(|(idx, _)| *idx);
+            config.combo_colours = combo_colours.into_iter().map(|(_, c)| c).collect();
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Skin {
+    /// Directory the skin is loaded from, `None` for the built in skin.
+    pub directory: Option<PathBuf>,
+    pub config: SkinConfig,
+}
+
+impl Skin {
+    pub fn load(directory: impl Into<PathBuf>) -> Self {
+        let directory = directory.into();
+        let config = std::fs::read_to_string(directory.join("skin.ini"))
+            .map(|content| SkinConfig::parse(&content))
+            .unwrap_or_default();
+        Skin {
+            directory: Some(directory),
+            config,
+        }
+    }
+
+    /// The directory a skin would be loaded from by [`Skin::find`].
+    pub fn directory(directory: Option<&Path>) -> Option<&Path> {
+        directory.filter(|directory| directory.is_dir())
+    }
+
+    /// Loads the skin in `directory`, falling back to the built in skin if there is none.
+    pub fn find(directory: Option<&Path>) -> Self {
+        match Skin::directory(directory) {
+            Some(directory) => Skin::load(directory),
+            None => Skin::default(),
+        }
+    }
+
+    /// Loads `file_name` from the skin directory, preferring its `@2x` variant.
+    /// Returns `None` if the skin doesn't have a usable one.
+    fn load_file(&self, file_name: &str) -> Option<RawTextureData> {
+        let directory = self.directory.as_ref()?;
+        [
+            (format!("{}@2x.png", file_name), 2.0),
+            (format!("{}.png", file_name), 1.0),
+        ]
+        .into_iter()
+        .find_map(|(name, scale_factor)| {
+            let path = directory.join(name);
+            let data = std::fs::read(&path).ok()?;
+            match RawTextureData::try_from_raw_image(&data) {
+                Ok(texture) => Some(RawTextureData {
+                    scale_factor,
+                    ..texture
+                }),
+                Err(e) => {
+                    println!("Failed to load skin texture '{}': {}", path.display(), e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Loads `element` from the skin directory, if it's an element osu! skins have.
+    fn load_element(&self, element: &SkinElement) -> Option<RawTextureData> {
+        self.load_file(element.file_name?)
+    }
+
+    pub fn texture(&self, element: &SkinElement) -> RawTextureData {
+        self.load_element(element)
+            .unwrap_or_else(|| default_texture(element.default))
+    }
+
+    pub fn hitobject_textures(&self) -> Vec<(String, RawTextureData)> {
+        HITOBJECT_ELEMENTS
+            .iter()
+            .map(|element| (element.key.to_owned(), self.texture(element)))
+            .collect()
+    }
+
+    pub fn number_textures(&self) -> Vec<(u8, RawTextureData)> {
+        DEFAULT_DIGITS
+            .iter()
+            .enumerate()
+            .map(|(digit, default)| {
+                let file_name = format!("{}-{}", self.config.hit_circle_prefix, digit);
+                (
+                    digit as u8,
+                    self.load_file(&file_name)
+                        .unwrap_or_else(|| default_texture(default)),
+                )
+            })
+            .collect()
+    }
+}
+
+fn default_texture(data: &[u8]) -> RawTextureData {
+    RawTextureData {
+        scale_factor: DEFAULT_SCALE_FACTOR,
+        ..RawTextureData::from_raw_image(data)
+    }
+}
+
+/// Skin textures found in a single directory, without any fallback.
+/// Used for the elements shipped with a beatmap, which are layered over the active skin.
+#[derive(Default)]
+pub struct SkinOverrides {
+    pub hitobject: Vec<(String, RawTextureData)>,
+    pub numbers: Vec<(u8, RawTextureData)>,
+}
+
+impl SkinOverrides {
+    pub fn load(directory: impl Into<PathBuf>) -> Self {
+        let skin = Skin {
+            directory: Some(directory.into()),
+            config: SkinConfig::default(),
+        };
+        let hitobject = HITOBJECT_ELEMENTS
+            .iter()
+            .filter_map(|element| {
+                skin.load_element(element)
+                    .map(|texture| (element.key.to_owned(), texture))
+            })
+            .collect();
+        let numbers = (0..10u8)
+            .filter_map(|digit| {
+                skin.load_file(&format!("{}-{}", skin.config.hit_circle_prefix, digit))
+                    .map(|texture| (digit, texture))
+            })
+            .collect();
+        SkinOverrides { hitobject, numbers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hitobject.is_empty() && self.numbers.is_empty()
+    }
+}
+
+/// Replaces the textures in `base` that have an entry with the same key in `overrides`.
+pub fn layer_textures<T: PartialEq>(
+    base: &mut [(T, RawTextureData)],
+    overrides: Vec<(T, RawTextureData)>,
+) {
+    for (key, texture) in overrides {
+        if let Some(entry) = base.iter_mut().find(|(k, _)| *k == key) {
+            entry.1 = texture;
+        }
+    }
+}
+
+#[test]
+fn test_skin_config() {
+    let config = SkinConfig::parse(
+        "[General]
+Name: Test Skin
+Author: someone
+
+[Colours]
+Combo2 : 0,255,0
+Combo1 : 255,0,0
+SliderBorder: 255,255,255
+
+[Fonts]
+HitCirclePrefix: fonts\\numbers
+HitCircleOverlap: 4
+",
+    );
+    assert_eq!(config.name, "Test Skin");
+    assert_eq!(config.author, "someone");
+    assert_eq!(
+        config.combo_colours,
+        vec![cgmath::vec3(1.0, 0.0, 0.0), cgmath::vec3(0.0, 1.0, 0.0)]
+    );
+    assert_eq!(config.hit_circle_prefix, "fonts/numbers");
+    assert_eq!(config.hit_circle_overlap, 4);
+
+    let default = SkinConfig::parse("");
+    assert_eq!(default.combo_colours, chart::DEFAULT_COMBO_COLOURS.to_vec());
+    assert_eq!(default.hit_circle_prefix, "default");
+}
@@ -0,0 +1,983 @@
+use cgmath::{Vector2, Vector3};
+
+use super::hitsound::{self, Sample};
+
+/// Size of the storyboard coordinate space, the 4:3 area the playfield is centered in.
+pub const STORYBOARD_SIZE: Vector2<f32> = cgmath::vec2(640.0, 480.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Layer {
+    Background,
+    /// Only shown while the player is failing.
+    Fail,
+    /// Only shown while the player is passing.
+    Pass,
+    Foreground,
+    /// Drawn over the hit objects.
+    Overlay,
+}
+
+impl Layer {
+    fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "Background" | "0" => Layer::Background,
+            "Fail" | "1" => Layer::Fail,
+            "Pass" | "2" => Layer::Pass,
+            "Foreground" | "3" => Layer::Foreground,
+            "Overlay" | "4" => Layer::Overlay,
+            _ => return None,
+        })
+    }
+}
+
+/// Point of the image its position refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    TopLeft,
+    TopCentre,
+    TopRight,
+    CentreLeft,
+    Centre,
+    CentreRight,
+    BottomLeft,
+    BottomCentre,
+    BottomRight,
+}
+
+impl Origin {
+    fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "TopLeft" | "0" => Origin::TopLeft,
+            // Custom origins are treated as centered like osu! does
+            "Centre" | "1" | "Custom" | "8" => Origin::Centre,
+            "CentreLeft" | "2" => Origin::CentreLeft,
+            "TopRight" | "3" => Origin::TopRight,
+            "BottomCentre" | "4" => Origin::BottomCentre,
+            "TopCentre" | "5" => Origin::TopCentre,
+            "CentreRight" | "6" => Origin::CentreRight,
+            "BottomLeft" | "7" => Origin::BottomLeft,
+            "BottomRight" | "9" => Origin::BottomRight,
+            _ => return None,
+        })
+    }
+
+    /// Position of the origin relative to the center of the image, in image sizes.
+    pub fn offset(&self) -> Vector2<f32> {
+        let (x, y) = match self {
+            Origin::TopLeft => (-0.5, -0.5),
+            Origin::TopCentre => (0.0, -0.5),
+            Origin::TopRight => (0.5, -0.5),
+            Origin::CentreLeft => (-0.5, 0.0),
+            Origin::Centre => (0.0, 0.0),
+            Origin::CentreRight => (0.5, 0.0),
+            Origin::BottomLeft => (-0.5, 0.5),
+            Origin::BottomCentre => (0.0, 0.5),
+            Origin::BottomRight => (0.5, 0.5),
+        };
+        cgmath::vec2(x, y)
+    }
+}
+
+/// Applies the osu! easing `easing` to `progress`, from 0 to 1.
+/// Unknown easings are linear.
+pub fn ease(easing: u8, t: f32) -> f32 {
+    use std::f32::consts::PI;
+    const BACK: f32 = 1.70158;
+    const BACK_IN_OUT: f32 = BACK * 1.525;
+    fn bounce_out(t: f32) -> f32 {
+        if t < 1.0 / 2.75 {
+            7.5625 * t * t
+        } else if t < 2.0 / 2.75 {
+            let t = t - 1.5 / 2.75;
+            7.5625 * t * t + 0.75
+        } else if t < 2.5 / 2.75 {
+            let t = t - 2.25 / 2.75;
+            7.5625 * t * t + 0.9375
+        } else {
+            let t = t - 2.625 / 2.75;
+            7.5625 * t * t + 0.984375
+        }
+    }
+    fn elastic_out(t: f32, period: f32) -> f32 {
+        2f32.powf(-10.0 * t) * ((t - period / 4.0) * (2.0 * PI) / period).sin() + 1.0
+    }
+    // Mirrors an ease-in into an ease-out and combines both into an ease-in-out
+    let out = |ease_in: fn(f32) -> f32| 1.0 - ease_in(1.0 - t);
+    let in_out = |ease_in: fn(f32) -> f32| {
+        if t < 0.5 {
+            ease_in(t * 2.0) / 2.0
+        } else {
+            1.0 - ease_in((1.0 - t) * 2.0) / 2.0
+        }
+    };
+    let quad = |t: f32| t * t;
+    let cubic = |t: f32| t * t * t;
+    let quart = |t: f32| t.powi(4);
+    let quint = |t: f32| t.powi(5);
+    let sine = |t: f32| 1.0 - (t * PI / 2.0).cos();
+    let expo = |t: f32| {
+        if t <= 0.0 {
+            0.0
+        } else {
+            2f32.powf(10.0 * (t - 1.0))
+        }
+    };
+    let circ = |t: f32| 1.0 - (1.0 - t * t).max(0.0).sqrt();
+    let back = |t: f32| t * t * ((BACK + 1.0) * t - BACK);
+    let back_in_out = |t: f32| t * t * ((BACK_IN_OUT + 1.0) * t - BACK_IN_OUT);
+    let bounce = |t: f32| 1.0 - bounce_out(1.0 - t);
+    let elastic = |t: f32| 1.0 - elastic_out(1.0 - t, 0.3);
+    match easing {
+        1 | 4 => out(quad),
+        2 | 3 => quad(t),
+        5 => in_out(quad),
+        6 => cubic(t),
+        7 => out(cubic),
+        8 => in_out(cubic),
+        9 => quart(t),
+        10 => out(quart),
+        11 => in_out(quart),
+        12 => quint(t),
+        13 => out(quint),
+        14 => in_out(quint),
+        15 => sine(t),
+        16 => out(sine),
+        17 => in_out(sine),
+        18 => expo(t),
+        19 => out(expo),
+        20 => in_out(expo),
+        21 => circ(t),
+        22 => out(circ),
+        23 => in_out(circ),
+        24 => elastic(t),
+        25 => elastic_out(t, 0.3),
+        26 => 2f32.powf(-10.0 * t) * ((0.5 * t - 0.075) * (2.0 * PI) / 0.3).sin() + 1.0,
+        27 => 2f32.powf(-10.0 * t) * ((0.25 * t - 0.075) * (2.0 * PI) / 0.3).sin() + 1.0,
+        28 => in_out(elastic),
+        29 => back(t),
+        30 => out(back),
+        31 => in_out(back_in_out),
+        32 => bounce(t),
+        33 => bounce_out(t),
+        34 => in_out(bounce),
+        _ => t,
+    }
+}
+
+/// Values a command can animate.
+pub trait Interpolate: Copy {
+    fn interpolate(from: Self, to: Self, progress: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(from: Self, to: Self, progress: f32) -> Self {
+        from + (to - from) * progress
+    }
+}
+
+impl Interpolate for Vector2<f32> {
+    fn interpolate(from: Self, to: Self, progress: f32) -> Self {
+        from + (to - from) * progress
+    }
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(from: Self, to: Self, progress: f32) -> Self {
+        from + (to - from) * progress
+    }
+}
+
+/// Parameters that are only on while their command is running.
+impl Interpolate for () {
+    fn interpolate(_from: Self, _to: Self, _progress: f32) -> Self {}
+}
+
+/// Animation of one property from `from` to `to` between `start` and `end`, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Command<T> {
+    pub easing: u8,
+    pub start: f32,
+    pub end: f32,
+    pub from: T,
+    pub to: T,
+}
+
+impl<T: Interpolate> Command<T> {
+    pub fn value_at(&self, time: f32) -> T {
+        if time >= self.end {
+            return self.to;
+        }
+        if time <= self.start {
+            return self.from;
+        }
+        let progress = (time - self.start) / (self.end - self.start);
+        T::interpolate(self.from, self.to, ease(self.easing, progress))
+    }
+
+    fn shifted(&self, offset: f32) -> Self {
+        Command {
+            start: self.start + offset,
+            end: self.end + offset,
+            ..*self
+        }
+    }
+}
+
+/// Returns the value of the last command of `commands` to have started at `time`, along with
+/// its start time. The commands run `offset` seconds late.
+fn active<T: Interpolate>(commands: &[Command<T>], offset: f32, time: f32) -> Option<(f32, T)> {
+    let started = commands.partition_point(|command| command.start + offset <= time);
+    let command = &commands[started.checked_sub(1)?];
+    Some((command.start + offset, command.value_at(time - offset)))
+}
+
+fn first_value<T: Interpolate>(commands: &[Command<T>]) -> Option<T> {
+    commands.first().map(|command| command.from)
+}
+
+/// Every command of an element, or of one of its trigger groups, each kind sorted by start time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Commands {
+    pub fade: Vec<Command<f32>>,
+    pub movement: Vec<Command<Vector2<f32>>>,
+    pub move_x: Vec<Command<f32>>,
+    pub move_y: Vec<Command<f32>>,
+    pub scale: Vec<Command<f32>>,
+    pub vector_scale: Vec<Command<Vector2<f32>>>,
+    pub rotate: Vec<Command<f32>>,
+    pub colour: Vec<Command<Vector3<f32>>>,
+    pub flip_h: Vec<Command<()>>,
+    pub flip_v: Vec<Command<()>>,
+    pub additive: Vec<Command<()>>,
+}
+
+impl Commands {
+    /// Appends the commands of `other` shifted by `offset` seconds.
+    fn extend_shifted(&mut self, other: &Commands, offset: f32) {
+        fn extend<T: Interpolate>(to: &mut Vec<Command<T>>, from: &[Command<T>], offset: f32) {
+            to.extend(from.iter().map(|command| command.shifted(offset)));
+        }
+        extend(&mut self.fade, &other.fade, offset);
+        extend(&mut self.movement, &other.movement, offset);
+        extend(&mut self.move_x, &other.move_x, offset);
+        extend(&mut self.move_y, &other.move_y, offset);
+        extend(&mut self.scale, &other.scale, offset);
+        extend(&mut self.vector_scale, &other.vector_scale, offset);
+        extend(&mut self.rotate, &other.rotate, offset);
+        extend(&mut self.colour, &other.colour, offset);
+        extend(&mut self.flip_h, &other.flip_h, offset);
+        extend(&mut self.flip_v, &other.flip_v, offset);
+        extend(&mut self.additive, &other.additive, offset);
+    }
+
+    fn sort(&mut self) {
+        fn sort<T>(list: &mut [Command<T>]) {
+            list.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+        }
+        sort(&mut self.fade);
+        sort(&mut self.movement);
+        sort(&mut self.move_x);
+        sort(&mut self.move_y);
+        sort(&mut self.scale);
+        sort(&mut self.vector_scale);
+        sort(&mut self.rotate);
+        sort(&mut self.colour);
+        sort(&mut self.flip_h);
+        sort(&mut self.flip_v);
+        sort(&mut self.additive);
+    }
+
+    /// Time span covered by the commands, `None` if there are none.
+    fn span(&self) -> Option<(f32, f32)> {
+        fn times<T>(list: &[Command<T>]) -> impl Iterator<Item = (f32, f32)> + '_ {
+            list.iter().map(|command| (command.start, command.end))
+        }
+        times(&self.fade)
+            .chain(times(&self.movement))
+            .chain(times(&self.move_x))
+            .chain(times(&self.move_y))
+            .chain(times(&self.scale))
+            .chain(times(&self.vector_scale))
+            .chain(times(&self.rotate))
+            .chain(times(&self.colour))
+            .chain(times(&self.flip_h))
+            .chain(times(&self.flip_v))
+            .chain(times(&self.additive))
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+    }
+
+    /// Parses a command line without its indentation, adding it to the matching kind.
+    /// Returns `None` if the line isn't a valid command.
+    fn parse_line(&mut self, line: &str) -> Option<()> {
+        let fields = line
+            .split(',')
+            .map(|field| field.trim())
+            .collect::<Vec<_>>();
+        let kind = *fields.first()?;
+        let easing = fields.get(1)?.parse::<u8>().ok()?;
+        let start = fields.get(2)?.parse::<f32>().ok()? / 1000.0;
+        let end = match fields.get(3)? {
+            &"" => start,
+            end => end.parse::<f32>().ok()? / 1000.0,
+        };
+        let params = &fields[4..];
+
+        fn floats(params: &[&str]) -> Option<Vec<f32>> {
+            params
+                .iter()
+                .map(|param| param.parse::<f32>().ok())
+                .collect()
+        }
+        // Values for a command animating `count` floats, expanding the shorthand where more than
+        // two sets of values chain commands of the same duration one after the other.
+        fn push<T: Interpolate>(
+            list: &mut Vec<Command<T>>,
+            easing: u8,
+            start: f32,
+            end: f32,
+            values: Vec<T>,
+        ) -> Option<()> {
+            let duration = end - start;
+            match values.len() {
+                0 => return None,
+                1 => list.push(Command {
+                    easing,
+                    start,
+                    end,
+                    from: values[0],
+                    to: values[0],
+                }),
+                _ => {
+                    for (i, pair) in values.windows(2).enumerate() {
+                        let offset = duration * i as f32;
+                        list.push(Command {
+                            easing,
+                            start: start + offset,
+                            end: end + offset,
+                            from: pair[0],
+                            to: pair[1],
+                        });
+                    }
+                }
+            }
+            Some(())
+        }
+
+        match kind {
+            "F" | "S" | "R" | "MX" | "MY" => {
+                let values = floats(params)?;
+                let list = match kind {
+                    "F" => &mut self.fade,
+                    "S" => &mut self.scale,
+                    "R" => &mut self.rotate,
+                    "MX" => &mut self.move_x,
+                    _ => &mut self.move_y,
+                };
+                push(list, easing, start, end, values)
+            }
+            "M" | "V" => {
+                let values = floats(params)?
+                    .chunks_exact(2)
+                    .map(|v| cgmath::vec2(v[0], v[1]))
+                    .collect();
+                let list = if kind == "M" {
+                    &mut self.movement
+                } else {
+                    &mut self.vector_scale
+                };
+                push(list, easing, start, end, values)
+            }
+            "C" => {
+                let values = floats(params)?
+                    .chunks_exact(3)
+                    .map(|v| cgmath::vec3(v[0], v[1], v[2]) / 255.0)
+                    .collect();
+                push(&mut self.colour, easing, start, end, values)
+            }
+            "P" => {
+                let list = match *params.first()? {
+                    "H" => &mut self.flip_h,
+                    "V" => &mut self.flip_v,
+                    "A" => &mut self.additive,
+                    _ => return None,
+                };
+                list.push(Command {
+                    easing,
+                    start,
+                    end,
+                    from: (),
+                    to: (),
+                });
+                Some(())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Commands run when a gameplay event happens between `start` and `end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerGroup {
+    /// Event starting the group, like `HitSoundClap`, `Passing` or `Failing`.
+    pub name: String,
+    pub start: f32,
+    pub end: f32,
+    /// Commands relative to the time the group was triggered.
+    pub commands: Commands,
+    /// Time after being triggered at which the last command ends.
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopType {
+    Forever,
+    Once,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    pub frame_count: usize,
+    pub frame_delay: f32,
+    pub loop_type: LoopType,
+}
+
+/// Sprite or animation of a storyboard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub layer: Layer,
+    pub origin: Origin,
+    /// Path of the image relative to the beatmap directory.
+    pub path: String,
+    pub position: Vector2<f32>,
+    pub animation: Option<Animation>,
+    /// Commands with loops unrolled.
+    pub commands: Commands,
+    pub triggers: Vec<TriggerGroup>,
+    /// Time the element appears at.
+    pub start: f32,
+    /// Time the element disappears at, ignoring triggers.
+    pub end: f32,
+}
+
+impl Element {
+    /// Path of every image the element shows.
+    pub fn frame_paths(&self) -> Vec<String> {
+        match self.animation {
+            Some(animation) => {
+                let (stem, extension) = self
+                    .path
+                    .rsplit_once('.')
+                    .unwrap_or((self.path.as_str(), ""));
+                (0..animation.frame_count)
+                    .map(|frame| format!("{}{}.{}", stem, frame, extension))
+                    .collect()
+            }
+            None => vec![self.path.clone()],
+        }
+    }
+
+    /// Whether the element is drawn with additive blending at some point.
+    pub fn has_additive(&self) -> bool {
+        !self.commands.additive.is_empty()
+            || self
+                .triggers
+                .iter()
+                .any(|group| !group.commands.additive.is_empty())
+    }
+}
+
+/// How an element looks at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElementState {
+    /// Position of the origin in storyboard coordinates.
+    pub position: Vector2<f32>,
+    /// Negative when flipped.
+    pub scale: Vector2<f32>,
+    pub rotation: f32,
+    pub colour: Vector3<f32>,
+    pub alpha: f32,
+    pub additive: bool,
+    /// Index of the animation frame shown, 0 for sprites.
+    pub frame: usize,
+}
+
+impl Element {
+    /// Returns how the element looks at `time`, with the trigger groups started at the times in
+    /// `triggered`. `None` if the element isn't visible.
+    pub fn state(&self, time: f32, triggered: &[Option<f32>]) -> Option<ElementState> {
+        let trigger_end = self
+            .triggers
+            .iter()
+            .zip(triggered)
+            .filter_map(|(group, at)| Some(group.duration + (*at)?))
+            .fold(self.end, f32::max);
+        if time < self.start || time >= trigger_end {
+            return None;
+        }
+
+        // The element's own commands followed by those of the started trigger groups
+        let sources = || {
+            std::iter::once((&self.commands, 0.0)).chain(
+                self.triggers
+                    .iter()
+                    .zip(triggered)
+                    .filter_map(|(group, at)| Some((&group.commands, (*at)?))),
+            )
+        };
+
+        // Latest command of a kind to have started over every source
+        fn value<'a, T: Interpolate>(
+            sources: impl Iterator<Item = (&'a Commands, f32)>,
+            own: &'a Commands,
+            select: fn(&Commands) -> &Vec<Command<T>>,
+            time: f32,
+        ) -> Option<(f32, T)> {
+            sources
+                .filter_map(|(commands, offset)| active(select(commands), offset, time))
+                .fold(None, |latest: Option<(f32, T)>, current| match latest {
+                    Some(latest) if latest.0 > current.0 => Some(latest),
+                    _ => Some(current),
+                })
+                .or_else(|| Some((f32::MIN, first_value(select(own))?)))
+        }
+        fn running<'a>(
+            mut sources: impl Iterator<Item = (&'a Commands, f32)>,
+            select: fn(&Commands) -> &Vec<Command<()>>,
+            time: f32,
+        ) -> bool {
+            sources.any(|(commands, offset)| {
+                select(commands).iter().any(|command| {
+                    let (start, end) = (command.start + offset, command.end + offset);
+                    // Parameters without a duration last for the whole lifetime of the element
+                    time >= start && (time < end || start == end)
+                })
+            })
+        }
+        let own = &self.commands;
+
+        let alpha = value(sources(), own, |c| &c.fade, time).map_or(1.0, |(_, alpha)| alpha);
+        if alpha <= 0.0 {
+            return None;
+        }
+
+        let movement = value(sources(), own, |c| &c.movement, time);
+        let mut position = movement.map_or(self.position, |(_, position)| position);
+        let move_start = movement.map_or(f32::MIN, |(start, _)| start);
+        // Single axis moves override full ones started before them
+        if let Some((start, x)) = value(sources(), own, |c| &c.move_x, time) {
+            if start >= move_start {
+                position.x = x;
+            }
+        }
+        if let Some((start, y)) = value(sources(), own, |c| &c.move_y, time) {
+            if start >= move_start {
+                position.y = y;
+            }
+        }
+
+        let mut scale = value(sources(), own, |c| &c.vector_scale, time)
+            .map_or(cgmath::vec2(1.0, 1.0), |(_, scale)| scale)
+            * value(sources(), own, |c| &c.scale, time).map_or(1.0, |(_, scale)| scale);
+        if running(sources(), |c| &c.flip_h, time) {
+            scale.x = -scale.x;
+        }
+        if running(sources(), |c| &c.flip_v, time) {
+            scale.y = -scale.y;
+        }
+
+        let frame = match self.animation {
+            Some(animation) => {
+                let frame = ((time - self.start) / animation.frame_delay).max(0.0) as usize;
+                match animation.loop_type {
+                    LoopType::Forever => frame % animation.frame_count,
+                    LoopType::Once => frame.min(animation.frame_count - 1),
+                }
+            }
+            None => 0,
+        };
+
+        Some(ElementState {
+            position,
+            scale,
+            rotation: value(sources(), own, |c| &c.rotate, time)
+                .map_or(0.0, |(_, rotation)| rotation),
+            colour: value(sources(), own, |c| &c.colour, time)
+                .map_or(cgmath::vec3(1.0, 1.0, 1.0), |(_, colour)| colour),
+            alpha,
+            additive: running(sources(), |c| &c.additive, time),
+            frame,
+        })
+    }
+}
+
+/// Returns whether the trigger `name` fires for a hit playing `samples`.
+///
+/// Names follow `HitSound[SampleSet][AdditionsSampleSet][Addition]`, like `HitSoundSoftDrumClap`.
+/// The sample set is the one of the hit normal, the additions' sample set is the one of the
+/// whistles, finishes and claps, and `All` matches any set. Parts left out match anything.
+pub fn hitsound_trigger_matches(name: &str, samples: &[Sample]) -> bool {
+    let rest = match name.strip_prefix("HitSound") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    // Custom sample set indices aren't tracked, ignore them
+    let rest = rest.trim_end_matches(|c: char| c.is_ascii_digit());
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in rest.chars() {
+        if c.is_uppercase() && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    let is_set = |word: &str| matches!(word, "All" | "Normal" | "Soft" | "Drum");
+    let set_count = words.iter().take(2).take_while(|word| is_set(word)).count();
+    let (sets, rest) = words.split_at(set_count);
+    let addition = match rest {
+        [] => None,
+        [addition] if matches!(addition.as_str(), "Whistle" | "Finish" | "Clap") => {
+            Some(addition.as_str())
+        }
+        _ => return false,
+    };
+    // `All` and left out sets match any set
+    let set_matches = |set: Option<&String>, sample_set: &str| {
+        set.map_or(true, |set| {
+            set == "All" || set.eq_ignore_ascii_case(sample_set)
+        })
+    };
+
+    let parts = samples
+        .iter()
+        .filter_map(|sample| sample.name.split_once('-'))
+        .collect::<Vec<_>>();
+    let normal_matches = parts
+        .iter()
+        .any(|&(sample_set, hit)| hit == "hitnormal" && set_matches(sets.first(), sample_set));
+    let additions_match = (sets.len() < 2 && addition.is_none())
+        || parts
+            .iter()
+            .any(|&(sample_set, hit)| match hit.strip_prefix("hit") {
+                Some("normal") | None => false,
+                Some(hit) => {
+                    set_matches(sets.get(1), sample_set)
+                        && addition.map_or(true, |addition| addition.eq_ignore_ascii_case(hit))
+                }
+            });
+    (sets.is_empty() || normal_matches) && additions_match
+}
+
+/// Lines of the `[Events]` section of `content` with their indentation, and the
+/// `[Variables]` of the file substituted.
+fn event_lines(content: &str) -> Vec<String> {
+    let section = |name: &'static str| {
+        content
+            .lines()
+            .skip_while(move |line| line.trim() != name)
+            .skip(1)
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .map(|line| line.trim_end())
+            .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with("//"))
+    };
+    let mut variables = section("[Variables]")
+        .filter_map(|line| line.trim().split_once('='))
+        .filter(|(name, _)| name.starts_with('$'))
+        .collect::<Vec<_>>();
+    // Replace longer names first so `$ab` isn't replaced with the value of `$a`
+    variables.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+    section("[Events]")
+        .map(|line| {
+            variables
+                .iter()
+                .fold(line.to_owned(), |line, (name, value)| {
+                    line.replace(name, value)
+                })
+        })
+        .collect()
+}
+
+/// Parses an element declaration, `Sprite,layer,origin,"path",x,y` or
+/// `Animation,layer,origin,"path",x,y,frameCount,frameDelay,loopType`.
+fn parse_element(line: &str) -> Option<Element> {
+    let fields = line
+        .split(',')
+        .map(|field| field.trim())
+        .collect::<Vec<_>>();
+    let animation = match *fields.first()? {
+        "Sprite" | "4" => None,
+        "Animation" | "6" => Some(Animation {
+            frame_count: fields.get(6)?.parse::<usize>().ok()?.max(1),
+            frame_delay: fields.get(7)?.parse::<f32>().ok()? / 1000.0,
+            loop_type: match fields.get(8).copied() {
+                Some("LoopOnce" | "1") => LoopType::Once,
+                _ => LoopType::Forever,
+            },
+        }),
+        _ => return None,
+    };
+    Some(Element {
+        layer: Layer::parse(fields.get(1)?)?,
+        origin: Origin::parse(fields.get(2)?)?,
+        path: hitsound::normalize_path(fields.get(3)?),
+        position: cgmath::vec2(fields.get(4)?.parse().ok()?, fields.get(5)?.parse().ok()?),
+        animation,
+        commands: Commands::default(),
+        triggers: Vec::new(),
+        start: 0.0,
+        end: 0.0,
+    })
+}
+
+/// Group of indented commands an element is currently reading.
+enum Group {
+    Loop {
+        start: f32,
+        count: u32,
+        commands: Commands,
+    },
+    Trigger(TriggerGroup),
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Storyboard {
+    /// In drawing order.
+    pub elements: Vec<Element>,
+}
+
+impl Storyboard {
+    /// Parses the elements in the `[Events]` of a `.osu` or `.osb` file.
+    pub fn parse(content: &str) -> Self {
+        let mut elements = Vec::new();
+        let mut current: Option<Element> = None;
+        let mut group: Option<Group> = None;
+
+        fn close_group(element: &mut Element, group: Option<Group>) {
+            match group {
+                Some(Group::Loop {
+                    start,
+                    count,
+                    commands,
+                }) => {
+                    // Every iteration lasts until the last inner command ends
+                    if let Some((_, duration)) = commands.span() {
+                        for iteration in 0..count.max(1) {
+                            element
+                                .commands
+                                .extend_shifted(&commands, start + duration * iteration as f32);
+                        }
+                    }
+                }
+                Some(Group::Trigger(mut trigger)) => {
+                    trigger.commands.sort();
+                    trigger.duration = trigger.commands.span().map_or(0.0, |(_, end)| end);
+                    element.triggers.push(trigger);
+                }
+                None => {}
+            }
+        }
+        fn finish(element: Option<Element>, elements: &mut Vec<Element>) {
+            if let Some(mut element) = element {
+                element.commands.sort();
+                // Elements without commands are never shown
+                if let Some((start, end)) = element.commands.span() {
+                    element.start = start;
+                    element.end = end;
+                    elements.push(element);
+                } else if !element.triggers.is_empty() {
+                    elements.push(element);
+                }
+            }
+        }
+
+        for line in event_lines(content) {
+            let depth = line.len() - line.trim_start_matches(|c| c == ' ' || c == '_').len();
+            let line = line.trim_start_matches(|c| c == ' ' || c == '_');
+            if depth == 0 {
+                if let Some(element) = &mut current {
+                    close_group(element, group.take());
+                }
+                finish(current.take(), &mut elements);
+                current = parse_element(line);
+                continue;
+            }
+            let element = match &mut current {
+                Some(element) => element,
+                None => continue,
+            };
+            if depth == 1 {
+                close_group(element, group.take());
+                let fields = line
+                    .split(',')
+                    .map(|field| field.trim())
+                    .collect::<Vec<_>>();
+                match fields[0] {
+                    "L" => {
+                        group = (|| {
+                            Some(Group::Loop {
+                                start: fields.get(1)?.parse::<f32>().ok()? / 1000.0,
+                                count: fields.get(2)?.parse().ok()?,
+                                commands: Commands::default(),
+                            })
+                        })();
+                    }
+                    "T" => {
+                        group = (|| {
+                            Some(Group::Trigger(TriggerGroup {
+                                name: fields.get(1)?.to_string(),
+                                start: fields.get(2)?.parse::<f32>().ok()? / 1000.0,
+                                end: fields.get(3)?.parse::<f32>().ok()? / 1000.0,
+                                commands: Commands::default(),
+                                duration: 0.0,
+                            }))
+                        })();
+                    }
+                    _ => {
+                        element.commands.parse_line(line);
+                    }
+                }
+            } else {
+                match &mut group {
+                    Some(Group::Loop { commands, .. })
+                    | Some(Group::Trigger(TriggerGroup { commands, .. })) => {
+                        commands.parse_line(line);
+                    }
+                    None => {}
+                }
+            }
+        }
+        if let Some(element) = &mut current {
+            close_group(element, group.take());
+        }
+        finish(current, &mut elements);
+
+        Storyboard { elements }
+    }
+
+    /// Adds the elements of `other` over the ones of this storyboard.
+    pub fn extend(&mut self, other: Storyboard) {
+        self.elements.extend(other.elements);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+}
+
+#[test]
+fn test_ease() {
+    for easing in 0..=34 {
+        assert!(ease(easing, 0.0).abs() < 1e-3, "easing {} at 0", easing);
+        assert!(
+            (ease(easing, 1.0) - 1.0).abs() < 1e-3,
+            "easing {} at 1",
+            easing
+        );
+    }
+    assert_eq!(ease(0, 0.25), 0.25);
+    assert_eq!(ease(2, 0.5), 0.25);
+    assert_eq!(ease(1, 0.5), 0.75);
+}
+
+#[test]
+fn test_storyboard() {
+    let storyboard = Storyboard::parse(
+        r#"[Variables]
+$c=255,0,0
+
+[Events]
+//Storyboard Layer 0 (Background)
+Sprite,Background,Centre,"sb\bg.jpg",320,240
+ F,0,1000,2000,0,1
+ M,0,1000,3000,0,0,100,100
+ MX,0,2000,,50
+ S,0,1000,,0.5
+ C,0,1000,,$c
+ P,0,1500,1600,H
+Animation,Foreground,TopLeft,"sb/star.png",0,0,3,100,LoopForever
+ F,0,0,,1
+ L,1000,3
+  R,0,0,500,0,1
+ T,HitSoundClap,0,5000
+  S,0,0,200,2,1
+Sprite,Pass,Centre,"sb/pass.png",320,240
+ F,0,0,1000,1,0,1
+"#,
+    );
+    assert_eq!(storyboard.elements.len(), 3);
+
+    let bg = &storyboard.elements[0];
+    assert_eq!(bg.layer, Layer::Background);
+    assert_eq!(bg.path, "sb/bg.jpg");
+    assert_eq!((bg.start, bg.end), (1.0, 3.0));
+    assert_eq!(bg.state(0.5, &[]), None);
+    let state = bg.state(1.5, &[]).unwrap();
+    assert_eq!(state.alpha, 0.5);
+    assert_eq!(state.position, cgmath::vec2(25.0, 25.0));
+    assert_eq!(state.scale, cgmath::vec2(-0.5, 0.5));
+    assert_eq!(state.colour, cgmath::vec3(1.0, 0.0, 0.0));
+    // The single axis move starting later overrides the full one
+    let state = bg.state(2.5, &[]).unwrap();
+    assert_eq!(state.position, cgmath::vec2(50.0, 75.0));
+    assert_eq!(state.scale, cgmath::vec2(0.5, 0.5));
+    assert_eq!(bg.state(3.0, &[]), None);
+
+    let star = &storyboard.elements[1];
+    assert_eq!(
+        star.frame_paths(),
+        vec!["sb/star0.png", "sb/star1.png", "sb/star2.png"]
+    );
+    // Loop unrolled three times
+    assert_eq!(star.commands.rotate.len(), 3);
+    assert_eq!((star.start, star.end), (0.0, 2.5));
+    let state = star.state(1.75, &[None]).unwrap();
+    assert_eq!(state.rotation, 0.5);
+    assert_eq!(state.frame, 2);
+    assert_eq!(star.state(1.85, &[None]).unwrap().frame, 0);
+    // Triggered at 2s, the group runs relative to it and keeps the element alive
+    assert_eq!(star.triggers[0].name, "HitSoundClap");
+    let state = star.state(2.1, &[Some(2.0)]).unwrap();
+    assert_eq!(state.scale, cgmath::vec2(1.5, 1.5));
+    assert_eq!(star.state(2.6, &[None]), None);
+    assert!(star.state(2.6, &[Some(2.5)]).is_some());
+
+    // Shorthand chaining two fades
+    let pass = &storyboard.elements[2];
+    assert_eq!(pass.commands.fade.len(), 2);
+    assert_eq!((pass.start, pass.end), (0.0, 2.0));
+    assert_eq!(pass.state(1.5, &[]).unwrap().alpha, 0.5);
+}
+
+#[test]
+fn test_hitsound_triggers() {
+    let sample = |name: &str| Sample {
+        name: name.to_owned(),
+        index: 0,
+        volume: 1.0,
+    };
+    let samples = [sample("soft-hitnormal"), sample("drum-hitclap")];
+    assert!(hitsound_trigger_matches("HitSound", &samples));
+    assert!(hitsound_trigger_matches("HitSoundClap", &samples));
+    assert!(hitsound_trigger_matches("HitSoundSoft", &samples));
+    // The first set is the one of the hit normal, the second the one of the additions
+    assert!(hitsound_trigger_matches("HitSoundSoftClap", &samples));
+    assert!(hitsound_trigger_matches("HitSoundSoftDrum", &samples));
+    assert!(hitsound_trigger_matches("HitSoundSoftDrumClap", &samples));
+    assert!(hitsound_trigger_matches("HitSoundAllDrumClap", &samples));
+    assert!(hitsound_trigger_matches("HitSoundSoftAll2", &samples));
+    assert!(!hitsound_trigger_matches("HitSoundDrumClap", &samples));
+    assert!(!hitsound_trigger_matches("HitSoundSoftSoftClap", &samples));
+    assert!(!hitsound_trigger_matches("HitSoundWhistle", &samples));
+    assert!(!hitsound_trigger_matches(
+        "HitSoundSoftDrumWhistle",
+        &samples
+    ));
+    assert!(!hitsound_trigger_matches(
+        "HitSoundAllSoft",
+        &[sample("soft-hitnormal")]
+    ));
+    assert!(!hitsound_trigger_matches("HitSoundSoftClapDrum", &samples));
+    assert!(!hitsound_trigger_matches("Passing", &samples));
+}
@@ -1,17 +1,43 @@
-use std::sync::Arc;
+use std::sync::{atomic::Ordering, Arc};
 
 use crate::game::GameContext;
 
+use super::settings_ui::{SettingsMessage, SettingsUI};
+
+/// Text size the break countdown grows by on every beat.
+const BREAK_PULSE_SIZE: f32 = 8.0;
+/// Colour the combo counter glows in on beats in kiai time.
+const KIAI_GLOW: iced::Color = iced::Color {
+    r: 1.0,
+    g: 0.8,
+    b: 0.2,
+    a: 1.0,
+};
+
 pub struct GameUI {
     ctx: Arc<GameContext>,
+    skip_button: iced::button::State,
+    settings: SettingsUI,
 }
 
 #[derive(Debug, Clone)]
-pub enum Message {}
+pub enum Message {
+    Skip,
+    Settings(SettingsMessage),
+}
 
 impl GameUI {
     pub fn new(ctx: Arc<GameContext>) -> GameUI {
-        GameUI { ctx }
+        GameUI {
+            ctx,
+            skip_button: iced::button::State::new(),
+            settings: SettingsUI::default(),
+        }
+    }
+
+    /// Whether the settings are waiting for a key to bind.
+    pub fn rebinding(&self) -> bool {
+        self.settings.rebinding()
     }
 }
 
@@ -19,24 +45,137 @@ impl iced_winit::Program for GameUI {
     type Renderer = iced_wgpu::Renderer;
     type Message = Message;
 
-    fn update(&mut self, _message: Self::Message) -> iced::Command<Self::Message> {
-        //match message {}
+    fn update(&mut self, message: Self::Message) -> iced::Command<Self::Message> {
+        match message {
+            Message::Skip => self.ctx.skip(),
+            Message::Settings(message) => self.settings.update(&self.ctx, message),
+        }
 
         iced::Command::none()
     }
 
     fn view(&mut self) -> iced::Element<Self::Message> {
+        if self.ctx.settings_open.load(Ordering::SeqCst) {
+            return self.settings.view(&self.ctx).map(Message::Settings);
+        }
+        self.settings.close();
+
         let chart_progress = self.ctx.chart_progress();
+        // Nothing is shown outside of plays
+        if chart_progress.is_none() {
+            return iced::Space::new(iced::Length::Fill, iced::Length::Fill).into();
+        }
+
+        let beat_pulse = chart_progress
+            .and_then(|p| p.beat)
+            .map_or(0.0, |beat| beat.pulse());
+
+        // Time left and whether the player would pass if the chart ended now
+        let break_overlay: iced::Element<_> = match chart_progress
+            .and_then(|p| p.break_remaining.map(|r| (r, p.health)))
+        {
+            Some((remaining, health)) => iced::Column::new()
+                .width(iced::Length::Fill)
+                .align_items(iced::Alignment::Center)
+                .push(
+                    iced::Text::new(format!("{}", remaining.ceil() as u32))
+                        .size(64 + (BREAK_PULSE_SIZE * beat_pulse) as u16),
+                )
+                .push(iced::Text::new(if health.is_passing() { "Pass" } else { "Fail" }).size(32))
+                .into(),
+            None => iced::Space::with_height(iced::Length::Shrink).into(),
+        };
+
+        // Results of the play once every object is judged
+        let results: iced::Element<_> = match self.ctx.score().as_ref() {
+            Some(score) => {
+                let judgements = &score.judgements;
+                iced::Column::new()
+                    .width(iced::Length::Fill)
+                    .align_items(iced::Alignment::Center)
+                    .spacing(8)
+                    .push(iced::Text::new("Results").size(48))
+                    .push(
+                        iced::Text::new(format!(
+                            "{}  {}  played by {}",
+                            score.grade(),
+                            score.total_score,
+                            score.player
+                        ))
+                        .size(36),
+                    )
+                    .push(
+                        iced::Text::new(format!(
+                            "300: {}   100: {}   50: {}   Miss: {}",
+                            judgements.great, judgements.good, judgements.meh, judgements.miss
+                        ))
+                        .size(28),
+                    )
+                    .push(
+                        iced::Text::new(format!(
+                            "{:.2}%   {}x   {}",
+                            score.accuracy * 100.0,
+                            score.max_combo,
+                            score.mods
+                        ))
+                        .size(28),
+                    )
+                    .push(
+                        iced::Text::new(format!(
+                            "{:.0}pp (aim {:.0}, speed {:.0}, accuracy {:.0})",
+                            score.performance.total,
+                            score.performance.aim,
+                            score.performance.speed,
+                            score.performance.accuracy
+                        ))
+                        .size(36),
+                    )
+                    .into()
+            }
+            None => iced::Space::with_height(iced::Length::Shrink).into(),
+        };
+
+        // Fade from the default black towards the glow as the pulse gets stronger
+        let pulse = chart_progress.map_or(0.0, |p| p.kiai_pulse);
+        let combo_colour = iced::Color::from_rgb(
+            KIAI_GLOW.r * pulse,
+            KIAI_GLOW.g * pulse,
+            KIAI_GLOW.b * pulse,
+        );
+
+        let skip: iced::Element<_> = if chart_progress.map_or(false, |p| p.skip_target.is_some()) {
+            iced::Button::new(&mut self.skip_button, iced::Text::new("Skip").size(32))
+                .on_press(Message::Skip)
+                .into()
+        } else {
+            iced::Space::with_width(iced::Length::Shrink).into()
+        };
+
         iced::Column::new()
             .height(iced::Length::Fill)
             .push(
                 iced::Row::new()
                     .width(iced::Length::Fill)
-                    .push(iced::Text::new("A"))
-                    .push(iced::Space::with_width(iced::Length::Fill))
+                    .push(
+                        iced::ProgressBar::new(
+                            0.0..=1.0,
+                            chart_progress.map(|p| p.health.value()).unwrap_or(1.0),
+                        )
+                        .width(iced::Length::FillPortion(2)),
+                    )
+                    .push(iced::Space::with_width(iced::Length::FillPortion(3)))
                     .push(iced::Text::new("B")),
             )
             .push(iced::Space::with_height(iced::Length::Fill))
+            .push(break_overlay)
+            .push(results)
+            .push(iced::Space::with_height(iced::Length::Fill))
+            .push(
+                iced::Row::new()
+                    .width(iced::Length::Fill)
+                    .push(iced::Space::with_width(iced::Length::Fill))
+                    .push(skip),
+            )
             .push(
                 iced::Row::new()
                     .width(iced::Length::Fill)
@@ -46,7 +185,8 @@ impl iced_winit::Program for GameUI {
                             "{}x",
                             chart_progress.map(|p| p.combo).unwrap_or(0)
                         ))
-                        .size(48),
+                        .size(48)
+                        .color(combo_colour),
                     )
                     .push(iced::Space::with_width(iced::Length::Fill))
                     .push(
@@ -1 +1,2 @@
 pub mod game_ui;
+pub mod settings_ui;
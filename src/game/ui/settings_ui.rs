@@ -0,0 +1,358 @@
+use std::path::PathBuf;
+
+use crate::game::{
+    settings::{Resolution, RESOLUTIONS},
+    GameContext,
+};
+
+/// Skins are picked from the directories in here.
+const SKINS_DIRECTORY: &str = "skins";
+const TEXT_SIZE: u16 = 20;
+const LABEL_WIDTH: u16 = 280;
+const MAX_OFFSET_MS: f32 = 300.0;
+/// Highest frame limit the slider goes to, 0 on it means no limit.
+const MAX_FRAME_LIMIT: u32 = 480;
+const FRAME_LIMIT_STEP: u32 = 10;
+const PANEL_COLOUR: iced::Color = iced::Color {
+    r: 0.9,
+    g: 0.9,
+    b: 0.9,
+    a: 0.95,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkinChoice {
+    /// The built in skin.
+    Default,
+    Directory(PathBuf),
+}
+
+impl std::fmt::Display for SkinChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkinChoice::Default => write!(f, "Default"),
+            SkinChoice::Directory(directory) => match directory.file_name() {
+                Some(name) => write!(f, "{}", name.to_string_lossy()),
+                None => write!(f, "{}", directory.display()),
+            },
+        }
+    }
+}
+
+/// The built in skin and every skin directory found.
+fn find_skins() -> Vec<SkinChoice> {
+    let mut directories = std::fs::read_dir(SKINS_DIRECTORY)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    directories.sort();
+    std::iter::once(SkinChoice::Default)
+        .chain(directories.into_iter().map(SkinChoice::Directory))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub enum SettingsMessage {
+    Resolution(Resolution),
+    Fullscreen(bool),
+    Vsync(bool),
+    FrameLimit(u32),
+    MasterVolume(f32),
+    MusicVolume(f32),
+    EffectsVolume(f32),
+    /// Universal offset in milliseconds.
+    Offset(f32),
+    /// Waits for the next key pressed to bind it to the hit key at this index.
+    BindKey(usize),
+    /// Name of a key pressed while rebinding.
+    KeyPressed(String),
+    CancelBinding,
+    Skin(SkinChoice),
+    IgnoreBeatmapSkins(bool),
+    BackgroundDim(u8),
+    CursorSize(f32),
+}
+
+struct Panel;
+
+impl iced::container::StyleSheet for Panel {
+    fn style(&self) -> iced::container::Style {
+        iced::container::Style {
+            background: Some(iced::Background::Color(PANEL_COLOUR)),
+            border_radius: 8.0,
+            ..Default::default()
+        }
+    }
+}
+
+/// Settings of this machine and of the active profile, changed as soon as a control is used.
+#[derive(Default)]
+pub struct SettingsUI {
+    scroll: iced::scrollable::State,
+    resolution: iced::pick_list::State<Resolution>,
+    frame_limit: iced::slider::State,
+    master_volume: iced::slider::State,
+    music_volume: iced::slider::State,
+    effects_volume: iced::slider::State,
+    offset: iced::slider::State,
+    hit_keys: [iced::button::State; 2],
+    skin: iced::pick_list::State<SkinChoice>,
+    /// Skins found when the settings were opened.
+    skins: Option<Vec<SkinChoice>>,
+    background_dim: iced::slider::State,
+    cursor_size: iced::slider::State,
+    /// Hit key waiting for the next key pressed, `None` when not rebinding.
+    rebinding: Option<usize>,
+}
+
+fn labelled<'a>(
+    label: String,
+    control: impl Into<iced::Element<'a, SettingsMessage>>,
+) -> iced::Element<'a, SettingsMessage> {
+    iced::Row::new()
+        .spacing(16)
+        .align_items(iced::Alignment::Center)
+        .push(
+            iced::Text::new(label)
+                .size(TEXT_SIZE)
+                .width(iced::Length::Units(LABEL_WIDTH)),
+        )
+        .push(control)
+        .into()
+}
+
+impl SettingsUI {
+    /// Whether a hit key is waiting for the next key pressed.
+    pub fn rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+
+    /// Forgets what was going on when the settings are closed.
+    pub fn close(&mut self) {
+        self.skins = None;
+        self.rebinding = None;
+    }
+
+    pub fn update(&mut self, ctx: &GameContext, message: SettingsMessage) {
+        match message {
+            SettingsMessage::Resolution(resolution) => {
+                ctx.update_settings(|settings| settings.resolution = resolution)
+            }
+            SettingsMessage::Fullscreen(fullscreen) => {
+                ctx.update_settings(|settings| settings.fullscreen = fullscreen)
+            }
+            SettingsMessage::Vsync(vsync) => ctx.update_settings(|settings| settings.vsync = vsync),
+            SettingsMessage::FrameLimit(limit) => ctx.update_settings(|settings| {
+                settings.frame_limit = Some(limit).filter(|&limit| limit > 0)
+            }),
+            SettingsMessage::MasterVolume(volume) => {
+                ctx.update_settings(|settings| settings.master_volume = volume)
+            }
+            SettingsMessage::MusicVolume(volume) => {
+                ctx.update_settings(|settings| settings.music_volume = volume)
+            }
+            SettingsMessage::EffectsVolume(volume) => {
+                ctx.update_settings(|settings| settings.effects_volume = volume)
+            }
+            SettingsMessage::Offset(offset) => {
+                let mut offsets = ctx.offsets.lock().unwrap();
+                offsets.universal = offset.round() / 1000.0;
+                offsets.save();
+            }
+            SettingsMessage::BindKey(idx) => self.rebinding = Some(idx),
+            SettingsMessage::KeyPressed(key) => {
+                if let Some(idx) = self.rebinding.take() {
+                    ctx.update_player_settings(|settings| settings.hit_keys[idx] = key);
+                }
+            }
+            SettingsMessage::CancelBinding => self.rebinding = None,
+            SettingsMessage::Skin(skin) => ctx.update_player_settings(|settings| {
+                settings.skin = match skin {
+                    SkinChoice::Default => None,
+                    SkinChoice::Directory(directory) => Some(directory),
+                }
+            }),
+            SettingsMessage::IgnoreBeatmapSkins(ignore) => {
+                ctx.update_player_settings(|settings| settings.ignore_beatmap_skins = ignore)
+            }
+            SettingsMessage::BackgroundDim(dim) => {
+                ctx.update_player_settings(|settings| settings.background_dim = dim)
+            }
+            SettingsMessage::CursorSize(size) => {
+                ctx.update_player_settings(|settings| settings.cursor_size = size)
+            }
+        }
+    }
+
+    pub fn view(&mut self, ctx: &GameContext) -> iced::Element<SettingsMessage> {
+        let settings = ctx.settings.lock().unwrap().clone();
+        let player = ctx.player_settings();
+        let offset = ctx.offsets.lock().unwrap().universal * 1000.0;
+
+        let selected_skin = match &player.skin {
+            Some(directory) => SkinChoice::Directory(directory.clone()),
+            None => SkinChoice::Default,
+        };
+        let mut skins = self.skins.get_or_insert_with(find_skins).clone();
+        if !skins.contains(&selected_skin) {
+            skins.push(selected_skin.clone());
+        }
+        let mut resolutions = RESOLUTIONS.to_vec();
+        if !resolutions.contains(&settings.resolution) {
+            resolutions.push(settings.resolution);
+        }
+
+        let rebinding = self.rebinding;
+        let hit_keys = self.hit_keys.iter_mut().enumerate().fold(
+            iced::Row::new().spacing(16),
+            |row, (idx, state)| {
+                let label = if rebinding == Some(idx) {
+                    "Press a key...".to_owned()
+                } else {
+                    player.hit_keys[idx].clone()
+                };
+                row.push(
+                    iced::Button::new(state, iced::Text::new(label).size(TEXT_SIZE))
+                        .on_press(SettingsMessage::BindKey(idx)),
+                )
+            },
+        );
+
+        let frame_limit = settings.frame_limit.unwrap_or(0).min(MAX_FRAME_LIMIT);
+        let content = iced::Scrollable::new(&mut self.scroll)
+            .padding(24)
+            .spacing(10)
+            .push(iced::Text::new("Settings").size(40))
+            .push(
+                iced::Text::new("Escape to go back. Resolution and fullscreen apply on restart.")
+                    .size(TEXT_SIZE),
+            )
+            .push(labelled(
+                "Resolution".to_owned(),
+                iced::PickList::new(
+                    &mut self.resolution,
+                    resolutions,
+                    Some(settings.resolution),
+                    SettingsMessage::Resolution,
+                )
+                .text_size(TEXT_SIZE),
+            ))
+            .push(
+                iced::Checkbox::new(
+                    settings.fullscreen,
+                    "Fullscreen",
+                    SettingsMessage::Fullscreen,
+                )
+                .text_size(TEXT_SIZE),
+            )
+            .push(
+                iced::Checkbox::new(settings.vsync, "VSync", SettingsMessage::Vsync)
+                    .text_size(TEXT_SIZE),
+            )
+            .push(labelled(
+                match settings.frame_limit {
+                    Some(limit) => format!("Frame limit: {} fps", limit),
+                    None => "Frame limit: none".to_owned(),
+                },
+                iced::Slider::new(
+                    &mut self.frame_limit,
+                    0..=MAX_FRAME_LIMIT,
+                    frame_limit,
+                    SettingsMessage::FrameLimit,
+                )
+                .step(FRAME_LIMIT_STEP),
+            ))
+            .push(labelled(
+                format!("Master volume: {:.0}%", settings.master_volume * 100.0),
+                iced::Slider::new(
+                    &mut self.master_volume,
+                    0.0..=1.0,
+                    settings.master_volume,
+                    SettingsMessage::MasterVolume,
+                )
+                .step(0.01),
+            ))
+            .push(labelled(
+                format!("Music volume: {:.0}%", settings.music_volume * 100.0),
+                iced::Slider::new(
+                    &mut self.music_volume,
+                    0.0..=1.0,
+                    settings.music_volume,
+                    SettingsMessage::MusicVolume,
+                )
+                .step(0.01),
+            ))
+            .push(labelled(
+                format!("Effects volume: {:.0}%", settings.effects_volume * 100.0),
+                iced::Slider::new(
+                    &mut self.effects_volume,
+                    0.0..=1.0,
+                    settings.effects_volume,
+                    SettingsMessage::EffectsVolume,
+                )
+                .step(0.01),
+            ))
+            .push(labelled(
+                format!("Audio offset: {:.0}ms", offset),
+                iced::Slider::new(
+                    &mut self.offset,
+                    -MAX_OFFSET_MS..=MAX_OFFSET_MS,
+                    offset.clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS),
+                    SettingsMessage::Offset,
+                )
+                .step(1.0),
+            ))
+            .push(labelled("Hit keys".to_owned(), hit_keys))
+            .push(labelled(
+                "Skin".to_owned(),
+                iced::PickList::new(
+                    &mut self.skin,
+                    skins,
+                    Some(selected_skin),
+                    SettingsMessage::Skin,
+                )
+                .text_size(TEXT_SIZE),
+            ))
+            .push(
+                iced::Checkbox::new(
+                    player.ignore_beatmap_skins,
+                    "Ignore beatmap skins",
+                    SettingsMessage::IgnoreBeatmapSkins,
+                )
+                .text_size(TEXT_SIZE),
+            )
+            .push(labelled(
+                format!("Background dim: {}%", player.background_dim),
+                iced::Slider::new(
+                    &mut self.background_dim,
+                    0..=100,
+                    player.background_dim.min(100),
+                    SettingsMessage::BackgroundDim,
+                ),
+            ))
+            .push(labelled(
+                format!("Cursor size: {:.2}x", player.cursor_size),
+                iced::Slider::new(
+                    &mut self.cursor_size,
+                    0.5..=2.0,
+                    player.cursor_size.clamp(0.5, 2.0),
+                    SettingsMessage::CursorSize,
+                )
+                .step(0.05),
+            ));
+
+        iced::Container::new(
+            iced::Container::new(content)
+                .width(iced::Length::Units(760))
+                .style(Panel),
+        )
+        .width(iced::Length::Fill)
+        .height(iced::Length::Fill)
+        .center_x()
+        .center_y()
+        .into()
+    }
+}
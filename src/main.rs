@@ -1,19 +1,33 @@
 #![feature(drain_filter)]
 
-use std::{collections::HashMap, sync::atomic::Ordering};
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU8, Ordering},
+    time::{Duration, Instant},
+};
 
 use futures::task::SpawnExt;
-use game::ui::game_ui::GameUI;
+use game::ui::{
+    game_ui::{self, GameUI},
+    settings_ui::SettingsMessage,
+};
 use iced_winit::winit;
 use wgpu_glyph::{ab_glyph, GlyphBrushBuilder, Section, Text};
 use winit::{
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Fullscreen, WindowBuilder},
 };
 
 use crate::{
     game::{
-        screen::{playing::PlayingScreen, GameLoadingResource, GameScreen, Screen, Updatable},
+        audio::SampleBank,
+        chart_file, osu_export,
+        screen::{
+            calibration::CalibrationScreen, playing::PlayingScreen, settings::SettingsScreen,
+            song_select::SongSelectScreen, GameLoadingResource, GameScreen, Screen, Updatable,
+        },
+        settings::Settings,
+        skin::{self, Skin},
         GameContext, GameResources,
     },
     job::spawn_job,
@@ -25,16 +39,150 @@ pub mod game;
 pub mod job;
 pub mod math;
 
+/// Change of the beatmap offset per key press, in seconds.
+const OFFSET_STEP_SECONDS: f32 = 0.005;
+
 pub type ArcLock<T> = std::sync::Arc<std::sync::RwLock<T>>;
 
+fn send_hit(
+    screen: &mut Option<GameScreen>,
+    ctx: &GameContext,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+) {
+    match screen {
+        Some(GameScreen::Playing(s)) => s.on_hit(
+            ctx,
+            cgmath::vec2(cursor_position.x as f32, cursor_position.y as f32),
+        ),
+        Some(GameScreen::Calibration(s)) => s.on_tap(),
+        Some(GameScreen::SongSelect(_) | GameScreen::Settings(_)) | None => {}
+    }
+}
+
+/// Writes the native chart file at `input` as a .osu file at `output`.
+fn export_chart(input: &str, output: &str) {
+    let content = match std::fs::read_to_string(input) {
+        Ok(content) => content,
+        Err(e) => return println!("Failed to read '{}': {}", input, e),
+    };
+    match chart_file::parse(&content) {
+        Ok((info, data)) => match std::fs::write(output, osu_export::write_osu(&info, &data)) {
+            Ok(()) => println!("Exported '{}' to '{}'", input, output),
+            Err(e) => println!("Failed to write '{}': {}", output, e),
+        },
+        Err(e) => println!("Failed to load chart '{}': {}", input, e),
+    }
+}
+
+/// Converts the .osu file at `input` to a native chart file at `output`.
+fn convert_beatmap(input: &str, output: &str) {
+    let content = match std::fs::read_to_string(input) {
+        Ok(content) => content,
+        Err(e) => return println!("Failed to read '{}': {}", input, e),
+    };
+    match chart_file::convert_osu(&content) {
+        Ok(chart) => match std::fs::write(output, chart) {
+            Ok(()) => println!("Converted '{}' to '{}'", input, output),
+            Err(e) => println!("Failed to write '{}': {}", output, e),
+        },
+        Err(e) => println!("Failed to load beatmap '{}': {}", input, e),
+    }
+}
+
+/// Loads the skin of the active profile and everything made from it, counting up `progress`.
+fn load_game_resources(ctx: &GameContext, progress: &AtomicU8) -> GameResources {
+    const PROGRESS: u8 = 100 / 4;
+
+    let gfx = &ctx.gfx;
+
+    let skin = Skin::find(ctx.player_settings().skin.as_deref());
+
+    let playfield = ogfx::Texture::from_texture_data(
+        &gfx,
+        &skin.texture(&skin::PLAYFIELD),
+        wgpu::TextureFormat::Rgba8Unorm,
+    );
+    let cursor = ogfx::Texture::from_texture_data(
+        &gfx,
+        &skin.texture(&skin::CURSOR),
+        wgpu::TextureFormat::Rgba8Unorm,
+    );
+
+    progress.fetch_add(PROGRESS, Ordering::SeqCst);
+
+    let hitobject_textures = skin.hitobject_textures();
+    let hitobject_atlas = game::graphics::atlas::Atlas::new(
+        &gfx,
+        hitobject_textures
+            .iter()
+            .map(|(key, value)| (key, value))
+            .collect::<Vec<_>>()
+            .as_slice(),
+        wgpu::TextureFormat::Rgba8Unorm,
+    );
+
+    progress.fetch_add(PROGRESS, Ordering::SeqCst);
+
+    let digits = skin.number_textures();
+    let number_atlas = game::graphics::atlas::Atlas::new(
+        &gfx,
+        digits
+            .iter()
+            .map(|(key, value)| (key, value))
+            .collect::<Vec<_>>()
+            .as_slice(),
+        wgpu::TextureFormat::Rgba8Unorm,
+    );
+
+    progress.fetch_add(PROGRESS, Ordering::SeqCst);
+
+    let (sounds, errors) = game::audio::load_samples(skin.directory.as_deref());
+    let mut samples = SampleBank::default();
+    let add_errors = samples.add(&mut ctx.audio.lock().unwrap(), sounds);
+    for e in errors.into_iter().chain(add_errors) {
+        println!("{}", e);
+    }
+
+    progress.fetch_add(PROGRESS, Ordering::SeqCst);
+
+    GameResources {
+        skin,
+        hitobject_atlas,
+        number_atlas,
+        playfield: std::sync::Arc::new(playfield),
+        cursor: std::sync::Arc::new(cursor),
+        samples,
+    }
+}
+
+/// Name of a key as it's kept in the settings, like `Z`.
+fn key_name(key: winit::event::VirtualKeyCode) -> String {
+    format!("{:?}", key)
+}
+
 fn main() {
     dotenv::dotenv().ok();
+    // `convert <beatmap.osu> <chart.ochart>` and `export <chart.ochart> <beatmap.osu>`
+    // convert between formats instead of starting the game
+    let args = std::env::args().collect::<Vec<_>>();
+    if let [_, command, input, output] = args.as_slice() {
+        match command.as_str() {
+            "convert" => return convert_beatmap(input, output),
+            "export" => return export_chart(input, output),
+            _ => {}
+        }
+    }
+    let settings = Settings::load();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_inner_size(winit::dpi::PhysicalSize::<u32> {
-            width: 1024,
-            height: 576,
+            width: settings.resolution.width,
+            height: settings.resolution.height,
         })
+        .with_fullscreen(settings.fullscreen.then(|| Fullscreen::Borderless(None)))
+        // The surface and everything laid out on it, like the letterboxed background, keep the
+        // size the window is created with
+        .with_resizable(false)
         .build(&event_loop)
         .unwrap();
 
@@ -45,14 +193,18 @@ fn main() {
     );
     let mut cursor_position = winit::dpi::PhysicalPosition::new(-1.0, -1.0);
     let mut modifiers = winit::event::ModifiersState::default();
+    let mut held_keys = HashSet::new();
+    let mut last_frame = Instant::now();
     let mut clipboard = iced_winit::Clipboard::connect(&window);
 
     let ctx = std::sync::Arc::new(GameContext::new(
         ogfx::context::Context::new(
             &window,
             cgmath::vec2(physical_size.width, physical_size.height),
+            settings.present_mode(),
         ),
         kira::manager::AudioManager::new(kira::manager::AudioManagerSettings::default()).unwrap(),
+        settings,
     ));
     let gfx = &ctx.gfx;
 
@@ -78,67 +230,13 @@ fn main() {
     let mut load_game_resource_job = spawn_job({
         let ctx = ctx.clone();
         let progress = progress.clone();
-        move || {
-            const PROGRESS: u8 = 100 / 2;
-
-            let gfx = &ctx.gfx;
-
-            let playfield = ogfx::Texture::new(
-                &gfx,
-                include_bytes!("../resources/ui/playfield.png"),
-                wgpu::TextureFormat::Rgba8Unorm,
-            );
-
-            progress.fetch_add(PROGRESS, std::sync::atomic::Ordering::SeqCst);
-
-            let mut map = HashMap::new();
-            map.insert(
-                "tinted".to_owned(),
-                ogfx::texture::RawTextureData::from_raw_image(include_bytes!(
-                    "../resources/circle/tinted.png"
-                )),
-            );
-            map.insert(
-                "overlay".to_owned(),
-                ogfx::texture::RawTextureData::from_raw_image(include_bytes!(
-                    "../resources/circle/overlay.png"
-                )),
-            );
-            map.insert(
-                "track".to_owned(),
-                ogfx::texture::RawTextureData::from_raw_image(include_bytes!(
-                    "../resources/circle/track.png"
-                )),
-            );
-            map.insert(
-                "approach".to_owned(),
-                ogfx::texture::RawTextureData::from_raw_image(include_bytes!(
-                    "../resources/circle/approach.png"
-                )),
-            );
-
-            let hitobject_atlas = game::graphics::atlas::Atlas::new(
-                &gfx,
-                map.iter()
-                    .map(|(key, value)| (key, value))
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-                wgpu::TextureFormat::Rgba8Unorm,
-            );
-
-            progress.fetch_add(PROGRESS, std::sync::atomic::Ordering::SeqCst);
-
-            GameResources {
-                hitobject_atlas,
-                playfield: std::sync::Arc::new(playfield),
-            }
-        }
+        move || load_game_resources(&ctx, &progress)
     });
 
     let mut current_screen: Option<GameScreen> = None;
-    let mut next_scene_resource: Option<GameLoadingResource> = Some(GameLoadingResource::Playing(
-        PlayingScreen::load(ctx.clone()),
-    ));
+    let mut next_scene_resource: Option<GameLoadingResource> = Some(
+        GameLoadingResource::SongSelect(SongSelectScreen::load(ctx.clone())),
+    );
 
     let proj_buffer = Buffer::new_with_alignable_data(
         gfx,
@@ -177,27 +275,165 @@ fn main() {
                 winit::event::WindowEvent::KeyboardInput { input, .. } => {
                     if input.virtual_keycode == Some(winit::event::VirtualKeyCode::Escape)
                         && input.state == winit::event::ElementState::Pressed
+                        && matches!(current_screen, Some(GameScreen::Playing(_)))
+                        && ctx.score().is_none()
                     {
-                        let mut song = ctx.song().unwrap();
-                        match song.state() {
-                            kira::instance::InstanceState::Playing => {
-                                song.pause(kira::instance::PauseInstanceSettings {
-                                    fade_tween: None,
-                                })
-                                .unwrap();
+                        if let Some(clock) = ctx.clock_mut().as_mut() {
+                            clock.toggle_pause();
+                        }
+                    }
+                    if input.state == winit::event::ElementState::Pressed {
+                        match (input.virtual_keycode, &mut current_screen) {
+                            (Some(key), Some(GameScreen::Settings(_)))
+                                if state.program().rebinding() =>
+                            {
+                                let message = if key == winit::event::VirtualKeyCode::Escape {
+                                    SettingsMessage::CancelBinding
+                                } else {
+                                    SettingsMessage::KeyPressed(key_name(key))
+                                };
+                                state.queue_message(game_ui::Message::Settings(message));
+                            }
+                            (
+                                Some(winit::event::VirtualKeyCode::Escape),
+                                Some(GameScreen::Settings(s)),
+                            ) => {
+                                s.close(&ctx);
+                                current_screen = None;
+                                next_scene_resource = Some(GameLoadingResource::SongSelect(
+                                    SongSelectScreen::load(ctx.clone()),
+                                ));
+                            }
+                            (
+                                Some(winit::event::VirtualKeyCode::F2),
+                                Some(GameScreen::Playing(s)),
+                            ) => {
+                                s.close(&ctx);
+                                current_screen = None;
+                                next_scene_resource = Some(GameLoadingResource::Calibration(
+                                    CalibrationScreen::load(ctx.clone()),
+                                ));
+                            }
+                            (
+                                Some(winit::event::VirtualKeyCode::Return),
+                                Some(GameScreen::Calibration(s)),
+                            ) => {
+                                s.apply(&ctx);
+                                current_screen = None;
+                                next_scene_resource = Some(GameLoadingResource::Playing(
+                                    PlayingScreen::load(ctx.clone()),
+                                ));
+                            }
+                            (
+                                Some(
+                                    winit::event::VirtualKeyCode::Return
+                                    | winit::event::VirtualKeyCode::Escape,
+                                ),
+                                Some(GameScreen::Playing(s)),
+                            ) if ctx.score().is_some() => {
+                                s.close(&ctx);
+                                current_screen = None;
+                                next_scene_resource = Some(GameLoadingResource::SongSelect(
+                                    SongSelectScreen::load(ctx.clone()),
+                                ));
+                            }
+                            (
+                                Some(winit::event::VirtualKeyCode::Return),
+                                Some(GameScreen::SongSelect(s)),
+                            ) if s.naming_profile() => s.finish_new_profile(&ctx),
+                            (
+                                Some(winit::event::VirtualKeyCode::Escape),
+                                Some(GameScreen::SongSelect(s)),
+                            ) if s.naming_profile() => s.cancel_new_profile(),
+                            // The name of the profile is typed through received characters
+                            (_, Some(GameScreen::SongSelect(s))) if s.naming_profile() => {}
+                            (
+                                Some(winit::event::VirtualKeyCode::F4),
+                                Some(GameScreen::SongSelect(s)),
+                            ) => {
+                                s.close(&ctx);
+                                current_screen = None;
+                                next_scene_resource = Some(GameLoadingResource::Settings(
+                                    SettingsScreen::load(ctx.clone()),
+                                ));
+                            }
+                            (
+                                Some(winit::event::VirtualKeyCode::Tab),
+                                Some(GameScreen::SongSelect(_)),
+                            ) => ctx.switch_profile(1),
+                            (
+                                Some(winit::event::VirtualKeyCode::F3),
+                                Some(GameScreen::SongSelect(s)),
+                            ) => s.start_new_profile(),
+                            (
+                                Some(winit::event::VirtualKeyCode::Up),
+                                Some(GameScreen::SongSelect(s)),
+                            ) => s.select(-1),
+                            (
+                                Some(winit::event::VirtualKeyCode::Down),
+                                Some(GameScreen::SongSelect(s)),
+                            ) => s.select(1),
+                            (
+                                Some(winit::event::VirtualKeyCode::Return),
+                                Some(GameScreen::SongSelect(s)),
+                            ) => {
+                                if s.play(&ctx) {
+                                    s.close(&ctx);
+                                    current_screen = None;
+                                    next_scene_resource = Some(GameLoadingResource::Playing(
+                                        PlayingScreen::load(ctx.clone()),
+                                    ));
+                                }
+                            }
+                            (
+                                Some(winit::event::VirtualKeyCode::Space),
+                                Some(GameScreen::Playing(_)),
+                            ) => ctx.skip(),
+                            (
+                                Some(winit::event::VirtualKeyCode::Equals),
+                                Some(GameScreen::Playing(s)),
+                            ) => s.adjust_offset(&ctx, OFFSET_STEP_SECONDS),
+                            (
+                                Some(winit::event::VirtualKeyCode::Minus),
+                                Some(GameScreen::Playing(s)),
+                            ) => s.adjust_offset(&ctx, -OFFSET_STEP_SECONDS),
+                            _ => {}
+                        }
+                    }
+                    if let Some(key) = input
+                        .virtual_keycode
+                        .filter(|&key| ctx.is_hit_key(&key_name(key)))
+                    {
+                        match input.state {
+                            winit::event::ElementState::Pressed => {
+                                // Ignore key repeats while the key is held down
+                                if held_keys.insert(key) {
+                                    send_hit(&mut current_screen, &ctx, cursor_position);
+                                }
+                            }
+                            winit::event::ElementState::Released => {
+                                held_keys.remove(&key);
                             }
-                            kira::instance::InstanceState::Paused(_) => song
-                                .resume(kira::instance::ResumeInstanceSettings {
-                                    fade_tween: None,
-                                    rewind_to_pause_position: false,
-                                })
-                                .unwrap(),
-                            _ => panic!(),
                         }
                     }
                 }
+                winit::event::WindowEvent::MouseInput {
+                    state: winit::event::ElementState::Pressed,
+                    button: winit::event::MouseButton::Left | winit::event::MouseButton::Right,
+                    ..
+                } => {
+                    send_hit(&mut current_screen, &ctx, cursor_position);
+                }
                 winit::event::WindowEvent::CursorMoved { position, .. } => {
                     cursor_position = position;
+                    if let Some(GameScreen::Playing(s)) = &mut current_screen {
+                        s.on_cursor_moved(&ctx, cgmath::vec2(position.x as f32, position.y as f32));
+                    }
+                }
+                winit::event::WindowEvent::ReceivedCharacter(c) => {
+                    if let Some(GameScreen::SongSelect(s)) = &mut current_screen {
+                        s.type_char(c);
+                    }
                 }
                 winit::event::WindowEvent::ModifiersChanged(new_modifiers) => {
                     modifiers = new_modifiers;
@@ -226,17 +462,59 @@ fn main() {
                 window.request_redraw();
             }
 
+            // Profiles and their settings can use different skins
+            if ctx.skin_outdated() {
+                *ctx.game_resources.lock().unwrap() = None;
+                progress.store(0, Ordering::SeqCst);
+                load_game_resource_job = spawn_job({
+                    let ctx = ctx.clone();
+                    let progress = progress.clone();
+                    move || load_game_resources(&ctx, &progress)
+                });
+            }
+
             let _program = state.program();
 
             if load_game_resource_job.finished() {
                 if let Some(game_loading_resource) = &mut next_scene_resource {
                     match game_loading_resource {
-                        GameLoadingResource::Playing(r) => {
-                            if let Some(resource) = r.poll().unwrap() {
+                        GameLoadingResource::Playing(r) => match r.poll().unwrap() {
+                            Some(Ok(resource)) => {
                                 current_screen =
                                     Some(GameScreen::Playing(PlayingScreen::new(&ctx, resource)));
                                 next_scene_resource = None;
                             }
+                            // Back to picking another chart
+                            Some(Err(e)) => {
+                                println!("{}", e);
+                                next_scene_resource = Some(GameLoadingResource::SongSelect(
+                                    SongSelectScreen::load(ctx.clone()),
+                                ));
+                            }
+                            None => {}
+                        },
+                        GameLoadingResource::Calibration(r) => {
+                            if let Some(Ok(resource)) = r.poll().unwrap() {
+                                current_screen = Some(GameScreen::Calibration(
+                                    CalibrationScreen::new(&ctx, resource),
+                                ));
+                                next_scene_resource = None;
+                            }
+                        }
+                        GameLoadingResource::SongSelect(r) => {
+                            if let Some(Ok(resource)) = r.poll().unwrap() {
+                                current_screen = Some(GameScreen::SongSelect(
+                                    SongSelectScreen::new(&ctx, resource),
+                                ));
+                                next_scene_resource = None;
+                            }
+                        }
+                        GameLoadingResource::Settings(r) => {
+                            if let Some(Ok(resource)) = r.poll().unwrap() {
+                                current_screen =
+                                    Some(GameScreen::Settings(SettingsScreen::new(&ctx, resource)));
+                                next_scene_resource = None;
+                            }
                         }
                     };
                 }
@@ -246,13 +524,29 @@ fn main() {
                 }
             }
 
+            if let Some(clock) = ctx.clock_mut().as_mut() {
+                clock.update();
+            }
+
             match &mut current_screen {
                 Some(s) => match s {
                     GameScreen::Playing(s) => s.update(&ctx),
+                    GameScreen::Calibration(s) => s.update(&ctx),
+                    GameScreen::SongSelect(s) => s.update(&ctx),
+                    GameScreen::Settings(s) => s.update(&ctx),
                 },
                 None => {}
             }
 
+            // Wait out the rest of the frame when there is a limit
+            if let Some(limit) = ctx.settings.lock().unwrap().frame_limit {
+                let frame_time = Duration::from_secs_f64(1.0 / limit as f64);
+                if let Some(remaining) = frame_time.checked_sub(last_frame.elapsed()) {
+                    std::thread::sleep(remaining);
+                }
+            }
+            last_frame = Instant::now();
+
             let gfx = &ctx.gfx;
 
             let frame = gfx.surface.get_current_texture().unwrap();
@@ -282,15 +576,19 @@ fn main() {
                         depth_stencil_attachment: None,
                     });
 
-                render_pass.set_pipeline(&pipeline.pipeline);
                 let rctx = ogfx::context::RenderContext::new();
-                rctx.with_initial_projection(&proj_bind_group, &mut render_pass, |pass| {
-                    match &current_screen {
-                        Some(s) => match s {
-                            GameScreen::Playing(s) => s.render(&rctx, pass),
-                        },
-                        None => {}
-                    }
+                rctx.with_initial_pipeline(&pipeline.pipeline, &mut render_pass, |pass| {
+                    rctx.with_initial_projection(&proj_bind_group, pass, |pass| {
+                        match &current_screen {
+                            Some(s) => match s {
+                                GameScreen::Playing(s) => s.render(&rctx, pass),
+                                GameScreen::Calibration(s) => s.render(&rctx, pass),
+                                GameScreen::SongSelect(s) => s.render(&rctx, pass),
+                                GameScreen::Settings(s) => s.render(&rctx, pass),
+                            },
+                            None => {}
+                        }
+                    });
                 });
             }
 
@@ -311,6 +609,33 @@ fn main() {
                 });
             }
 
+            if let Some(GameScreen::Calibration(s)) = &current_screen {
+                glyph_brush.queue(Section {
+                    screen_position: (gfx.dimensions.x as f32 / 2.0, 40.0),
+                    bounds: (gfx.dimensions.x as f32, gfx.dimensions.y as f32),
+                    text: vec![Text::new(&s.text(&ctx))
+                        .with_color([1.0, 1.0, 1.0, 1.0])
+                        .with_scale(28.0)],
+                    layout: wgpu_glyph::Layout::default_wrap()
+                        .h_align(wgpu_glyph::HorizontalAlign::Center),
+                    ..Section::default()
+                });
+            }
+
+            if let Some(GameScreen::SongSelect(s)) = &current_screen {
+                let lines = s.text(&ctx);
+                glyph_brush.queue(Section {
+                    screen_position: (40.0, 40.0),
+                    bounds: (gfx.dimensions.x as f32 - 80.0, gfx.dimensions.y as f32),
+                    text: lines
+                        .iter()
+                        .map(|(line, colour)| Text::new(line).with_color(*colour).with_scale(24.0))
+                        .collect(),
+                    layout: wgpu_glyph::Layout::default_wrap(),
+                    ..Section::default()
+                });
+            }
+
             glyph_brush
                 .draw_queued(
                     &gfx.device,
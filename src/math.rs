@@ -52,3 +52,29 @@ fn test_remap() {
     assert_eq!(clamped_remap(0.0, 1000.0, 1.0, 0.0, 250.0), 0.75);
     assert_eq!(clamped_remap(0.0, 1000.0, 0.0, 1.0, -250.0), 0.0);
 }
+
+/// Returns the scale fitting a `size` rectangle entirely inside `bounds` while keeping its aspect
+/// ratio, leaving bars on the sides that don't match.
+pub fn letterbox_scale(size: cgmath::Vector2<f32>, bounds: cgmath::Vector2<f32>) -> f32 {
+    (bounds.x / size.x).min(bounds.y / size.y)
+}
+
+#[test]
+fn test_letterbox_scale() {
+    let screen = cgmath::vec2(1024.0, 576.0);
+    // Same aspect ratio fills the screen
+    assert_eq!(
+        letterbox_scale(cgmath::vec2(1920.0, 1080.0), screen),
+        1024.0 / 1920.0
+    );
+    // 4:3 gets bars on the left and right
+    assert_eq!(
+        letterbox_scale(cgmath::vec2(800.0, 600.0), screen),
+        576.0 / 600.0
+    );
+    // Ultrawide gets bars on the top and bottom
+    assert_eq!(
+        letterbox_scale(cgmath::vec2(2560.0, 1080.0), screen),
+        1024.0 / 2560.0
+    );
+}